@@ -0,0 +1,279 @@
+use proc_macro2::TokenStream;
+use quote::{quote, quote_spanned};
+use syn::parse::Parser;
+use syn::punctuated::Punctuated;
+use syn::spanned::Spanned;
+use syn::{Expr, ItemFn, Lit, Meta, ReturnType, Token};
+
+/// 属性宏参数
+#[derive(Default)]
+struct Config {
+    /// iouring的entry数量
+    entries: Option<u32>,
+    /// 线程数量，设置时使用thread-per-core启动
+    worker_threads: Option<usize>,
+    /// 是否开启定时器
+    timer_enabled: bool,
+}
+
+impl Config {
+    fn parse(args: TokenStream) -> syn::Result<Config> {
+        let mut config = Config::default();
+        let metas = Punctuated::<Meta, Token![,]>::parse_terminated.parse2(args)?;
+        for meta in metas {
+            let ident = meta
+                .path()
+                .get_ident()
+                .ok_or_else(|| syn::Error::new_spanned(meta.path(), "expected an identifier"))?
+                .to_string();
+            match (ident.as_str(), &meta) {
+                ("entries", Meta::NameValue(nv)) => {
+                    config.entries = Some(parse_int(&nv.value, "entries")?);
+                }
+                ("worker_threads", Meta::NameValue(nv)) => {
+                    let threads = parse_int(&nv.value, "worker_threads")?;
+                    if threads == 0 {
+                        return Err(syn::Error::new_spanned(
+                            &nv.value,
+                            "`worker_threads` must be greater than 0",
+                        ));
+                    }
+                    config.worker_threads = Some(threads);
+                }
+                ("timer_enabled", Meta::Path(_)) => {
+                    config.timer_enabled = true;
+                }
+                ("timer_enabled", Meta::NameValue(nv)) => {
+                    config.timer_enabled = parse_bool(&nv.value, "timer_enabled")?;
+                }
+                ("entries" | "worker_threads", _) => {
+                    return Err(syn::Error::new_spanned(
+                        &meta,
+                        format!("`{}` expects a value, e.g. `{} = 1024`", ident, ident),
+                    ));
+                }
+                _ => {
+                    return Err(syn::Error::new_spanned(
+                        &meta,
+                        format!(
+                            "unknown attribute `{}`, expected one of `entries`, `worker_threads`, `timer_enabled`",
+                            ident
+                        ),
+                    ));
+                }
+            }
+        }
+        Ok(config)
+    }
+
+    /// 生成RuntimeBuilder
+    fn builder(&self) -> TokenStream {
+        let mut builder = quote!(::shlrt::RuntimeBuilder::new());
+        if let Some(entries) = self.entries {
+            builder = quote!(#builder.with_entries(#entries));
+        }
+        if self.timer_enabled {
+            builder = quote!(#builder.enable_timer());
+        }
+        builder
+    }
+}
+
+fn parse_int<T: std::str::FromStr>(expr: &Expr, name: &str) -> syn::Result<T>
+where
+    T::Err: std::fmt::Display,
+{
+    match expr {
+        Expr::Lit(syn::ExprLit {
+            lit: Lit::Int(lit), ..
+        }) => lit.base10_parse(),
+        _ => Err(syn::Error::new_spanned(
+            expr,
+            format!("`{}` expects an integer literal", name),
+        )),
+    }
+}
+
+fn parse_bool(expr: &Expr, name: &str) -> syn::Result<bool> {
+    match expr {
+        Expr::Lit(syn::ExprLit {
+            lit: Lit::Bool(lit), ..
+        }) => Ok(lit.value),
+        _ => Err(syn::Error::new_spanned(
+            expr,
+            format!("`{}` expects a bool literal", name),
+        )),
+    }
+}
+
+/// 检查函数签名，返回去掉async之后的函数
+fn check_fn(mut input: ItemFn, config: &Config, kind: &str) -> syn::Result<ItemFn> {
+    if input.sig.asyncness.take().is_none() {
+        return Err(syn::Error::new_spanned(
+            input.sig.fn_token,
+            "the `async` keyword is missing from the function declaration",
+        ));
+    }
+    if !input.sig.inputs.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &input.sig.inputs,
+            format!("the {} function cannot accept arguments", kind),
+        ));
+    }
+    if !input.sig.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &input.sig.generics,
+            format!("the {} function cannot have generic parameters", kind),
+        ));
+    }
+    if config.worker_threads.is_some() {
+        if let ReturnType::Type(_, ty) = &input.sig.output {
+            return Err(syn::Error::new_spanned(
+                ty,
+                format!(
+                    "the {} function cannot return a value when `worker_threads` is set",
+                    kind
+                ),
+            ));
+        }
+    }
+    Ok(input)
+}
+
+/// 生成调用运行时的函数体
+fn expand_body(mut input: ItemFn, config: &Config) -> ItemFn {
+    let body = &input.block;
+    let builder = config.builder();
+    let span = body.span();
+    let block = match config.worker_threads {
+        None => quote_spanned! {span=>
+            {
+                let body = async #body;
+                #builder
+                    .build()
+                    .expect("Failed building the Runtime")
+                    .block_on(body)
+            }
+        },
+        Some(threads) => quote_spanned! {span=>
+            {
+                ::shlrt::Launcher::new(#builder)
+                    .threads(#threads)
+                    .run(|_| async #body)
+                    .expect("Failed launching the Runtime");
+            }
+        },
+    };
+    input.block = syn::parse2(block).expect("generated block must be valid");
+    input
+}
+
+pub(crate) fn main(args: TokenStream, item: TokenStream) -> TokenStream {
+    let result = syn::parse2::<ItemFn>(item).and_then(|input| {
+        let config = Config::parse(args)?;
+        let input = check_fn(input, &config, "main")?;
+        Ok(expand_body(input, &config))
+    });
+    match result {
+        Ok(input) => quote!(#input),
+        Err(e) => e.to_compile_error(),
+    }
+}
+
+pub(crate) fn test(args: TokenStream, item: TokenStream) -> TokenStream {
+    let result = syn::parse2::<ItemFn>(item).and_then(|input| {
+        if let Some(attr) = input.attrs.iter().find(|attr| attr.path().is_ident("test")) {
+            return Err(syn::Error::new_spanned(attr, "second test attribute is supplied"));
+        }
+        let config = Config::parse(args)?;
+        let input = check_fn(input, &config, "test")?;
+        Ok(expand_body(input, &config))
+    });
+    match result {
+        Ok(input) => quote! {
+            #[::core::prelude::v1::test]
+            #input
+        },
+        Err(e) => e.to_compile_error(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proc_macro2::TokenStream;
+    use quote::{quote, ToTokens};
+    use syn::ItemFn;
+
+    /// 展开成功时返回生成的函数
+    fn expand(output: TokenStream) -> ItemFn {
+        syn::parse2(output.clone()).unwrap_or_else(|_| panic!("expected a function, got `{}`", output))
+    }
+
+    /// 展开失败时返回compile_error!中的信息
+    fn error(output: TokenStream) -> String {
+        let output = output.to_string();
+        assert!(output.contains("compile_error"), "expected an error, got `{}`", output);
+        output
+    }
+
+    #[test]
+    fn main_uses_block_on() {
+        let output = expand(super::main(
+            quote!(entries = 256, timer_enabled),
+            quote!(async fn main() -> i32 { 1 }),
+        ));
+        assert!(output.sig.asyncness.is_none());
+        let body = output.block.to_token_stream().to_string();
+        assert!(body.contains("with_entries (256u32)"), "{}", body);
+        assert!(body.contains("enable_timer ()"), "{}", body);
+        assert!(body.contains("block_on"), "{}", body);
+    }
+
+    #[test]
+    fn worker_threads_uses_launcher() {
+        let output = expand(super::main(quote!(worker_threads = 4), quote!(async fn main() {})));
+        let body = output.block.to_token_stream().to_string();
+        assert!(body.contains(":: shlrt :: Launcher :: new"), "{}", body);
+        assert!(body.contains("threads (4usize)"), "{}", body);
+    }
+
+    #[test]
+    fn worker_threads_rejects_return_value() {
+        let output = error(super::main(
+            quote!(worker_threads = 2),
+            quote!(async fn main() -> i32 { 1 }),
+        ));
+        assert!(output.contains("cannot return a value when `worker_threads` is set"), "{}", output);
+    }
+
+    #[test]
+    fn invalid_arguments() {
+        let output = error(super::main(quote!(worker_threads = 0), quote!(async fn main() {})));
+        assert!(output.contains("must be greater than 0"), "{}", output);
+        let output = error(super::main(quote!(entries), quote!(async fn main() {})));
+        assert!(output.contains("`entries` expects a value"), "{}", output);
+        let output = error(super::main(quote!(threads = 2), quote!(async fn main() {})));
+        assert!(output.contains("unknown attribute `threads`"), "{}", output);
+    }
+
+    #[test]
+    fn invalid_signature() {
+        let output = error(super::main(quote!(), quote!(fn main() {})));
+        assert!(output.contains("the `async` keyword is missing"), "{}", output);
+        let output = error(super::test(quote!(), quote!(async fn t(a: u32) {})));
+        assert!(output.contains("the test function cannot accept arguments"), "{}", output);
+    }
+
+    #[test]
+    fn test_adds_test_attribute() {
+        let output = expand(super::test(quote!(entries = 8), quote!(async fn t() {})));
+        assert_eq!(output.attrs.len(), 1);
+        assert!(output.attrs[0].path().segments.iter().any(|segment| segment.ident == "test"));
+    }
+
+    #[test]
+    fn duplicate_test_attribute() {
+        let output = error(super::test(quote!(), quote!(#[test] async fn t() {})));
+        assert!(output.contains("second test attribute is supplied"), "{}", output);
+    }
+}
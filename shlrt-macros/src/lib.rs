@@ -1,32 +1,34 @@
 use proc_macro::TokenStream;
-use proc_macro2::Span;
-use quote::{quote, quote_spanned, ToTokens};
-use syn::parse::Parser;
-use syn::{parse_macro_input, DeriveInput};
 
-// 类函数过程宏
-#[proc_macro]
-pub fn make_test(_item: TokenStream) -> TokenStream {
-    "fn answer() -> u32 {40}".parse().unwrap()
-}
+mod entry;
 
-// 派生宏
-#[proc_macro_derive(AnswerFn)]
-pub fn derive_answer_fn(_item: TokenStream) -> TokenStream {
-    let input = parse_macro_input!(_item as DeriveInput);
-    let ident = input.ident;
-    quote!(
-        impl #ident {
-            pub fn go_to_sleep(&self) {}
-        }
-    )
-    .into()
+/// 用shlrt运行时运行async main函数
+///
+/// ```ignore
+/// #[shlrt::main(entries = 1024, timer_enabled = true)]
+/// async fn main() {
+///     println!("hello shlrt");
+/// }
+/// ```
+///
+/// 参数：
+/// - `entries`：iouring的entry数量
+/// - `worker_threads`：thread-per-core启动的线程数，每个线程都会运行一次函数体
+/// - `timer_enabled`：开启定时器
+#[proc_macro_attribute]
+pub fn main(args: TokenStream, item: TokenStream) -> TokenStream {
+    entry::main(args.into(), item.into()).into()
 }
 
-// 属性宏
+/// 为每个测试创建一个shlrt运行时，参数同 [macro@main]
+///
+/// ```ignore
+/// #[shlrt::test(entries = 256)]
+/// async fn my_test() {
+///     assert!(true);
+/// }
+/// ```
 #[proc_macro_attribute]
-pub fn attr_fn(attr: TokenStream, item: TokenStream) -> TokenStream {
-    println!("attr: \"{}\"", attr.to_string());
-    println!("item: \"{}\"", item.to_string());
-    item
+pub fn test(args: TokenStream, item: TokenStream) -> TokenStream {
+    entry::test(args.into(), item.into()).into()
 }
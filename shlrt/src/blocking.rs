@@ -0,0 +1,281 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+use crate::runtime::CURRENT;
+use crate::task::JoinError;
+
+/// 在阻塞线程池中运行闭包，用于没有io_uring操作码的系统调用或者CPU密集的工作。
+/// 返回的 [BlockingHandle] 在闭包完成后通过跨线程唤醒回到当前运行时。
+///
+/// # Panics
+///
+/// 不在运行时中调用时会panic
+pub fn spawn_blocking<F, T>(func: F) -> BlockingHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    CURRENT.with(|cx| cx.blocking.spawn(func))
+}
+
+/// 等待阻塞任务的结果
+pub struct BlockingHandle<T> {
+    shared: Arc<Mutex<Shared<T>>>,
+}
+
+struct Shared<T> {
+    output: Option<Result<T, JoinError>>,
+    waker: Option<Waker>,
+}
+
+impl<T> Future for BlockingHandle<T> {
+    type Output = Result<T, JoinError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut shared = self.shared.lock().unwrap();
+        if let Some(output) = shared.output.take() {
+            return Poll::Ready(output);
+        }
+        match &shared.waker {
+            Some(waker) if waker.will_wake(cx.waker()) => {}
+            _ => shared.waker = Some(cx.waker().clone()),
+        }
+        Poll::Pending
+    }
+}
+
+/// 线程池中的任务
+trait Job: Send {
+    fn run(self: Box<Self>);
+}
+
+struct BlockingTask<F, T> {
+    func: Option<F>,
+    shared: Arc<Mutex<Shared<T>>>,
+}
+
+impl<F, T> BlockingTask<F, T> {
+    fn complete(&self, output: Result<T, JoinError>) {
+        let waker = {
+            let mut shared = self.shared.lock().unwrap();
+            shared.output = Some(output);
+            shared.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+impl<F, T> Job for BlockingTask<F, T>
+where
+    F: FnOnce() -> T + Send,
+    T: Send,
+{
+    fn run(mut self: Box<Self>) {
+        let func = self.func.take().expect("blocking task run twice");
+        let output = catch_unwind(AssertUnwindSafe(func)).map_err(JoinError::panic);
+        self.complete(output);
+    }
+}
+
+impl<F, T> Drop for BlockingTask<F, T> {
+    fn drop(&mut self) {
+        // 线程池关闭时还没有运行的任务
+        if self.func.is_some() {
+            self.complete(Err(JoinError::cancelled()));
+        }
+    }
+}
+
+/// 按需创建线程的阻塞线程池，每个运行时一个
+pub(crate) struct BlockingPool {
+    inner: Arc<PoolInner>,
+}
+
+struct PoolInner {
+    state: Mutex<PoolState>,
+    condvar: Condvar,
+    /// 最大线程数量
+    max_threads: usize,
+    /// 空闲线程在退出前等待的时间
+    keep_alive: Duration,
+}
+
+struct PoolState {
+    queue: VecDeque<Box<dyn Job>>,
+    /// 已经创建的线程数量
+    threads: usize,
+    /// 正在等待任务的线程数量
+    idle: usize,
+    /// 已经通知但还没有醒来的线程数量
+    notified: usize,
+    shutdown: bool,
+}
+
+impl BlockingPool {
+    pub(crate) const DEFAULT_MAX_THREADS: usize = 512;
+    pub(crate) const DEFAULT_KEEP_ALIVE: Duration = Duration::from_secs(10);
+
+    pub(crate) fn new(max_threads: usize, keep_alive: Duration) -> BlockingPool {
+        BlockingPool {
+            inner: Arc::new(PoolInner {
+                state: Mutex::new(PoolState {
+                    queue: VecDeque::new(),
+                    threads: 0,
+                    idle: 0,
+                    notified: 0,
+                    shutdown: false,
+                }),
+                condvar: Condvar::new(),
+                max_threads: max_threads.max(1),
+                keep_alive,
+            }),
+        }
+    }
+
+    pub(crate) fn spawn<F, T>(&self, func: F) -> BlockingHandle<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let shared = Arc::new(Mutex::new(Shared {
+            output: None,
+            waker: None,
+        }));
+        let task = Box::new(BlockingTask {
+            func: Some(func),
+            shared: shared.clone(),
+        });
+
+        let mut state = self.inner.state.lock().unwrap();
+        state.queue.push_back(task);
+        if state.idle > state.notified {
+            state.notified += 1;
+            self.inner.condvar.notify_one();
+        } else if state.threads < self.inner.max_threads {
+            // 没有空闲线程时再创建，创建失败时任务留在队列中等待已有线程处理
+            let inner = self.inner.clone();
+            let spawned = std::thread::Builder::new()
+                .name("shlrt-blocking".to_string())
+                .spawn(move || inner.run());
+            if spawned.is_ok() {
+                state.threads += 1;
+            }
+        }
+        BlockingHandle { shared }
+    }
+}
+
+impl PoolInner {
+    fn run(&self) {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(job) = state.queue.pop_front() {
+                drop(state);
+                job.run();
+                state = self.state.lock().unwrap();
+                continue;
+            }
+            if state.shutdown {
+                break;
+            }
+
+            state.idle += 1;
+            let (guard, result) = self.condvar.wait_timeout(state, self.keep_alive).unwrap();
+            state = guard;
+            state.idle -= 1;
+            if state.notified > 0 {
+                state.notified -= 1;
+            } else if result.timed_out() && state.queue.is_empty() {
+                // 空闲太久，退出线程
+                break;
+            }
+        }
+        state.threads -= 1;
+    }
+}
+
+impl Drop for BlockingPool {
+    fn drop(&mut self) {
+        // 正在运行的任务会继续跑完，还在排队的任务以取消结束
+        let queue = {
+            let mut state = self.inner.state.lock().unwrap();
+            state.shutdown = true;
+            std::mem::take(&mut state.queue)
+        };
+        self.inner.condvar.notify_all();
+        drop(queue);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::task::Wake;
+    use std::thread::Thread;
+
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    fn wait<F: Future>(future: F) -> F::Output {
+        let waker = Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let mut future = std::pin::pin!(future);
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+            std::thread::park();
+        }
+    }
+
+    #[test]
+    fn run_blocking_task() {
+        let pool = BlockingPool::new(2, Duration::from_millis(100));
+        let handles = (0..8).map(|i| pool.spawn(move || i * 2)).collect::<Vec<_>>();
+        for (i, handle) in handles.into_iter().enumerate() {
+            assert_eq!(wait(handle).unwrap(), i * 2);
+        }
+        assert!(pool.inner.state.lock().unwrap().threads <= 2);
+    }
+
+    #[test]
+    fn catch_panic() {
+        let pool = BlockingPool::new(1, Duration::from_millis(100));
+        let err = wait(pool.spawn(|| panic!("boom"))).unwrap_err();
+        assert!(err.is_panic());
+        assert_eq!(wait(pool.spawn(|| 1)).unwrap(), 1);
+    }
+
+    #[test]
+    fn cancel_queued_on_drop() {
+        let pool = BlockingPool::new(1, Duration::from_millis(100));
+        let started = Arc::new(AtomicUsize::new(0));
+        let (tx, rx) = std::sync::mpsc::channel::<()>();
+        let counter = started.clone();
+        let running = pool.spawn(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            let _ = rx.recv();
+        });
+        while started.load(Ordering::SeqCst) == 0 {
+            std::thread::yield_now();
+        }
+        let queued = pool.spawn(|| ());
+        drop(pool);
+        assert!(wait(queued).unwrap_err().is_cancelled());
+        tx.send(()).unwrap();
+        assert!(wait(running).is_ok());
+    }
+}
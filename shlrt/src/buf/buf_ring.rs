@@ -0,0 +1,247 @@
+use std::alloc::{alloc_zeroed, dealloc, Layout};
+use std::cell::Cell;
+use std::io;
+use std::ops::Deref;
+use std::ptr::NonNull;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU16, Ordering};
+
+use io_uring::cqueue;
+use io_uring::types::BufRingEntry as RawEntry;
+
+use super::IoBuf;
+use crate::driver::op::CompletionMeta;
+use crate::driver::{self, Inner};
+
+/// 注册到io_uring的provided buffer ring，包含entries个大小为buf_size的缓冲区。
+/// recv/read操作带上group id后由内核在数据到达时挑选缓冲区，
+/// 等待中的操作不需要占用缓冲区。
+#[derive(Clone)]
+pub struct BufRing {
+    inner: Rc<RingInner>,
+}
+
+struct RingInner {
+    driver: Inner,
+    bgid: u16,
+    /// 和内核共享的ring，页对齐
+    ring: NonNull<RawEntry>,
+    ring_layout: Layout,
+    entries: u16,
+    buf_size: usize,
+    /// 所有缓冲区的内存
+    bufs: Box<[u8]>,
+    /// 本地维护的tail，放回缓冲区后发布给内核
+    tail: Cell<u16>,
+}
+
+impl BufRing {
+    /// 注册一个buffer ring，entries必须是2的幂并且不超过32768
+    ///
+    /// # Panics
+    ///
+    /// 不在运行时中调用时会panic
+    pub fn new(bgid: u16, entries: u16, buf_size: usize) -> io::Result<BufRing> {
+        if !entries.is_power_of_two() || entries > 1 << 15 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "entries must be a power of 2 and at most 32768",
+            ));
+        }
+        if buf_size == 0 || buf_size > u32::MAX as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid buf_size"));
+        }
+
+        let ring_layout = Layout::from_size_align(entries as usize * std::mem::size_of::<RawEntry>(), 4096)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let ring = NonNull::new(unsafe { alloc_zeroed(ring_layout) } as *mut RawEntry)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::OutOfMemory))?;
+        let driver = driver::CURRENT.with(|inner| inner.clone());
+        if let Err(e) = driver.register_buf_ring(ring.as_ptr() as u64, entries, bgid) {
+            unsafe { dealloc(ring.as_ptr() as *mut u8, ring_layout) };
+            return Err(e);
+        }
+
+        // 注册成功后才创建RingInner，它在drop时会注销bgid并释放ring，
+        // 注册失败时bgid可能属于另一个ring
+        let inner = RingInner {
+            driver,
+            bgid,
+            ring,
+            ring_layout,
+            entries,
+            buf_size,
+            bufs: vec![0; entries as usize * buf_size].into_boxed_slice(),
+            tail: Cell::new(0),
+        };
+        for bid in 0..entries {
+            inner.push(bid);
+        }
+        inner.publish();
+        Ok(BufRing {
+            inner: Rc::new(inner),
+        })
+    }
+
+    /// 注册时使用的group id
+    pub fn bgid(&self) -> u16 {
+        self.inner.bgid
+    }
+
+    /// 每个缓冲区的大小
+    pub fn buf_size(&self) -> usize {
+        self.inner.buf_size
+    }
+
+    /// 从cqe中取出内核选中的缓冲区，没有选中缓冲区时（例如读到EOF）返回None
+    pub(crate) fn take(&self, meta: CompletionMeta) -> io::Result<Option<BufRingEntry>> {
+        let len = meta.result? as usize;
+        Ok(cqueue::buffer_select(meta.flags).map(|bid| BufRingEntry {
+            ring: self.clone(),
+            bid,
+            len,
+        }))
+    }
+}
+
+impl RingInner {
+    /// 把缓冲区放到tail的位置，调用publish之后内核才能看到
+    fn push(&self, bid: u16) {
+        let tail = self.tail.get();
+        let index = (tail & (self.entries - 1)) as usize;
+        unsafe {
+            let entry = &mut *self.ring.as_ptr().add(index);
+            entry.set_addr(self.bufs.as_ptr().add(bid as usize * self.buf_size) as u64);
+            entry.set_len(self.buf_size as u32);
+            entry.set_bid(bid);
+        }
+        self.tail.set(tail.wrapping_add(1));
+    }
+
+    fn publish(&self) {
+        unsafe {
+            let tail = RawEntry::tail(self.ring.as_ptr()) as *const AtomicU16;
+            (*tail).store(self.tail.get(), Ordering::Release);
+        }
+    }
+}
+
+impl Drop for RingInner {
+    fn drop(&mut self) {
+        // 使用这个ring的op都持有BufRing，走到这里时已经没有进行中的op
+        let _ = self.driver.unregister_buf_ring(self.bgid);
+        unsafe { dealloc(self.ring.as_ptr() as *mut u8, self.ring_layout) };
+    }
+}
+
+/// 内核选中的缓冲区，drop时放回 [BufRing]
+pub struct BufRingEntry {
+    ring: BufRing,
+    bid: u16,
+    len: usize,
+}
+
+impl BufRingEntry {
+    /// 缓冲区id
+    pub fn bid(&self) -> u16 {
+        self.bid
+    }
+}
+
+unsafe impl IoBuf for BufRingEntry {
+    #[inline]
+    fn read_ptr(&self) -> *const u8 {
+        unsafe {
+            self.ring
+                .inner
+                .bufs
+                .as_ptr()
+                .add(self.bid as usize * self.ring.inner.buf_size)
+        }
+    }
+
+    #[inline]
+    fn bytes_init(&self) -> usize {
+        self.len
+    }
+}
+
+impl Deref for BufRingEntry {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        super::deref(self)
+    }
+}
+
+impl Drop for BufRingEntry {
+    fn drop(&mut self) {
+        self.ring.inner.push(self.bid);
+        self.ring.inner.publish();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::sync::atomic::{AtomicU16, Ordering};
+
+    use super::{BufRing, RawEntry};
+    use crate::driver::mock::runtime;
+    use crate::driver::shared_fd::SharedFd;
+    use crate::net::TcpStream;
+
+    const IORING_CQE_F_BUFFER: u32 = 1;
+    const IORING_CQE_BUFFER_SHIFT: u32 = 16;
+
+    /// 内核看到的tail
+    fn published_tail(ring: &BufRing) -> u16 {
+        unsafe { (*(RawEntry::tail(ring.inner.ring.as_ptr()) as *const AtomicU16)).load(Ordering::Acquire) }
+    }
+
+    #[test]
+    fn failed_register_keeps_existing_ring() {
+        let (rt, mock) = runtime();
+        rt.enter(|| {
+            let ring = BufRing::new(1, 4, 16).unwrap();
+            let err = BufRing::new(1, 4, 16).map(drop).unwrap_err();
+            assert_eq!(err.raw_os_error(), Some(libc::EEXIST));
+            // 注册失败不能注销另一个ring的group id
+            assert!(mock.has_buf_ring(1));
+            drop(ring);
+            assert!(!mock.has_buf_ring(1));
+        });
+    }
+
+    #[test]
+    fn entry_returns_to_ring_on_drop() {
+        let (rt, mock) = runtime();
+        let ring = rt.enter(|| BufRing::new(2, 4, 16).unwrap());
+        assert_eq!(published_tail(&ring), 4);
+
+        let result = Rc::new(RefCell::new(None));
+        let output = result.clone();
+        let recv_ring = ring.clone();
+        rt.enter(|| {
+            crate::spawn(async move {
+                let stream = TcpStream::from_shared_fd(SharedFd::new_without_register(-1));
+                *output.borrow_mut() = Some(stream.recv_buf_ring(&recv_ring).await);
+            })
+        });
+        rt.step();
+        let recv = mock.take_submitted()[0].user_data;
+        mock.complete(recv, 5, IORING_CQE_F_BUFFER | 3 << IORING_CQE_BUFFER_SHIFT);
+        rt.step();
+
+        let entry = result.borrow_mut().take().unwrap().unwrap().unwrap();
+        assert_eq!(entry.bid(), 3);
+        assert_eq!(entry.len(), 5);
+        drop(entry);
+        // 缓冲区被放到旧tail的位置并发布给内核
+        assert_eq!(published_tail(&ring), 5);
+        let slot = unsafe { &*ring.inner.ring.as_ptr() };
+        assert_eq!(slot.bid(), 3);
+        assert_eq!(slot.len(), 16);
+    }
+}
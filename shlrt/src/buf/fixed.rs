@@ -0,0 +1,227 @@
+use std::cell::{Cell, RefCell};
+use std::io;
+use std::ops::{Deref, DerefMut};
+use std::ptr::NonNull;
+use std::rc::Rc;
+
+use super::{IoBuf, IoBufMut};
+use crate::driver::{self, Inner};
+
+/// 通过IORING_REGISTER_BUFFERS注册到io_uring的一组缓冲区。
+/// 取出的 [FixedBuf] 交给读写操作时会自动使用ReadFixed/WriteFixed，
+/// 内存页只在注册时锁定一次。每个io_uring只能注册一组。
+#[derive(Clone)]
+pub struct FixedBufPool {
+    inner: Rc<PoolInner>,
+}
+
+struct PoolInner {
+    driver: Inner,
+    /// 注册的内存，注销前不能移动或者释放
+    bufs: Vec<NonNull<[u8]>>,
+    /// 每个缓冲区已经初始化的长度
+    init: Vec<Cell<usize>>,
+    /// 空闲的缓冲区索引
+    free: RefCell<Vec<u16>>,
+}
+
+impl FixedBufPool {
+    /// 注册一组缓冲区，每个缓冲区的容量就是它的capacity，已有的数据会保留
+    ///
+    /// # Panics
+    ///
+    /// 不在运行时中调用时会panic
+    pub fn register(bufs: impl IntoIterator<Item = Vec<u8>>) -> io::Result<FixedBufPool> {
+        let bufs = bufs.into_iter().collect::<Vec<_>>();
+        // 缓冲区索引是u16
+        if bufs.len() > u16::MAX as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "too many buffers"));
+        }
+
+        let mut init = Vec::with_capacity(bufs.len());
+        let mut raw = Vec::with_capacity(bufs.len());
+        for mut buf in bufs {
+            init.push(Cell::new(buf.len()));
+            buf.resize(buf.capacity(), 0);
+            raw.push(NonNull::from(Box::leak(buf.into_boxed_slice())));
+        }
+        let iovecs = raw
+            .iter()
+            .map(|buf| libc::iovec {
+                iov_base: buf.as_ptr() as *mut u8 as *mut libc::c_void,
+                iov_len: buf.len(),
+            })
+            .collect::<Vec<_>>();
+        let driver = driver::CURRENT.with(|inner| inner.clone());
+        if let Err(e) = driver.register_buffers(&iovecs) {
+            for buf in raw {
+                drop(unsafe { Box::from_raw(buf.as_ptr()) });
+            }
+            return Err(e);
+        }
+
+        // 注册成功后才创建PoolInner，它在drop时会注销io_uring上的固定缓冲区，
+        // 注册失败时已经注册的可能是另一个池
+        let inner = PoolInner {
+            driver,
+            free: RefCell::new((0..raw.len() as u16).rev().collect()),
+            bufs: raw,
+            init,
+        };
+        Ok(FixedBufPool {
+            inner: Rc::new(inner),
+        })
+    }
+
+    /// 取出一个空闲的缓冲区，全部被占用时返回None
+    pub fn try_next(&self) -> Option<FixedBuf> {
+        let index = self.inner.free.borrow_mut().pop()?;
+        let buf = self.inner.bufs[index as usize];
+        Some(FixedBuf {
+            pool: self.inner.clone(),
+            index,
+            ptr: buf.cast(),
+            cap: buf.len(),
+            len: self.inner.init[index as usize].get(),
+        })
+    }
+
+    /// 缓冲区总数
+    pub fn len(&self) -> usize {
+        self.inner.bufs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.bufs.is_empty()
+    }
+
+    /// 空闲的缓冲区数量
+    pub fn available(&self) -> usize {
+        self.inner.free.borrow().len()
+    }
+}
+
+impl Drop for PoolInner {
+    fn drop(&mut self) {
+        // 取出的FixedBuf都持有PoolInner，走到这里时已经没有使用中的缓冲区
+        if !self.bufs.is_empty() {
+            let _ = self.driver.unregister_buffers();
+        }
+        for buf in self.bufs.drain(..) {
+            drop(unsafe { Box::from_raw(buf.as_ptr()) });
+        }
+    }
+}
+
+/// 从 [FixedBufPool] 取出的缓冲区，drop时放回池中
+pub struct FixedBuf {
+    pool: Rc<PoolInner>,
+    index: u16,
+    ptr: NonNull<u8>,
+    cap: usize,
+    len: usize,
+}
+
+impl FixedBuf {
+    /// 注册时的缓冲区索引
+    pub fn buf_index(&self) -> u16 {
+        self.index
+    }
+
+    /// 缓冲区容量
+    pub fn capacity(&self) -> usize {
+        self.cap
+    }
+}
+
+unsafe impl IoBuf for FixedBuf {
+    #[inline]
+    fn read_ptr(&self) -> *const u8 {
+        self.ptr.as_ptr()
+    }
+
+    #[inline]
+    fn bytes_init(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    fn fixed_index(&self) -> Option<u16> {
+        Some(self.index)
+    }
+}
+
+unsafe impl IoBufMut for FixedBuf {
+    #[inline]
+    fn write_ptr(&mut self) -> *mut u8 {
+        self.ptr.as_ptr()
+    }
+
+    #[inline]
+    fn bytes_total(&mut self) -> usize {
+        self.cap
+    }
+
+    #[inline]
+    unsafe fn set_init(&mut self, init_len: usize) {
+        self.len = init_len;
+    }
+
+    #[inline]
+    fn fixed_index_mut(&mut self) -> Option<u16> {
+        Some(self.index)
+    }
+}
+
+impl Deref for FixedBuf {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl DerefMut for FixedBuf {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl Drop for FixedBuf {
+    fn drop(&mut self) {
+        self.pool.init[self.index as usize].set(self.len);
+        self.pool.free.borrow_mut().push(self.index);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FixedBufPool;
+    use crate::driver::mock::runtime;
+
+    #[test]
+    fn second_register_keeps_first_pool() {
+        let (rt, mock) = runtime();
+        rt.enter(|| {
+            let pool = FixedBufPool::register((0..2).map(|_| Vec::with_capacity(16))).unwrap();
+            let err = FixedBufPool::register(vec![Vec::with_capacity(16)]).map(drop).unwrap_err();
+            assert_eq!(err.raw_os_error(), Some(libc::EBUSY));
+            // 失败的注册不能注销第一个池的缓冲区
+            assert!(mock.has_buffers());
+            assert_eq!(pool.try_next().unwrap().capacity(), 16);
+            drop(pool);
+            assert!(!mock.has_buffers());
+        });
+    }
+
+    #[test]
+    fn reject_too_many_buffers() {
+        let (rt, mock) = runtime();
+        rt.enter(|| {
+            let bufs = (0..=u16::MAX as usize).map(|_| Vec::new());
+            let err = FixedBufPool::register(bufs).map(drop).unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+            assert!(!mock.has_buffers());
+        });
+    }
+}
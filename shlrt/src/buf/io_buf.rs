@@ -3,6 +3,11 @@ use crate::buf::slice::SliceMut;
 use core::ops::Bound;
 use std::ops;
 
+/// 可以交给内核读取的缓冲区
+///
+/// # Safety
+///
+/// 在缓冲区被移动之后read_ptr返回的指针也要保持有效，并且前bytes_init个字节已经初始化
 pub unsafe trait IoBuf: Unpin + 'static {
     /// 返回读缓冲区的指针
     fn read_ptr(&self) -> *const u8;
@@ -10,6 +15,12 @@ pub unsafe trait IoBuf: Unpin + 'static {
     /// 返回长度
     fn bytes_init(&self) -> usize;
 
+    /// 注册到io_uring的固定缓冲区索引，不是固定缓冲区时返回None
+    #[inline]
+    fn fixed_index(&self) -> Option<u16> {
+        None
+    }
+
     /// 返回一个可读片段，不带所有权
     #[inline]
     fn slice(self, range: impl ops::RangeBounds<usize>) -> Slice<Self>
@@ -21,6 +32,10 @@ pub unsafe trait IoBuf: Unpin + 'static {
     }
 
     /// 同slice方法，但是不检查是否越界
+    ///
+    /// # Safety
+    ///
+    /// range必须在已经初始化的数据之内
     #[inline]
     unsafe fn slice_unchecked(self, range: impl ops::RangeBounds<usize>) -> Slice<Self>
     where
@@ -139,6 +154,11 @@ unsafe impl IoBuf for bytes::BytesMut {
     }
 }
 
+/// 可以交给内核写入的缓冲区
+///
+/// # Safety
+///
+/// 在缓冲区被移动之后write_ptr返回的指针也要保持有效，并且可以写入bytes_total个字节
 pub unsafe trait IoBufMut: Unpin + 'static {
     /// 获取写缓冲区的指针
     fn write_ptr(&mut self) -> *mut u8;
@@ -147,8 +167,18 @@ pub unsafe trait IoBufMut: Unpin + 'static {
     fn bytes_total(&mut self) -> usize;
 
     /// 设置初始化到的位置
+    ///
+    /// # Safety
+    ///
+    /// 调用者要保证前pos个字节已经被写入
     unsafe fn set_init(&mut self, pos: usize);
 
+    /// 注册到io_uring的固定缓冲区索引，不是固定缓冲区时返回None
+    #[inline]
+    fn fixed_index_mut(&mut self) -> Option<u16> {
+        None
+    }
+
     /// 返回带有所有权的切片
     /// # Examples
     /// ```
@@ -168,6 +198,10 @@ pub unsafe trait IoBufMut: Unpin + 'static {
     }
 
     /// 同slice方法，但是不检查是否越界
+    ///
+    /// # Safety
+    ///
+    /// range必须在缓冲区的容量之内
     #[inline]
     unsafe fn slice_mut_unchecked(mut self, range: impl ops::RangeBounds<usize>) -> SliceMut<Self>
     where
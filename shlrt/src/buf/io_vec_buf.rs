@@ -1,6 +1,10 @@
 use std::ffi::c_void;
 
 /// Iovec 抽象，适配readv
+///
+/// # Safety
+///
+/// 在缓冲区被移动之后iovec数组和它指向的内存也要保持有效
 pub unsafe trait IoVecBuf: Unpin + 'static {
     /// 返回iovec结构的指针
     /// struct iovec {
@@ -59,11 +63,20 @@ impl From<VecBuf> for Vec<Vec<u8>> {
 }
 
 /// 可变的iovec 抽象，适配writev
+///
+/// # Safety
+///
+/// 在缓冲区被移动之后iovec数组和它指向的内存也要保持有效，并且可以写入
 pub unsafe trait IoVecBufMut: Unpin + 'static {
     fn write_iovec_ptr(&mut self) -> *mut libc::iovec;
 
     fn write_iovec_len(&mut self) -> usize;
 
+    /// 设置初始化到的位置
+    ///
+    /// # Safety
+    ///
+    /// 调用者要保证iovec中前pos个字节已经被写入
     unsafe fn set_init(&mut self, pos: usize);
 }
 
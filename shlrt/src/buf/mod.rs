@@ -7,14 +7,22 @@ pub use io_vec_buf::{IoVecBuf, IoVecBufMut, VecBuf};
 mod slice;
 pub use slice::{IoVecWrapper, IoVecWrapperMut, Slice, SliceMut};
 
+mod buf_ring;
+pub use buf_ring::{BufRing, BufRingEntry};
+
+mod fixed;
+pub use fixed::{FixedBuf, FixedBufPool};
+
 mod raw_buf;
 pub use raw_buf::{RawBuf, RawBufIovec};
 
+// 只有还没有使用的AsyncReadRentExt用到
+#[allow(dead_code)]
 mod vec_wrapper;
-pub(crate) use vec_wrapper::{read_vec_meta, write_vec_meta};
+pub(crate) use vec_wrapper::write_vec_meta;
 
 pub(crate) fn deref(buf: &impl IoBuf) -> &[u8] {
-    /// 强转为切片引用
+    // 强转为切片引用
     unsafe {
         std::slice::from_raw_parts(buf.read_ptr(), buf.bytes_init())
     }
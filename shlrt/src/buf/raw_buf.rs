@@ -9,6 +9,10 @@ pub struct RawBuf {
 
 impl RawBuf {
     /// 创建空的RawBuf
+    ///
+    /// # Safety
+    ///
+    /// 空指针不能交给内核读写
     #[inline]
     pub unsafe fn uninit() -> Self {
         Self {
@@ -18,6 +22,10 @@ impl RawBuf {
     }
 
     /// 创建并初始化
+    ///
+    /// # Safety
+    ///
+    /// ptr开始的len个字节在RawBuf使用期间必须可用
     #[inline]
     pub unsafe fn new(ptr: *const u8, len: usize) -> Self {
         Self { ptr, len }
@@ -54,7 +62,11 @@ unsafe impl IoBufMut for RawBuf {
 }
 
 impl RawBuf {
-    /// 从iovec创建RawBuf
+    /// 从iovec创建RawBuf，只使用第一个iovec
+    ///
+    /// # Safety
+    ///
+    /// 第一个iovec指向的内存在RawBuf使用期间必须可用
     #[inline]
     pub unsafe fn new_from_iovec_mut<T: IoVecBufMut>(data: &mut T) -> Option<Self> {
         if data.write_iovec_len() == 0 {
@@ -64,6 +76,11 @@ impl RawBuf {
         Some(Self::new(iovec.iov_base as _, iovec.iov_len))
     }
 
+    /// 同new_from_iovec_mut
+    ///
+    /// # Safety
+    ///
+    /// 第一个iovec指向的内存在RawBuf使用期间必须可用
     #[inline]
     pub unsafe fn new_from_iovec<T: IoVecBuf>(data: &T) -> Option<Self> {
         if data.read_iovec_len() == 0 {
@@ -81,6 +98,9 @@ pub struct RawBufIovec {
 }
 
 impl RawBufIovec {
+    /// # Safety
+    ///
+    /// ptr开始的len个iovec和它们指向的内存在RawBufIovec使用期间必须可用
     #[inline]
     pub unsafe fn new(ptr: *const libc::iovec, len: usize) -> Self {
         Self { ptr, len }
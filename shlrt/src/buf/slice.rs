@@ -18,6 +18,11 @@ impl<T: IoBuf + IoBufMut> SliceMut<T> {
 }
 
 impl<T> SliceMut<T> {
+    /// 不检查区间的new
+    ///
+    /// # Safety
+    ///
+    /// 调用者要保证区间满足new中的断言
     #[inline]
     pub unsafe fn new_unchecked(buf: T, begin: usize, end: usize) -> Self {
         Self { buf, begin, end }
@@ -70,6 +75,11 @@ unsafe impl<T: IoBuf> IoBuf for SliceMut<T> {
     fn bytes_init(&self) -> usize {
         ops::Deref::deref(self).len()
     }
+
+    #[inline]
+    fn fixed_index(&self) -> Option<u16> {
+        self.buf.fixed_index()
+    }
 }
 
 unsafe impl<T: IoBufMut> IoBufMut for SliceMut<T> {
@@ -87,6 +97,11 @@ unsafe impl<T: IoBufMut> IoBufMut for SliceMut<T> {
     unsafe fn set_init(&mut self, n: usize) {
         self.buf.set_init(self.begin + n);
     }
+
+    #[inline]
+    fn fixed_index_mut(&mut self) -> Option<u16> {
+        self.buf.fixed_index_mut()
+    }
 }
 
 pub struct Slice<T> {
@@ -105,6 +120,11 @@ impl<T: IoBuf> Slice<T> {
 }
 
 impl<T> Slice<T> {
+    /// 不检查区间的new
+    ///
+    /// # Safety
+    ///
+    /// 调用者要保证区间满足new中的断言
     #[inline]
     pub unsafe fn new_unchecked(buf: T, begin: usize, end: usize) -> Self {
         Self { buf, begin, end }
@@ -146,6 +166,11 @@ unsafe impl<T: IoBuf> IoBuf for Slice<T> {
     fn bytes_init(&self) -> usize {
         self.end - self.begin
     }
+
+    #[inline]
+    fn fixed_index(&self) -> Option<u16> {
+        self.buf.fixed_index()
+    }
 }
 
 pub struct IoVecWrapper<T> {
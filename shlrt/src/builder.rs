@@ -1,10 +1,34 @@
-use std::marker::PhantomData;
-use std::ptr::NonNull;
+use std::io;
+use std::rc::Rc;
+use std::time::Duration;
 
+use crate::blocking::BlockingPool;
+use crate::driver::{FusionDriver, IoUringDriver, LegacyDriver};
+use crate::runtime::{Context, Runtime};
+use crate::time::wheel::TimeDriver;
+
+/// 运行时构建器
+#[derive(Clone)]
 pub struct RuntimeBuilder {
     /// iouring中的entry数量
-    entries: Option<usize>,
+    entries: Option<u32>,
     uring_builder: io_uring::Builder,
+    /// 完成队列的大小，不设置时是entries的四倍
+    cq_entries: Option<u32>,
+    /// 是否开启定时器
+    timer_enabled: bool,
+    /// 固定文件表的槽位数量
+    fixed_files: Option<u32>,
+    /// 强制使用legacy driver
+    force_legacy: bool,
+    /// 同时存在的op数量上限
+    max_in_flight: Option<usize>,
+    /// 阻塞线程池的最大线程数量
+    max_blocking_threads: usize,
+    /// 阻塞线程池中空闲线程的存活时间
+    blocking_keep_alive: Duration,
+    /// drop运行时时等待进行中的op被取消的最长时间
+    shutdown_timeout: Duration,
 }
 
 impl Default for RuntimeBuilder {
@@ -14,10 +38,193 @@ impl Default for RuntimeBuilder {
 }
 
 impl RuntimeBuilder {
+    /// drop运行时时等待进行中op的默认最长时间
+    pub(crate) const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(1);
+
     pub fn new() -> Self {
         Self {
             entries: None,
             uring_builder: io_uring::IoUring::builder(),
+            cq_entries: None,
+            timer_enabled: false,
+            fixed_files: None,
+            force_legacy: false,
+            max_in_flight: None,
+            max_blocking_threads: BlockingPool::DEFAULT_MAX_THREADS,
+            blocking_keep_alive: BlockingPool::DEFAULT_KEEP_ALIVE,
+            shutdown_timeout: Self::DEFAULT_SHUTDOWN_TIMEOUT,
+        }
+    }
+
+    /// 设置iouring的entry数量，不设置时使用默认值1024
+    #[must_use]
+    pub fn with_entries(mut self, entries: u32) -> Self {
+        self.entries = Some(entries);
+        self
+    }
+
+    /// 开启定时器，开启后才能使用 [time](crate::time) 中的函数
+    #[must_use]
+    pub fn enable_timer(mut self) -> Self {
+        self.timer_enabled = true;
+        self
+    }
+
+    /// 注册有nr个空槽位的固定文件表，open和accept可以直接返回固定槽位（直接描述符）。
+    /// 使用legacy driver时忽略
+    #[must_use]
+    pub fn with_fixed_files(mut self, nr: u32) -> Self {
+        self.fixed_files = Some(nr);
+        self
+    }
+
+    /// 限制同时存在的io操作数量，达到上限后新的操作会等待已有的操作结束再提交，
+    /// 避免大量并发连接耗尽内存。默认不限制，legacy driver中不生效
+    #[must_use]
+    pub fn max_in_flight(mut self, max: usize) -> Self {
+        self.max_in_flight = Some(max);
+        self
+    }
+
+    /// 强制使用基于epoll的 [LegacyDriver]，不尝试创建io_uring。
+    /// 不设置时只有io_uring_setup失败（内核不支持或者被seccomp禁止等）才会使用它
+    #[must_use]
+    pub fn force_legacy(mut self) -> Self {
+        self.force_legacy = true;
+        self
+    }
+
+    /// 设置spawn_blocking线程池的最大线程数量，默认512
+    #[must_use]
+    pub fn max_blocking_threads(mut self, threads: usize) -> Self {
+        self.max_blocking_threads = threads;
+        self
+    }
+
+    /// 设置spawn_blocking线程池中空闲线程的存活时间，默认10秒
+    #[must_use]
+    pub fn blocking_keep_alive(mut self, keep_alive: Duration) -> Self {
+        self.blocking_keep_alive = keep_alive;
+        self
+    }
+
+    /// 直接替换iouring的构建器。完成队列的大小仍然由 [setup_cqsize](Self::setup_cqsize) 决定，
+    /// 构建器上设置的cqsize会被覆盖
+    #[must_use]
+    pub fn uring_builder(mut self, uring_builder: io_uring::Builder) -> Self {
+        self.uring_builder = uring_builder;
+        self
+    }
+
+    /// 开启IORING_SETUP_SQPOLL，内核线程空闲idle毫秒后休眠
+    #[must_use]
+    pub fn setup_sqpoll(mut self, idle: u32) -> Self {
+        self.uring_builder.setup_sqpoll(idle);
+        self
+    }
+
+    /// 将SQPOLL内核线程绑定到指定的cpu上
+    #[must_use]
+    pub fn setup_sqpoll_cpu(mut self, cpu: u32) -> Self {
+        self.uring_builder.setup_sqpoll_cpu(cpu);
+        self
+    }
+
+    /// 开启IORING_SETUP_IOPOLL，只能用于O_DIRECT打开的文件
+    #[must_use]
+    pub fn setup_iopoll(mut self) -> Self {
+        self.uring_builder.setup_iopoll();
+        self
+    }
+
+    /// 开启IORING_SETUP_COOP_TASKRUN
+    #[must_use]
+    pub fn setup_coop_taskrun(mut self) -> Self {
+        self.uring_builder.setup_coop_taskrun();
+        self
+    }
+
+    /// 开启IORING_SETUP_SINGLE_ISSUER，只有创建ring的线程可以提交sqe
+    #[must_use]
+    pub fn setup_single_issuer(mut self) -> Self {
+        self.uring_builder.setup_single_issuer();
+        self
+    }
+
+    /// 开启IORING_SETUP_DEFER_TASKRUN，需要同时开启SINGLE_ISSUER
+    #[must_use]
+    pub fn setup_defer_taskrun(mut self) -> Self {
+        self.uring_builder.setup_defer_taskrun();
+        self
+    }
+
+    /// 设置完成队列的大小，默认是entries的四倍（超过内核上限时截断）
+    #[must_use]
+    pub fn setup_cqsize(mut self, entries: u32) -> Self {
+        self.cq_entries = Some(entries);
+        self
+    }
+
+    /// 设置drop运行时时取消进行中的op后最多等待多久，默认1秒。
+    /// 超时后仍未完成的op引用的内存会被泄漏，见 [Runtime::shutdown_timeout]
+    #[must_use]
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = timeout;
+        self
+    }
+
+    /// 构建运行时
+    pub fn build(&self) -> io::Result<Runtime<FusionDriver>> {
+        let driver = if self.force_legacy {
+            FusionDriver::Legacy(LegacyDriver::new()?)
+        } else {
+            let entries = self.entries.unwrap_or(IoUringDriver::DEFAULT_ENTRIES);
+            let mut uring_builder = self.uring_builder.clone();
+            match self.cq_entries {
+                Some(cq_entries) => {
+                    uring_builder.setup_cqsize(cq_entries);
+                }
+                None => {
+                    uring_builder
+                        .setup_cqsize(entries.saturating_mul(IoUringDriver::DEFAULT_CQ_FACTOR))
+                        .setup_clamp();
+                }
+            }
+            let uring = IoUringDriver::new_with_entries(&uring_builder, entries);
+            match uring {
+                Ok(driver) => {
+                    if let Some(nr) = self.fixed_files {
+                        driver.register_files_sparse(nr)?;
+                    }
+                    if let Some(max) = self.max_in_flight {
+                        driver.set_max_in_flight(max);
+                    }
+                    FusionDriver::Uring(driver)
+                }
+                // 只有内核不支持或者禁止io_uring时才退回到legacy driver，参数错误等其他错误直接返回
+                Err(e) if matches!(e.raw_os_error(), Some(libc::ENOSYS | libc::EPERM | libc::EACCES)) => {
+                    FusionDriver::Legacy(LegacyDriver::new()?)
+                }
+                Err(e) => return Err(e),
+            }
+        };
+        let blocking = BlockingPool::new(self.max_blocking_threads, self.blocking_keep_alive);
+        let timer = self.timer_enabled.then(|| Rc::new(TimeDriver::new()));
+        Ok(Runtime::new(Context::new(blocking, timer), driver, self.shutdown_timeout))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RuntimeBuilder;
+
+    #[test]
+    fn invalid_setup_is_not_hidden_by_fallback() {
+        // 内核支持io_uring时，参数错误需要返回给调用者，而不是退回到legacy driver
+        if RuntimeBuilder::new().build().unwrap().features().is_legacy() {
+            return;
         }
+        let err = RuntimeBuilder::new().with_entries(0).build().err().unwrap();
+        assert_eq!(err.raw_os_error(), Some(libc::EINVAL));
     }
-}
\ No newline at end of file
+}
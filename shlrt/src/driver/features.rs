@@ -0,0 +1,217 @@
+use std::io;
+
+use io_uring::{IoUring, Probe};
+
+/// 运行时使用的driver支持的功能，启动时通过IORING_REGISTER_PROBE和io_uring_params得到
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Features {
+    legacy: bool,
+    /// 支持的opcode位图，内核不支持probe（5.6之前）时为None
+    opcodes: Option<[u64; 4]>,
+    single_mmap: bool,
+    nodrop: bool,
+    submit_stable: bool,
+    rw_cur_pos: bool,
+    cur_personality: bool,
+    fast_poll: bool,
+    poll_32bits: bool,
+    sqpoll_nonfixed: bool,
+    ext_arg: bool,
+    native_workers: bool,
+}
+
+impl Features {
+    /// 探测io_uring支持的opcode和feature位
+    pub(crate) fn probe(uring: &IoUring) -> Features {
+        let mut probe = Probe::new();
+        let opcodes = uring.submitter().register_probe(&mut probe).ok().map(|_| {
+            let mut opcodes = [0u64; 4];
+            for opcode in 0..=u8::MAX {
+                if probe.is_supported(opcode) {
+                    opcodes[opcode as usize / 64] |= 1 << (opcode % 64);
+                }
+            }
+            opcodes
+        });
+
+        let params = uring.params();
+        Features {
+            legacy: false,
+            opcodes,
+            single_mmap: params.is_feature_single_mmap(),
+            nodrop: params.is_feature_nodrop(),
+            submit_stable: params.is_feature_submit_stable(),
+            rw_cur_pos: params.is_feature_rw_cur_pos(),
+            cur_personality: params.is_feature_cur_personality(),
+            fast_poll: params.is_feature_fast_poll(),
+            poll_32bits: params.is_feature_poll_32bits(),
+            sqpoll_nonfixed: params.is_feature_sqpoll_nonfixed(),
+            ext_arg: params.is_feature_ext_arg(),
+            native_workers: params.is_feature_native_workers(),
+        }
+    }
+
+    /// legacy driver没有任何io_uring功能
+    pub(crate) fn legacy() -> Features {
+        Features {
+            legacy: true,
+            opcodes: Some([0; 4]),
+            single_mmap: false,
+            nodrop: false,
+            submit_stable: false,
+            rw_cur_pos: false,
+            cur_personality: false,
+            fast_poll: false,
+            poll_32bits: false,
+            sqpoll_nonfixed: false,
+            ext_arg: false,
+            native_workers: false,
+        }
+    }
+
+    /// mock driver不检查opcode
+    pub(crate) fn mock() -> Features {
+        Features {
+            legacy: false,
+            opcodes: None,
+            ..Features::legacy()
+        }
+    }
+
+    /// 去掉opcode，模拟不支持它的内核
+    pub(crate) fn set_unsupported(&mut self, opcode: u8) {
+        let opcodes = self.opcodes.get_or_insert([u64::MAX; 4]);
+        opcodes[opcode as usize / 64] &= !(1 << (opcode % 64));
+    }
+
+    /// 是否使用基于epoll的legacy driver
+    pub fn is_legacy(&self) -> bool {
+        self.legacy
+    }
+
+    /// 内核是否支持这个opcode，例如`io_uring::opcode::Read::CODE`。
+    /// 内核不支持probe时无法判断，总是返回true
+    pub fn is_opcode_supported(&self, opcode: u8) -> bool {
+        match &self.opcodes {
+            Some(opcodes) => opcodes[opcode as usize / 64] & (1 << (opcode % 64)) != 0,
+            None => true,
+        }
+    }
+
+    /// 内核是否支持IORING_REGISTER_PROBE
+    pub fn is_probed(&self) -> bool {
+        self.opcodes.is_some()
+    }
+
+    /// IORING_FEAT_SINGLE_MMAP
+    pub fn single_mmap(&self) -> bool {
+        self.single_mmap
+    }
+
+    /// IORING_FEAT_NODROP，cq满时内核不会丢弃cqe
+    pub fn nodrop(&self) -> bool {
+        self.nodrop
+    }
+
+    /// IORING_FEAT_SUBMIT_STABLE，提交之后sqe引用的数据可以释放
+    pub fn submit_stable(&self) -> bool {
+        self.submit_stable
+    }
+
+    /// IORING_FEAT_RW_CUR_POS，读写时offset为-1表示使用当前位置
+    pub fn rw_cur_pos(&self) -> bool {
+        self.rw_cur_pos
+    }
+
+    /// IORING_FEAT_CUR_PERSONALITY
+    pub fn cur_personality(&self) -> bool {
+        self.cur_personality
+    }
+
+    /// IORING_FEAT_FAST_POLL，socket没有就绪时内部poll而不是使用线程池
+    pub fn fast_poll(&self) -> bool {
+        self.fast_poll
+    }
+
+    /// IORING_FEAT_POLL_32BITS
+    pub fn poll_32bits(&self) -> bool {
+        self.poll_32bits
+    }
+
+    /// IORING_FEAT_SQPOLL_NONFIXED，SQPOLL模式下可以使用普通fd
+    pub fn sqpoll_nonfixed(&self) -> bool {
+        self.sqpoll_nonfixed
+    }
+
+    /// IORING_FEAT_EXT_ARG，等待时可以直接带上超时
+    pub fn ext_arg(&self) -> bool {
+        self.ext_arg
+    }
+
+    /// IORING_FEAT_NATIVE_WORKERS
+    pub fn native_workers(&self) -> bool {
+        self.native_workers
+    }
+
+    /// opcode不被支持时返回ErrorKind::Unsupported
+    pub(crate) fn check(&self, opcode: u8) -> io::Result<()> {
+        if self.is_opcode_supported(opcode) {
+            Ok(())
+        } else {
+            Err(unsupported_opcode(opcode))
+        }
+    }
+}
+
+fn unsupported_opcode(opcode: u8) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("io_uring opcode {} is not supported by the kernel", opcode),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use io_uring::{opcode, IoUring};
+
+    use super::Features;
+
+    #[test]
+    fn probe() {
+        // 内核或者沙箱可能不允许创建io_uring
+        let uring = match IoUring::new(8) {
+            Ok(uring) => uring,
+            Err(_) => return,
+        };
+        let features = Features::probe(&uring);
+        assert!(!features.is_legacy());
+        // IORING_REGISTER_PROBE从5.6开始支持，Nop在所有版本中都有
+        if features.is_probed() {
+            assert!(features.is_opcode_supported(opcode::Nop::CODE));
+        }
+        assert!(features.check(opcode::Nop::CODE).is_ok());
+    }
+
+    #[test]
+    fn legacy_supports_no_opcode() {
+        let features = Features::legacy();
+        assert!(features.is_legacy());
+        let err = features.check(opcode::Read::CODE).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+    }
+
+    #[test]
+    fn unsupported_opcode() {
+        let mut features = Features::mock();
+        assert!(!features.is_probed());
+        assert!(features.check(opcode::Fsync::CODE).is_ok());
+
+        features.set_unsupported(opcode::Fsync::CODE);
+        assert!(features.is_probed());
+        let err = features.check(opcode::Fsync::CODE).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+        assert!(features.check(opcode::Read::CODE).is_ok());
+    }
+}
@@ -0,0 +1,77 @@
+use std::io;
+use std::time::Duration;
+
+use crate::driver::{Driver, Features, IoUringDriver, LegacyDriver, Metrics, UnparkHandle};
+
+/// 运行时实际使用的driver，io_uring不可用或者被强制关闭时使用基于epoll的 [LegacyDriver]
+pub enum FusionDriver {
+    Uring(IoUringDriver),
+    Legacy(LegacyDriver),
+}
+
+impl FusionDriver {
+    /// 是否使用的是legacy driver
+    pub fn is_legacy(&self) -> bool {
+        matches!(self, FusionDriver::Legacy(_))
+    }
+}
+
+impl Driver for FusionDriver {
+    fn with<R>(&self, f: impl FnOnce() -> R) -> R {
+        match self {
+            FusionDriver::Uring(driver) => driver.with(f),
+            FusionDriver::Legacy(driver) => driver.with(f),
+        }
+    }
+
+    fn submit(&self) -> io::Result<()> {
+        match self {
+            FusionDriver::Uring(driver) => driver.submit(),
+            FusionDriver::Legacy(driver) => driver.submit(),
+        }
+    }
+
+    fn park(&self) -> io::Result<()> {
+        match self {
+            FusionDriver::Uring(driver) => driver.park(),
+            FusionDriver::Legacy(driver) => driver.park(),
+        }
+    }
+
+    fn park_timeout(&self, duration: Duration) -> io::Result<()> {
+        match self {
+            FusionDriver::Uring(driver) => driver.park_timeout(duration),
+            FusionDriver::Legacy(driver) => driver.park_timeout(duration),
+        }
+    }
+
+    fn shutdown_timeout(&self, timeout: Duration) -> io::Result<usize> {
+        match self {
+            FusionDriver::Uring(driver) => driver.shutdown_timeout(timeout),
+            FusionDriver::Legacy(driver) => driver.shutdown_timeout(timeout),
+        }
+    }
+
+    fn features(&self) -> Features {
+        match self {
+            FusionDriver::Uring(driver) => driver.features(),
+            FusionDriver::Legacy(driver) => driver.features(),
+        }
+    }
+
+    fn metrics(&self) -> Metrics {
+        match self {
+            FusionDriver::Uring(driver) => driver.metrics(),
+            FusionDriver::Legacy(driver) => driver.metrics(),
+        }
+    }
+
+    type Unpark = UnparkHandle;
+
+    fn unpark(&self) -> Self::Unpark {
+        match self {
+            FusionDriver::Uring(driver) => driver.unpark(),
+            FusionDriver::Legacy(driver) => driver.unpark(),
+        }
+    }
+}
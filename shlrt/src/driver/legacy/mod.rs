@@ -0,0 +1,632 @@
+use crate::driver::op::{CompletionMeta, Op, OpAble, OpTrace};
+use crate::driver::thread::{register_unpark, unregister_unpark};
+use crate::driver::{Driver, EventWaker, Features, Inner, Metrics, UnparkHandle, CURRENT};
+use crate::macros::trace::trace_event;
+use crate::utils::thread_id::current_thread_id;
+use slab::Slab;
+use std::cell::UnsafeCell;
+use std::collections::HashMap;
+use std::io;
+use std::os::fd::{AsRawFd, RawFd};
+use std::rc::Rc;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+/// eventfd在epoll中的token
+const EVENTFD_TOKEN: u64 = u64::MAX;
+
+/// 一次epoll_wait最多取出的事件数量
+const EVENTS_CAPACITY: usize = 1024;
+
+/// op等待的就绪方向
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Direction {
+    Read,
+    Write,
+}
+
+/// legacy driver不支持的操作返回的错误
+pub(crate) fn unsupported() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        "operation is not supported by the legacy driver",
+    )
+}
+
+/// 把系统调用的返回值转换成op的结果
+pub(crate) fn cvt(ret: i64) -> io::Result<u32> {
+    if ret < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(ret as u32)
+    }
+}
+
+/// 注册到epoll的fd的就绪状态，使用边沿触发，系统调用返回WouldBlock时清除
+struct ScheduledIo {
+    readable: bool,
+    writable: bool,
+    /// 等待读就绪的op
+    readers: Vec<Waker>,
+    /// 等待写就绪的op
+    writers: Vec<Waker>,
+}
+
+impl ScheduledIo {
+    fn new() -> ScheduledIo {
+        ScheduledIo {
+            readable: false,
+            writable: false,
+            readers: Vec::new(),
+            writers: Vec::new(),
+        }
+    }
+
+    fn is_ready(&self, direction: Direction) -> bool {
+        match direction {
+            Direction::Read => self.readable,
+            Direction::Write => self.writable,
+        }
+    }
+
+    fn clear(&mut self, direction: Direction) {
+        match direction {
+            Direction::Read => self.readable = false,
+            Direction::Write => self.writable = false,
+        }
+    }
+
+    fn add_waker(&mut self, direction: Direction, waker: &Waker) {
+        let wakers = match direction {
+            Direction::Read => &mut self.readers,
+            Direction::Write => &mut self.writers,
+        };
+        if !wakers.iter().any(|w| w.will_wake(waker)) {
+            wakers.push(waker.clone());
+        }
+    }
+
+    /// 收到epoll事件，设置就绪状态并唤醒对应的op
+    fn set_ready(&mut self, events: u32) {
+        let closed = (libc::EPOLLHUP | libc::EPOLLERR) as u32;
+        if events & (libc::EPOLLIN | libc::EPOLLRDHUP) as u32 != 0 || events & closed != 0 {
+            self.readable = true;
+            self.readers.drain(..).for_each(Waker::wake);
+        }
+        if events & libc::EPOLLOUT as u32 != 0 || events & closed != 0 {
+            self.writable = true;
+            self.writers.drain(..).for_each(Waker::wake);
+        }
+    }
+
+    fn wake_all(&mut self) {
+        self.readers.drain(..).for_each(Waker::wake);
+        self.writers.drain(..).for_each(Waker::wake);
+    }
+}
+
+/// legacy driver中op的状态
+enum Lifecycle {
+    /// 等待fd在direction方向就绪后重新执行系统调用
+    Waiting { fd: RawFd, direction: Direction },
+    /// 系统调用已经有结果
+    Completed(io::Result<u32>),
+    /// 没有执行的op，等待 [LegacyInner::complete_op] 完成
+    Prepared,
+    /// 没有执行的op被drop，保留位置直到complete_op，避免位置被复用后结果写到其他op上
+    Ignored,
+}
+
+/// 封装epoll数据
+pub(crate) struct LegacyInner {
+    /// 操作
+    ops: Slab<Lifecycle>,
+    /// 注册过的fd
+    io: HashMap<RawFd, ScheduledIo>,
+    /// epoll fd
+    epoll: RawFd,
+    /// epoll_wait的事件缓冲区
+    events: Vec<libc::epoll_event>,
+    /// 跨线程唤醒器
+    shared_waker: Arc<EventWaker>,
+}
+
+impl LegacyInner {
+    /// 在epoll中重新关注fd，fd还没有注册（或者被关闭后复用）时先注册。
+    /// 边沿触发模式下MOD会重新报告当前的就绪状态，不会漏掉清除状态之前到达的事件。
+    fn arm(&mut self, fd: RawFd, direction: Direction) -> io::Result<()> {
+        let mut event = libc::epoll_event {
+            events: (libc::EPOLLIN | libc::EPOLLOUT | libc::EPOLLRDHUP | libc::EPOLLET) as u32,
+            u64: fd as u64,
+        };
+        let ret = unsafe { libc::epoll_ctl(self.epoll, libc::EPOLL_CTL_MOD, fd, &mut event) };
+        if ret < 0 {
+            let e = io::Error::last_os_error();
+            if e.raw_os_error() != Some(libc::ENOENT) {
+                return Err(e);
+            }
+            cvt(
+                unsafe { libc::epoll_ctl(self.epoll, libc::EPOLL_CTL_ADD, fd, &mut event) } as i64,
+            )?;
+        }
+        self.io
+            .entry(fd)
+            .or_insert_with(ScheduledIo::new)
+            .clear(direction);
+        Ok(())
+    }
+
+    /// 执行op的系统调用，没有就绪时在epoll中等待
+    fn call<T: OpAble>(&mut self, data: &mut T) -> Lifecycle {
+        let interest = data.legacy_interest();
+        match (data.legacy_call(), interest) {
+            (Err(e), Some((direction, fd))) if e.kind() == io::ErrorKind::WouldBlock => {
+                match self.arm(fd, direction) {
+                    Ok(()) => Lifecycle::Waiting { fd, direction },
+                    Err(e) => Lifecycle::Completed(Err(e)),
+                }
+            }
+            (result, _) => Lifecycle::Completed(result),
+        }
+    }
+
+    /// 提交任务和data，先直接执行一次系统调用
+    pub(crate) fn submit_with_data<T>(
+        this: &Rc<UnsafeCell<LegacyInner>>,
+        data: T,
+    ) -> Result<Op<T>, (io::Error, T)>
+    where
+        T: OpAble,
+    {
+        let inner = unsafe { &mut *this.get() };
+        let mut data = data;
+        let mut trace = OpTrace::new();
+        let (opcode, fd) = (data.opcode(), data.fd());
+        let lifecycle = inner.call(&mut data);
+        let index = inner.ops.insert(lifecycle);
+        trace.submitted(opcode, fd, index as _);
+        Ok(Op {
+            driver: Inner::Legacy(this.clone()),
+            index,
+            data: Some(data),
+            timeout: None,
+            trace,
+            cancel: None,
+        })
+    }
+
+    /// 创建一个没有执行的op，只能由 [LegacyInner::complete_op] 完成
+    pub(crate) fn prepare_op<T>(this: &Rc<UnsafeCell<LegacyInner>>, data: T) -> Op<T> {
+        let inner = unsafe { &mut *this.get() };
+        Op {
+            driver: Inner::Legacy(this.clone()),
+            index: inner.ops.insert(Lifecycle::Prepared),
+            data: Some(data),
+            timeout: None,
+            trace: OpTrace::new(),
+            cancel: None,
+        }
+    }
+
+    /// 直接完成一个op，op已经被drop时释放它的位置
+    pub(crate) fn complete_op(
+        this: &Rc<UnsafeCell<LegacyInner>>,
+        index: usize,
+        result: io::Result<u32>,
+    ) {
+        let inner = unsafe { &mut *this.get() };
+        match inner.ops.get_mut(index) {
+            Some(Lifecycle::Ignored) => {
+                inner.ops.remove(index);
+            }
+            Some(lifecycle) => *lifecycle = Lifecycle::Completed(result),
+            None => {}
+        }
+    }
+
+    /// 轮询操作，fd就绪后重新执行系统调用
+    pub(crate) fn poll_op<T: OpAble>(
+        this: &Rc<UnsafeCell<LegacyInner>>,
+        data: &mut T,
+        index: usize,
+        cx: &mut Context<'_>,
+    ) -> Poll<CompletionMeta> {
+        let inner = unsafe { &mut *this.get() };
+        if let Lifecycle::Waiting { fd, direction } = inner.ops[index] {
+            // 没有ScheduledIo说明fd已经注销，直接重试
+            if let Some(io) = inner.io.get_mut(&fd) {
+                if !io.is_ready(direction) {
+                    io.add_waker(direction, cx.waker());
+                    return Poll::Pending;
+                }
+            }
+            inner.ops[index] = inner.call(data);
+            if let Lifecycle::Waiting { fd, direction } = inner.ops[index] {
+                if let Some(io) = inner.io.get_mut(&fd) {
+                    io.add_waker(direction, cx.waker());
+                }
+                return Poll::Pending;
+            }
+        }
+
+        match inner.ops.remove(index) {
+            Lifecycle::Completed(result) => Poll::Ready(CompletionMeta { result, flags: 0 }),
+            // 只有链中的op会处于Prepared，legacy driver不支持链接
+            Lifecycle::Prepared => Poll::Ready(CompletionMeta { result: Err(unsupported()), flags: 0 }),
+            Lifecycle::Waiting { .. } | Lifecycle::Ignored => {
+                unreachable!("op polled in an unexpected state")
+            }
+        }
+    }
+
+    /// 清理操作，系统调用都是同步完成的，没有需要等待内核的数据
+    pub(crate) fn drop_op(this: &Rc<UnsafeCell<LegacyInner>>, index: usize) {
+        let inner = unsafe { &mut *this.get() };
+        match inner.ops.get_mut(index) {
+            Some(lifecycle @ Lifecycle::Prepared) => *lifecycle = Lifecycle::Ignored,
+            Some(_) => {
+                inner.ops.remove(index);
+            }
+            None => {}
+        }
+    }
+
+    /// 取消还在等待就绪的操作，以ECANCELED完成
+    pub(crate) fn cancel_op(this: &Rc<UnsafeCell<LegacyInner>>, index: usize) {
+        let inner = unsafe { &mut *this.get() };
+        inner.cancel(index);
+    }
+
+    fn cancel(&mut self, index: usize) {
+        if let Some(Lifecycle::Waiting { fd, .. }) = self.ops.get(index) {
+            if let Some(io) = self.io.get_mut(fd) {
+                io.wake_all();
+            }
+            trace_event!(index, fd, "cancel");
+            self.ops[index] =
+                Lifecycle::Completed(Err(io::Error::from_raw_os_error(libc::ECANCELED)));
+        }
+    }
+
+    /// fd关闭前从epoll中移除，避免fd复用后沿用旧的就绪状态
+    pub(crate) fn deregister_fd(this: &Rc<UnsafeCell<LegacyInner>>, fd: RawFd) {
+        let inner = unsafe { &mut *this.get() };
+        if let Some(mut io) = inner.io.remove(&fd) {
+            unsafe { libc::epoll_ctl(inner.epoll, libc::EPOLL_CTL_DEL, fd, std::ptr::null_mut()) };
+            io.wake_all();
+        }
+    }
+
+    /// 等待epoll事件，timeout为毫秒，-1表示一直等待
+    fn poll_events(&mut self, timeout: i32) -> io::Result<()> {
+        let n = unsafe {
+            libc::epoll_wait(
+                self.epoll,
+                self.events.as_mut_ptr(),
+                self.events.len() as i32,
+                timeout,
+            )
+        };
+        if n < 0 {
+            let e = io::Error::last_os_error();
+            if e.kind() == io::ErrorKind::Interrupted {
+                return Ok(());
+            }
+            return Err(e);
+        }
+        trace_event!(events = n, "tick");
+
+        for i in 0..n as usize {
+            let (events, token) = (self.events[i].events, self.events[i].u64);
+            if token == EVENTFD_TOKEN {
+                // 读出计数，eventfd回到未就绪状态
+                let mut buf = [0u8; 8];
+                unsafe {
+                    libc::read(
+                        self.shared_waker.as_raw_fd(),
+                        buf.as_mut_ptr() as *mut libc::c_void,
+                        buf.len(),
+                    )
+                };
+                continue;
+            }
+            if let Some(io) = self.io.get_mut(&(token as RawFd)) {
+                io.set_ready(events);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for LegacyInner {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.epoll);
+        }
+    }
+}
+
+/// 基于epoll的driver，在io_uring不可用时使用。
+/// 等待fd就绪后执行非阻塞的系统调用，没有就绪事件的操作（例如普通文件的读写）直接同步执行。
+/// 链接操作、multishot、provided buffer和固定文件等io_uring特有的功能返回ErrorKind::Unsupported。
+pub struct LegacyDriver {
+    inner: Rc<UnsafeCell<LegacyInner>>,
+}
+
+impl LegacyDriver {
+    pub(crate) fn new() -> io::Result<LegacyDriver> {
+        let epoll = unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) };
+        if epoll < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let eventfd = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC | libc::EFD_NONBLOCK) };
+        if eventfd < 0 {
+            let e = io::Error::last_os_error();
+            unsafe { libc::close(epoll) };
+            return Err(e);
+        }
+        let shared_waker = Arc::new(EventWaker::new(eventfd));
+        let inner = LegacyInner {
+            ops: Slab::new(),
+            io: HashMap::new(),
+            epoll,
+            events: vec![libc::epoll_event { events: 0, u64: 0 }; EVENTS_CAPACITY],
+            shared_waker,
+        };
+
+        let mut event = libc::epoll_event {
+            events: libc::EPOLLIN as u32,
+            u64: EVENTFD_TOKEN,
+        };
+        cvt(unsafe { libc::epoll_ctl(epoll, libc::EPOLL_CTL_ADD, eventfd, &mut event) } as i64)?;
+        register_unpark(current_thread_id(), Arc::downgrade(&inner.shared_waker));
+
+        Ok(LegacyDriver {
+            inner: Rc::new(UnsafeCell::new(inner)),
+        })
+    }
+
+    /// 调用其他线程投递过来的waker，返回是否有waker
+    fn wake_remote(inner: &mut LegacyInner) -> bool {
+        let wakers = inner.shared_waker.take_wakers();
+        let woken = !wakers.is_empty();
+        for waker in wakers {
+            waker.wake();
+        }
+        woken
+    }
+
+    fn inner_park(&self, timeout: Option<Duration>) -> io::Result<()> {
+        let inner = unsafe { &mut *self.inner.get() };
+        let mut need_wait = !Self::wake_remote(inner);
+        if need_wait {
+            // 先标记即将阻塞再检查一次，其他线程在此之后投递的waker一定会写eventfd
+            inner.shared_waker.awake.store(false, Ordering::SeqCst);
+            if Self::wake_remote(inner) {
+                need_wait = false;
+            }
+        }
+        let timeout = match (need_wait, timeout) {
+            (false, _) => 0,
+            // 向上取整到毫秒，避免定时器还没到期就返回
+            (true, Some(duration)) => duration
+                .as_nanos()
+                .div_ceil(1_000_000)
+                .min(i32::MAX as u128) as i32,
+            (true, None) => -1,
+        };
+        if timeout != 0 {
+            trace_event!(timeout_ms = timeout, "park");
+        }
+        let result = inner.poll_events(timeout);
+        inner.shared_waker.awake.store(true, Ordering::SeqCst);
+        result
+    }
+}
+
+impl Driver for LegacyDriver {
+    fn with<R>(&self, f: impl FnOnce() -> R) -> R {
+        let inner = Inner::Legacy(self.inner.clone());
+        CURRENT.set(&inner, f)
+    }
+
+    fn submit(&self) -> io::Result<()> {
+        // 系统调用在提交时已经执行，这里只收取已经就绪的事件
+        let inner = unsafe { &mut *self.inner.get() };
+        inner.poll_events(0)
+    }
+
+    fn park(&self) -> io::Result<()> {
+        self.inner_park(None)
+    }
+
+    fn park_timeout(&self, duration: Duration) -> io::Result<()> {
+        self.inner_park(Some(duration))
+    }
+
+    fn shutdown_timeout(&self, _timeout: Duration) -> io::Result<usize> {
+        // 没有在内核中运行的操作，取消所有等待就绪的op即可
+        let inner = unsafe { &mut *self.inner.get() };
+        let waiting = inner
+            .ops
+            .iter()
+            .filter(|(_, lifecycle)| matches!(lifecycle, Lifecycle::Waiting { .. }))
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+        for index in waiting {
+            inner.cancel(index);
+        }
+        Ok(0)
+    }
+
+    fn features(&self) -> Features {
+        Features::legacy()
+    }
+
+    fn metrics(&self) -> Metrics {
+        Metrics::default()
+    }
+
+    type Unpark = UnparkHandle;
+
+    fn unpark(&self) -> Self::Unpark {
+        let inner = unsafe { &*self.inner.get() };
+        UnparkHandle(Arc::downgrade(&inner.shared_waker))
+    }
+}
+
+impl AsRawFd for LegacyDriver {
+    fn as_raw_fd(&self) -> RawFd {
+        unsafe { (*self.inner.get()).epoll }
+    }
+}
+
+impl Drop for LegacyDriver {
+    fn drop(&mut self) {
+        let inner = unsafe { &*self.inner.get() };
+        unregister_unpark(current_thread_id(), &Arc::downgrade(&inner.shared_waker));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::os::fd::RawFd;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    use crate::driver::shared_fd::SharedFd;
+    use crate::fs::File;
+    use crate::io::Canceller;
+    use crate::Chain;
+    use crate::RuntimeBuilder;
+
+    /// 非阻塞的pipe，返回(读端, 写端)
+    fn pipe() -> (RawFd, RawFd) {
+        let mut fds = [0; 2];
+        assert_eq!(
+            unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_NONBLOCK | libc::O_CLOEXEC) },
+            0
+        );
+        (fds[0], fds[1])
+    }
+
+    fn write(fd: RawFd, data: &[u8]) {
+        let n = unsafe { libc::write(fd, data.as_ptr() as *const libc::c_void, data.len()) };
+        assert_eq!(n, data.len() as isize);
+    }
+
+    fn file(fd: RawFd) -> File {
+        File::from_shared_fd(SharedFd::new_without_register(fd))
+    }
+
+    #[test]
+    fn readiness_is_rearmed() {
+        let mut rt = RuntimeBuilder::new().force_legacy().build().unwrap();
+        let (rx, tx) = pipe();
+        let (ready_tx, ready_rx) = std::sync::mpsc::channel();
+        let writer = std::thread::spawn(move || {
+            for chunk in [b"ab", b"cd"] {
+                std::thread::sleep(Duration::from_millis(20));
+                write(tx, chunk);
+                // 等待读端重新进入等待
+                let _ = ready_rx.recv();
+            }
+        });
+
+        rt.block_on(async {
+            let file = file(rx);
+            for expected in [b"ab", b"cd"] {
+                // 第二次读时pipe已经读空，需要在epoll中重新等待就绪
+                let (res, buf) = file.read_at(Vec::with_capacity(8), 0).await;
+                assert_eq!(&buf[..res.unwrap()], expected);
+                ready_tx.send(()).unwrap();
+            }
+        });
+        writer.join().unwrap();
+        unsafe { libc::close(tx) };
+    }
+
+    #[test]
+    fn cancel_wakes_waiters() {
+        let rt = RuntimeBuilder::new().force_legacy().build().unwrap();
+        let (rx, tx) = pipe();
+        let rx = Rc::new(file(rx));
+        let canceller = Canceller::new();
+        let handle = canceller.handle();
+        let canceled = Rc::new(RefCell::new(None));
+        let read = Rc::new(RefCell::new(None));
+        let (canceled_out, read_out) = (canceled.clone(), read.clone());
+        let rx2 = rx.clone();
+        rt.enter(|| {
+            crate::spawn(async move {
+                let (res, _) = rx
+                    .cancelable_read_at(Vec::with_capacity(8), 0, &handle)
+                    .await;
+                *canceled_out.borrow_mut() = Some(res.unwrap_err().kind());
+            });
+            crate::spawn(async move {
+                let (res, buf) = rx2.read_at(Vec::with_capacity(8), 0).await;
+                *read_out.borrow_mut() = Some(buf[..res.unwrap()].to_vec());
+            });
+        });
+        rt.step();
+        assert!(canceled.borrow().is_none() && read.borrow().is_none());
+
+        // 被取消的op在同一个fd的等待队列中，取消时需要唤醒它，另一个op重新等待
+        rt.enter(|| canceller.cancel());
+        rt.step();
+        assert_eq!(*canceled.borrow(), Some(std::io::ErrorKind::Interrupted));
+        assert!(read.borrow().is_none());
+
+        write(tx, b"xy");
+        rt.step();
+        assert_eq!(read.borrow().as_deref(), Some(&b"xy"[..]));
+        drop(rt);
+        unsafe { libc::close(tx) };
+    }
+
+    #[test]
+    fn offset_is_ignored_on_pipes() {
+        let mut rt = RuntimeBuilder::new().force_legacy().build().unwrap();
+        let (rx, tx) = pipe();
+        rt.block_on(async {
+            // pipe不支持pread/pwrite，返回ESPIPE后退回到read/write
+            let (res, _) = file(tx).write_at(b"hello".to_vec(), 3).await;
+            assert_eq!(res.unwrap(), 5);
+            let (res, buf) = file(rx).read_at(Vec::with_capacity(8), 3).await;
+            assert_eq!(&buf[..res.unwrap()], b"hello");
+        });
+    }
+
+    #[test]
+    fn dropped_link_keeps_its_slot() {
+        let rt = RuntimeBuilder::new().force_legacy().build().unwrap();
+        let (rx, tx) = pipe();
+        let read = Rc::new(RefCell::new(None));
+        let output = read.clone();
+        let chain = rt.enter(|| {
+            let mut chain = Chain::new();
+            drop(chain.sync_all(&file(-1)));
+            // 新的op不能复用被drop的link的位置
+            crate::spawn(async move {
+                let (res, buf) = file(rx).read_at(Vec::with_capacity(8), 0).await;
+                *output.borrow_mut() = Some(res.map(|n| buf[..n].to_vec()));
+            });
+            chain
+        });
+        rt.step();
+
+        // 没有提交的链以ECANCELED完成，不能影响还在等待的read
+        rt.enter(|| drop(chain));
+        rt.step();
+        assert!(read.borrow().is_none());
+
+        write(tx, b"ok");
+        rt.step();
+        assert_eq!(read.borrow_mut().take().unwrap().unwrap(), b"ok");
+        unsafe { libc::close(tx) };
+    }
+}
@@ -0,0 +1,182 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+
+/// driver的运行统计，通过 [Runtime::metrics](crate::Runtime::metrics) 获取快照。
+/// 计数器只在driver所在线程上更新，没有原子操作。legacy driver不统计
+#[derive(Clone, PartialEq, Eq)]
+pub struct Metrics {
+    /// 每个opcode放入sq的sqe数量
+    pub(crate) sqes: [u64; 256],
+    /// 收割的cqe数量
+    pub(crate) cqes: u64,
+    /// op失败时按errno统计的次数
+    pub(crate) errors: BTreeMap<i32, u64>,
+    /// 提交的取消操作数量
+    pub(crate) cancels: u64,
+    /// slab中的op数量
+    pub(crate) in_flight: usize,
+    /// 阻塞在submit_and_wait中的总时间
+    pub(crate) submit_wait_time: Duration,
+    /// 阻塞等待的次数
+    pub(crate) parks: u64,
+    /// 因为超时而结束等待的次数
+    pub(crate) timeout_wakeups: u64,
+    /// 检测到IORING_SQ_CQ_OVERFLOW的次数
+    pub(crate) cq_overflows: u64,
+    /// 内核丢弃的cqe数量
+    pub(crate) cq_dropped: u64,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics {
+            sqes: [0; 256],
+            cqes: 0,
+            errors: BTreeMap::new(),
+            cancels: 0,
+            in_flight: 0,
+            submit_wait_time: Duration::ZERO,
+            parks: 0,
+            timeout_wakeups: 0,
+            cq_overflows: 0,
+            cq_dropped: 0,
+        }
+    }
+}
+
+impl fmt::Debug for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // 只输出出现过的opcode
+        let sqes = self
+            .sqes
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count != 0)
+            .collect::<BTreeMap<_, _>>();
+        f.debug_struct("Metrics")
+            .field("sqes", &sqes)
+            .field("cqes", &self.cqes)
+            .field("errors", &self.errors)
+            .field("cancels", &self.cancels)
+            .field("in_flight", &self.in_flight)
+            .field("submit_wait_time", &self.submit_wait_time)
+            .field("parks", &self.parks)
+            .field("timeout_wakeups", &self.timeout_wakeups)
+            .field("cq_overflows", &self.cq_overflows)
+            .field("cq_dropped", &self.cq_dropped)
+            .finish()
+    }
+}
+
+impl Metrics {
+    /// 这个opcode放入sq的sqe数量，例如`io_uring::opcode::Read::CODE`
+    pub fn sqes_pushed(&self, opcode: u8) -> u64 {
+        self.sqes[opcode as usize]
+    }
+
+    /// 放入sq的sqe总数，不包括driver内部的超时和eventfd读操作
+    pub fn total_sqes_pushed(&self) -> u64 {
+        self.sqes.iter().sum()
+    }
+
+    /// 收割的cqe总数
+    pub fn cqes_reaped(&self) -> u64 {
+        self.cqes
+    }
+
+    /// op以这个errno失败的次数
+    pub fn errors_by_errno(&self, errno: i32) -> u64 {
+        self.errors.get(&errno).copied().unwrap_or(0)
+    }
+
+    /// 所有出现过的errno和对应的次数，按errno排序
+    pub fn errors(&self) -> impl Iterator<Item = (i32, u64)> + '_ {
+        self.errors.iter().map(|(errno, count)| (*errno, *count))
+    }
+
+    /// 提交的取消操作数量，包括drop和关闭运行时产生的取消
+    pub fn cancels(&self) -> u64 {
+        self.cancels
+    }
+
+    /// 获取快照时slab中的op数量，包括已经被drop但是还在等待cqe的op
+    pub fn in_flight(&self) -> usize {
+        self.in_flight
+    }
+
+    /// 阻塞在submit_and_wait中的总时间
+    pub fn submit_wait_time(&self) -> Duration {
+        self.submit_wait_time
+    }
+
+    /// 线程阻塞等待完成事件的次数
+    pub fn parks(&self) -> u64 {
+        self.parks
+    }
+
+    /// 阻塞等待因为超时（而不是完成事件或者唤醒）结束的次数
+    pub fn timeout_wakeups(&self) -> u64 {
+        self.timeout_wakeups
+    }
+
+    /// cq溢出的次数，每次溢出后driver都会把内核暂存的cqe刷回cq，
+    /// 持续增长说明cq太小
+    pub fn cq_overflows(&self) -> u64 {
+        self.cq_overflows
+    }
+
+    /// 内核因为cq满了而丢弃的cqe数量，没有IORING_FEAT_NODROP的内核上可能不为0，
+    /// 对应的op永远不会完成
+    pub fn cq_dropped(&self) -> u64 {
+        self.cq_dropped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use io_uring::opcode;
+
+    use crate::driver::mock::runtime;
+    use crate::driver::op::Op;
+    use crate::driver::shared_fd::SharedFd;
+    use crate::driver::Driver;
+
+    #[test]
+    fn counters_follow_op_lifecycle() {
+        let (rt, mock) = runtime();
+        let fd = SharedFd::new_without_register(-1);
+        let (first, second) = rt.enter(|| {
+            (Op::read_at(&fd, Vec::new(), 0).unwrap(), Op::read_at(&fd, Vec::new(), 0).unwrap())
+        });
+        let sqes = mock.take_submitted();
+        mock.complete(sqes[0].user_data, -libc::EBADF, 0);
+        rt.step();
+        // 已经完成的op直接释放，进行中的op需要取消并且留在slab中等待cqe
+        rt.enter(|| drop((first, second)));
+
+        let metrics = rt.metrics();
+        assert_eq!(metrics.sqes_pushed(opcode::Read::CODE), 2);
+        assert_eq!(metrics.sqes_pushed(opcode::AsyncCancel::CODE), 1);
+        assert_eq!(metrics.total_sqes_pushed(), 3);
+        assert_eq!(metrics.cqes_reaped(), 1);
+        assert_eq!(metrics.errors_by_errno(libc::EBADF), 1);
+        assert_eq!(metrics.cancels(), 1);
+        assert_eq!(metrics.in_flight(), 1);
+        assert_eq!(metrics.parks(), 0);
+
+        mock.complete(sqes[1].user_data, -libc::ECANCELED, 0);
+        mock.park().unwrap();
+        // 没有事件可以交付，等待超时
+        mock.park_timeout(Duration::from_millis(1)).unwrap();
+
+        let metrics = rt.metrics();
+        assert_eq!(metrics.cqes_reaped(), 2);
+        assert_eq!(metrics.errors().collect::<Vec<_>>(), [(libc::EBADF, 1), (libc::ECANCELED, 1)]);
+        assert_eq!(metrics.in_flight(), 0);
+        assert_eq!(metrics.parks(), 2);
+        assert_eq!(metrics.timeout_wakeups(), 1);
+    }
+}
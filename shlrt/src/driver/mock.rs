@@ -0,0 +1,420 @@
+use std::cell::UnsafeCell;
+use std::collections::{HashSet, VecDeque};
+use std::io;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use io_uring::{opcode, squeue};
+
+use crate::driver::op::{CompletionMeta, MultishotOp, Op, OpAble, OpTrace};
+use crate::driver::uring::{Ops, CANCEL_USERDATA, LINK_TIMEOUT_FLAG, MIN_REVERSED_USERDATA};
+use crate::driver::thread::{register_unpark, unregister_unpark};
+use crate::driver::util::timespec;
+use crate::blocking::BlockingPool;
+use crate::driver::{CURRENT, Driver, EventWaker, Features, Inner, Metrics, Unpark};
+use crate::runtime::Runtime;
+use crate::utils::thread_id::current_thread_id;
+
+/// 提交给mock driver的sqe
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct MockSqe {
+    pub(crate) opcode: u8,
+    /// IOSQE_*标志位
+    pub(crate) flags: u8,
+    pub(crate) user_data: u64,
+}
+
+
+/// 封装mock数据，op的状态机和io_uring driver完全一样
+pub(crate) struct MockInner {
+    ops: Ops,
+    /// 按提交顺序记录的sqe
+    submitted: Vec<MockSqe>,
+    /// 测试安排的cqe，下一次submit或者park时按顺序交付
+    completions: VecDeque<(u64, i32, u32)>,
+    /// 已经注册的buffer ring的group id
+    buf_rings: HashSet<u16>,
+    /// 是否注册了固定缓冲区
+    buffers: bool,
+    /// 测试设置的内核支持的opcode
+    features: Features,
+    /// 接收阻塞线程池等其他线程投递的waker
+    shared_waker: Arc<EventWaker>,
+    /// 和io_uring driver一样的运行统计
+    metrics: Metrics,
+}
+
+impl MockInner {
+    fn record(&mut self, opcode: u8, flags: squeue::Flags, sqe: &squeue::Entry) {
+        self.metrics.sqes[opcode as usize] += 1;
+        self.submitted.push(MockSqe {
+            opcode,
+            flags: flags.bits(),
+            user_data: sqe.get_user_data(),
+        });
+    }
+
+    fn push_cancel(&mut self, index: usize) {
+        let cancel = opcode::AsyncCancel::new(index as u64).build().user_data(CANCEL_USERDATA);
+        self.metrics.cancels += 1;
+        self.record(opcode::AsyncCancel::CODE, squeue::Flags::empty(), &cancel);
+    }
+
+    /// 调用其他线程投递的waker，再交付安排好的cqe，返回唤醒和cqe的数量
+    fn tick(&mut self) -> usize {
+        let mut events = 0;
+        for waker in self.shared_waker.take_wakers() {
+            waker.wake();
+            events += 1;
+        }
+        while let Some((user_data, res, flags)) = self.completions.pop_front() {
+            events += 1;
+            self.metrics.cqes += 1;
+            if user_data >= MIN_REVERSED_USERDATA {
+                continue;
+            }
+            if user_data & LINK_TIMEOUT_FLAG != 0 {
+                self.ops.complete_link_timeout(user_data, res);
+                continue;
+            }
+            let index = user_data as usize;
+            assert!(self.ops.get(index).is_some(), "no op with user_data {}", user_data);
+            let result = if res >= 0 {
+                Ok(res as u32)
+            } else {
+                *self.metrics.errors.entry(-res).or_insert(0) += 1;
+                Err(io::Error::from_raw_os_error(-res))
+            };
+            self.ops.complete(index, result, flags);
+        }
+        events
+    }
+
+    fn new_op<T>(this: &Rc<UnsafeCell<MockInner>>, data: T) -> Op<T> {
+        let inner = unsafe { &mut *this.get() };
+        Op {
+            driver: Inner::Mock(this.clone()),
+            index: inner.ops.insert(),
+            data: Some(data),
+            timeout: None,
+            trace: OpTrace::new(),
+            cancel: None,
+        }
+    }
+
+    pub(crate) fn submit_with_data<T: OpAble>(
+        this: &Rc<UnsafeCell<MockInner>>,
+        data: T,
+    ) -> Result<Op<T>, (io::Error, T)> {
+        let mut data = data;
+        let inner = unsafe { &mut *this.get() };
+        if let Err(e) = inner.ops.check_capacity() {
+            return Err((e, data));
+        }
+        let opcode = data.opcode();
+        if let Err(e) = inner.features.check(opcode) {
+            return Inner::Mock(this.clone()).blocking_fallback(data, e);
+        }
+        let (op, sqe) = Self::prepare_op(this, data);
+        inner.record(opcode, squeue::Flags::empty(), &sqe);
+        Ok(op)
+    }
+
+    pub(crate) fn submit_with_timeout<T: OpAble>(
+        this: &Rc<UnsafeCell<MockInner>>,
+        data: T,
+        timeout: Duration,
+    ) -> Result<Op<T>, (io::Error, T)> {
+        let mut op = Self::new_op(this, data);
+        let timespec = op.timeout.insert(Box::new(timespec(timeout)));
+        let link_timeout = opcode::LinkTimeout::new(&**timespec)
+            .build()
+            .user_data(op.index as u64 | LINK_TIMEOUT_FLAG);
+        let data = unsafe { op.data.as_mut().unwrap_unchecked() };
+        let sqe = data
+            .uring_op()
+            .flags(squeue::Flags::IO_LINK)
+            .user_data(op.index as _);
+        op.trace.submitted(data.opcode(), data.fd(), op.index as _);
+        let inner = unsafe { &mut *this.get() };
+        inner.record(data.opcode(), squeue::Flags::IO_LINK, &sqe);
+        inner.record(opcode::LinkTimeout::CODE, squeue::Flags::empty(), &link_timeout);
+        Ok(op)
+    }
+
+    pub(crate) fn prepare_op<T: OpAble>(this: &Rc<UnsafeCell<MockInner>>, data: T) -> (Op<T>, squeue::Entry) {
+        let mut op = Self::new_op(this, data);
+        let data = unsafe { op.data.as_mut().unwrap_unchecked() };
+        let sqe = data.uring_op().user_data(op.index as _);
+        op.trace.submitted(data.opcode(), data.fd(), op.index as _);
+        (op, sqe)
+    }
+
+    pub(crate) fn submit_chain(
+        this: &Rc<UnsafeCell<MockInner>>,
+        opcodes: &[u8],
+        entries: Vec<squeue::Entry>,
+        flag: squeue::Flags,
+    ) -> io::Result<()> {
+        let inner = unsafe { &mut *this.get() };
+        let last = entries.len().saturating_sub(1);
+        for (i, (&opcode, sqe)) in opcodes.iter().zip(&entries).enumerate() {
+            let flags = if i < last { flag } else { squeue::Flags::empty() };
+            inner.record(opcode, flags, sqe);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn complete_op(this: &Rc<UnsafeCell<MockInner>>, index: usize, result: io::Result<u32>) {
+        let inner = unsafe { &mut *this.get() };
+        inner.ops.complete(index, result, 0);
+    }
+
+    pub(crate) fn submit_multishot<T: OpAble>(
+        this: &Rc<UnsafeCell<MockInner>>,
+        data: T,
+    ) -> io::Result<MultishotOp<T>> {
+        let inner = unsafe { &mut *this.get() };
+        let mut op = MultishotOp {
+            driver: Inner::Mock(this.clone()),
+            index: inner.ops.insert_multishot(data.release()),
+            data: Some(data),
+        };
+        let data = unsafe { op.data.as_mut().unwrap_unchecked() };
+        let sqe = data.uring_op().user_data(op.index as _);
+        inner.record(data.opcode(), squeue::Flags::empty(), &sqe);
+        Ok(op)
+    }
+
+    pub(crate) fn poll_multishot(
+        this: &Rc<UnsafeCell<MockInner>>,
+        index: usize,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<CompletionMeta>> {
+        let inner = unsafe { &mut *this.get() };
+        unsafe { inner.ops.get(index).unwrap_unchecked() }.poll_multishot(cx)
+    }
+
+    pub(crate) fn rearm_multishot<T: OpAble>(
+        this: &Rc<UnsafeCell<MockInner>>,
+        index: usize,
+        data: &mut T,
+    ) -> io::Result<()> {
+        let inner = unsafe { &mut *this.get() };
+        unsafe { inner.ops.get(index).unwrap_unchecked() }.rearm();
+        let sqe = data.uring_op().user_data(index as _);
+        inner.record(data.opcode(), squeue::Flags::empty(), &sqe);
+        Ok(())
+    }
+
+    pub(crate) fn poll_capacity(this: &Rc<UnsafeCell<MockInner>>, cx: &mut Context<'_>) -> Poll<()> {
+        let inner = unsafe { &mut *this.get() };
+        inner.ops.poll_capacity(cx)
+    }
+
+    pub(crate) fn poll_op(this: &Rc<UnsafeCell<MockInner>>, index: usize, cx: &mut Context<'_>) -> Poll<CompletionMeta> {
+        let inner = unsafe { &mut *this.get() };
+        unsafe { inner.ops.get(index).unwrap_unchecked() }.poll_op(cx)
+    }
+
+    pub(crate) fn drop_op<T: 'static>(this: &Rc<UnsafeCell<MockInner>>, index: usize, data: &mut Option<T>) {
+        if index == usize::MAX {
+            return;
+        }
+        let inner = unsafe { &mut *this.get() };
+        if let Some(lifecycle) = inner.ops.get(index) {
+            if !lifecycle.drop_op(data) {
+                inner.push_cancel(index);
+            }
+        }
+    }
+
+    pub(crate) fn cancel_op(this: &Rc<UnsafeCell<MockInner>>, index: usize) {
+        let inner = unsafe { &mut *this.get() };
+        inner.push_cancel(index);
+    }
+
+    /// 和内核一样，同一个group id不能重复注册
+    pub(crate) fn register_buf_ring(this: &Rc<UnsafeCell<MockInner>>, bgid: u16) -> io::Result<()> {
+        let inner = unsafe { &mut *this.get() };
+        if inner.buf_rings.insert(bgid) {
+            Ok(())
+        } else {
+            Err(io::Error::from_raw_os_error(libc::EEXIST))
+        }
+    }
+
+    pub(crate) fn unregister_buf_ring(this: &Rc<UnsafeCell<MockInner>>, bgid: u16) -> io::Result<()> {
+        let inner = unsafe { &mut *this.get() };
+        if inner.buf_rings.remove(&bgid) {
+            Ok(())
+        } else {
+            Err(io::Error::from_raw_os_error(libc::EINVAL))
+        }
+    }
+
+    /// 和内核一样，每个ring只能注册一组固定缓冲区
+    pub(crate) fn register_buffers(this: &Rc<UnsafeCell<MockInner>>) -> io::Result<()> {
+        let inner = unsafe { &mut *this.get() };
+        if std::mem::replace(&mut inner.buffers, true) {
+            Err(io::Error::from_raw_os_error(libc::EBUSY))
+        } else {
+            Ok(())
+        }
+    }
+
+    pub(crate) fn unregister_buffers(this: &Rc<UnsafeCell<MockInner>>) -> io::Result<()> {
+        let inner = unsafe { &mut *this.get() };
+        if std::mem::replace(&mut inner.buffers, false) {
+            Ok(())
+        } else {
+            Err(io::Error::from_raw_os_error(libc::ENXIO))
+        }
+    }
+}
+
+/// 不访问内核的driver，用于单元测试op的状态机。
+/// 通过 [Inner] 提交的sqe都会被记录下来，测试用 [MockDriver::complete] 安排cqe的结果、
+/// 标志位和顺序，再调用 [Runtime::step](crate::Runtime::step) 推进运行时。
+#[derive(Clone)]
+pub(crate) struct MockDriver {
+    inner: Rc<UnsafeCell<MockInner>>,
+}
+
+impl MockDriver {
+    pub(crate) fn new() -> MockDriver {
+        let eventfd = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC | libc::EFD_NONBLOCK) };
+        assert!(eventfd >= 0, "eventfd: {}", io::Error::last_os_error());
+        let shared_waker = Arc::new(EventWaker::new(eventfd));
+        register_unpark(current_thread_id(), Arc::downgrade(&shared_waker));
+        MockDriver {
+            inner: Rc::new(UnsafeCell::new(MockInner {
+                ops: Ops::new(),
+                submitted: Vec::new(),
+                completions: VecDeque::new(),
+                buf_rings: HashSet::new(),
+                buffers: false,
+                features: Features::mock(),
+                shared_waker,
+                metrics: Metrics::default(),
+            })),
+        }
+    }
+
+    /// 让opcode像在不支持它的内核上一样被拒绝
+    pub(crate) fn set_unsupported(&self, opcode: u8) {
+        unsafe { &mut *self.inner.get() }.features.set_unsupported(opcode);
+    }
+
+    /// 取出上次调用之后提交的sqe
+    pub(crate) fn take_submitted(&self) -> Vec<MockSqe> {
+        std::mem::take(&mut unsafe { &mut *self.inner.get() }.submitted)
+    }
+
+    /// 安排一个cqe，res为负数时表示-errno
+    pub(crate) fn complete(&self, user_data: u64, res: i32, flags: u32) {
+        unsafe { &mut *self.inner.get() }.completions.push_back((user_data, res, flags));
+    }
+
+    /// slab中还没有释放的op数量
+    pub(crate) fn live_ops(&self) -> usize {
+        unsafe { &*self.inner.get() }.ops.len()
+    }
+
+    /// 限制op数量，和 [RuntimeBuilder::max_in_flight](crate::RuntimeBuilder::max_in_flight) 一样
+    pub(crate) fn set_max_in_flight(&self, max: usize) {
+        unsafe { &mut *self.inner.get() }.ops.set_max(max);
+    }
+
+    /// group id是否已经注册了buffer ring
+    pub(crate) fn has_buf_ring(&self, bgid: u16) -> bool {
+        unsafe { &*self.inner.get() }.buf_rings.contains(&bgid)
+    }
+
+    /// 是否注册了固定缓冲区
+    pub(crate) fn has_buffers(&self) -> bool {
+        unsafe { &*self.inner.get() }.buffers
+    }
+}
+
+impl Driver for MockDriver {
+    fn with<R>(&self, f: impl FnOnce() -> R) -> R {
+        let inner = Inner::Mock(self.inner.clone());
+        CURRENT.set(&inner, f)
+    }
+
+    fn submit(&self) -> io::Result<()> {
+        unsafe { &mut *self.inner.get() }.tick();
+        Ok(())
+    }
+
+    fn park(&self) -> io::Result<()> {
+        let inner = unsafe { &mut *self.inner.get() };
+        assert!(!inner.completions.is_empty(), "mock driver would block forever");
+        inner.metrics.parks += 1;
+        inner.tick();
+        Ok(())
+    }
+
+    /// 没有可以交付的事件时立即返回，当作等待超时
+    fn park_timeout(&self, _duration: Duration) -> io::Result<()> {
+        let inner = unsafe { &mut *self.inner.get() };
+        inner.metrics.parks += 1;
+        if inner.tick() == 0 {
+            inner.metrics.timeout_wakeups += 1;
+        }
+        Ok(())
+    }
+
+    fn shutdown_timeout(&self, _timeout: Duration) -> io::Result<usize> {
+        let inner = unsafe { &mut *self.inner.get() };
+        let in_flight = inner.ops.in_flight().collect::<Vec<_>>();
+        for index in in_flight {
+            inner.push_cancel(index);
+        }
+        inner.tick();
+        Ok(inner.ops.in_flight().count())
+    }
+
+    fn features(&self) -> Features {
+        unsafe { &*self.inner.get() }.features
+    }
+
+    fn metrics(&self) -> Metrics {
+        let inner = unsafe { &*self.inner.get() };
+        let mut metrics = inner.metrics.clone();
+        metrics.in_flight = inner.ops.len();
+        metrics
+    }
+
+    type Unpark = MockUnpark;
+
+    fn unpark(&self) -> Self::Unpark {
+        MockUnpark
+    }
+}
+
+impl Drop for MockInner {
+    fn drop(&mut self) {
+        unregister_unpark(current_thread_id(), &Arc::downgrade(&self.shared_waker));
+    }
+}
+
+/// 使用mock driver的运行时，测试通过 [Runtime::enter] 创建op，再用 [Runtime::step] 推进
+pub(crate) fn runtime() -> (Runtime<MockDriver>, MockDriver) {
+    let mock = MockDriver::new();
+    let blocking = BlockingPool::new(1, Duration::from_secs(1));
+    let context = crate::runtime::Context::new(blocking, None);
+    (Runtime::new(context, mock.clone(), Duration::ZERO), mock)
+}
+
+/// mock driver不会阻塞，不需要唤醒
+pub(crate) struct MockUnpark;
+
+impl Unpark for MockUnpark {
+    fn unpark(&self) -> io::Result<()> {
+        Ok(())
+    }
+}
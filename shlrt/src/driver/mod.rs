@@ -1,15 +1,37 @@
-use io_uring;
 use std::io;
 use std::task::{Context, Poll};
 use std::time::Duration;
-use crate::driver::op::{CompletionMeta, Op, OpAble, OpCanceller};
+use std::cell::UnsafeCell;
+use std::os::fd::RawFd;
+use std::rc::Rc;
+use io_uring::squeue::Flags;
+use crate::driver::legacy::{unsupported, LegacyInner};
+use crate::driver::mock::MockInner;
+use crate::driver::op::{CompletionMeta, MultishotOp, Op, OpAble, OpCanceller};
 use crate::driver::uring::UringInner;
-use crate::scoped_thread_local;
+use crate::{runtime, scoped_thread_local};
 
+mod features;
+mod fusion;
+mod metrics;
+pub(crate) mod legacy;
+// mock driver只在测试中构造，其他构建中Inner::Mock不会出现
+#[cfg_attr(not(test), allow(dead_code))]
+pub(crate) mod mock;
 pub(crate) mod op;
 pub(crate) mod shared_fd;
+pub(crate) mod thread;
 mod uring;
 mod util;
+mod waker;
+
+pub use features::Features;
+pub use fusion::FusionDriver;
+pub use legacy::LegacyDriver;
+pub use metrics::Metrics;
+pub use uring::IoUringDriver;
+pub(crate) use waker::EventWaker;
+pub use waker::UnparkHandle;
 
 scoped_thread_local!(pub(crate) static CURRENT: Inner);
 
@@ -23,25 +45,251 @@ pub trait Driver {
     fn park(&self) -> io::Result<()>;
     /// Wait with timeout and process returned events.
     fn park_timeout(&self, duration: Duration) -> io::Result<()>;
+    /// Cancel every in-flight op and wait for their completions until timeout.
+    ///
+    /// Returns the number of ops still outstanding when the timeout elapsed.
+    fn shutdown_timeout(&self, timeout: Duration) -> io::Result<usize>;
+    /// Capabilities of the underlying kernel interface, probed at startup.
+    fn features(&self) -> Features;
+    /// Snapshot of the driver statistics.
+    fn metrics(&self) -> Metrics;
+
+    /// The struct to wake thread from another thread.
+    type Unpark: Unpark;
+    /// Get Unpark handle.
+    fn unpark(&self) -> Self::Unpark;
+}
+
+/// Wake a parked driver from any thread.
+pub trait Unpark: Sync + Send + 'static {
+    /// Unblock a thread that is blocked by the associated driver.
+    ///
+    /// Returns immediately if the driver is not parked.
+    fn unpark(&self) -> io::Result<()>;
+}
+
+impl<T: Unpark + ?Sized> Unpark for Box<T> {
+    fn unpark(&self) -> io::Result<()> {
+        (**self).unpark()
+    }
 }
 
-pub(crate) struct Inner(std::rc::Rc<std::cell::UnsafeCell<UringInner>>);
+/// 当前线程driver的内部数据，op通过它提交和轮询
+pub(crate) enum Inner {
+    Uring(Rc<UnsafeCell<UringInner>>),
+    Legacy(Rc<UnsafeCell<LegacyInner>>),
+    /// 只有单元测试会创建，见 [mock::MockDriver]
+    Mock(Rc<UnsafeCell<MockInner>>),
+}
+
+impl Clone for Inner {
+    fn clone(&self) -> Self {
+        match self {
+            Inner::Uring(this) => Inner::Uring(this.clone()),
+            Inner::Legacy(this) => Inner::Legacy(this.clone()),
+            Inner::Mock(this) => Inner::Mock(this.clone()),
+        }
+    }
+}
+
+/// 在阻塞线程池中执行的op，等待结果的任务被取消时op以ECANCELED完成
+struct BlockingOp {
+    driver: Option<Inner>,
+    index: usize,
+}
+
+impl BlockingOp {
+    fn complete(mut self, result: io::Result<u32>) {
+        if let Some(driver) = self.driver.take() {
+            driver.complete_op(self.index, result);
+        }
+    }
+}
+
+impl Drop for BlockingOp {
+    fn drop(&mut self) {
+        if let Some(driver) = self.driver.take() {
+            driver.complete_op(self.index, Err(io::Error::from_raw_os_error(libc::ECANCELED)));
+        }
+    }
+}
 
 impl Inner {
-    /// 提交op操作
-    fn submit_with<T: OpAble>(&self, data: T) -> io::Result<Op<T>> {
-        UringInner::submit_with_data(&self.0, data)
+    /// 提交op操作，失败时返回data
+    fn submit_with<T: OpAble>(&self, data: T) -> Result<Op<T>, (io::Error, T)> {
+        match self {
+            Inner::Uring(this) => UringInner::submit_with_data(this, data),
+            Inner::Legacy(this) => LegacyInner::submit_with_data(this, data),
+            Inner::Mock(this) => MockInner::submit_with_data(this, data),
+        }
+    }
+
+    /// 内核不支持op的opcode时，把系统调用放到阻塞线程池中执行，op照常等待结果。
+    /// op没有对应的阻塞调用或者不在运行时中时返回err
+    fn blocking_fallback<T: OpAble>(&self, data: T, err: io::Error) -> Result<Op<T>, (io::Error, T)> {
+        let call = match data.blocking_call() {
+            Some(call) if runtime::CURRENT.is_set() => call,
+            _ => return Err((err, data)),
+        };
+        let handle = runtime::CURRENT.with(|cx| cx.blocking.spawn(call));
+        // sqe不会被提交，op由线程池的结果完成
+        let (op, _) = self.prepare_op(data);
+        let blocking = BlockingOp {
+            driver: Some(self.clone()),
+            index: op.index,
+        };
+        crate::spawn(async move {
+            let result = handle.await.unwrap_or_else(|e| Err(e.into()));
+            blocking.complete(result);
+        });
+        Ok(op)
+    }
+
+    /// 提交op操作和链接的超时操作
+    fn submit_with_timeout<T: OpAble>(&self, data: T, timeout: Duration) -> Result<Op<T>, (io::Error, T)> {
+        match self {
+            Inner::Uring(this) => UringInner::submit_with_timeout(this, data, timeout),
+            Inner::Legacy(_) => Err((unsupported(), data)),
+            Inner::Mock(this) => MockInner::submit_with_timeout(this, data, timeout),
+        }
+    }
+
+    /// 创建op并生成sqe，但是不放入sq
+    fn prepare_op<T: OpAble>(&self, mut data: T) -> (Op<T>, io_uring::squeue::Entry) {
+        match self {
+            Inner::Uring(this) => UringInner::prepare_op(this, data),
+            Inner::Legacy(this) => {
+                let entry = data.uring_op();
+                (LegacyInner::prepare_op(this, data), entry)
+            }
+            Inner::Mock(this) => MockInner::prepare_op(this, data),
+        }
+    }
+
+    /// 给除了最后一个以外的sqe加上flag，把它们一起放入sq。opcodes和entries一一对应
+    fn submit_chain(&self, opcodes: &[u8], entries: Vec<io_uring::squeue::Entry>, flag: Flags) -> io::Result<()> {
+        match self {
+            Inner::Uring(this) => UringInner::submit_chain(this, opcodes, entries, flag),
+            Inner::Legacy(_) => Err(unsupported()),
+            Inner::Mock(this) => MockInner::submit_chain(this, opcodes, entries, flag),
+        }
+    }
+
+    /// 直接完成一个没有提交的op
+    fn complete_op(&self, index: usize, result: io::Result<u32>) {
+        match self {
+            Inner::Uring(this) => UringInner::complete_op(this, index, result),
+            Inner::Legacy(this) => LegacyInner::complete_op(this, index, result),
+            Inner::Mock(this) => MockInner::complete_op(this, index, result),
+        }
+    }
+
+    /// 提交multishot操作
+    fn submit_multishot<T: OpAble>(&self, data: T) -> io::Result<MultishotOp<T>> {
+        match self {
+            Inner::Uring(this) => UringInner::submit_multishot(this, data),
+            Inner::Legacy(_) => Err(unsupported()),
+            Inner::Mock(this) => MockInner::submit_multishot(this, data),
+        }
+    }
+
+    fn poll_multishot(&self, index: usize, cx: &mut Context<'_>) -> Poll<Option<CompletionMeta>> {
+        match self {
+            Inner::Uring(this) => UringInner::poll_multishot(this, index, cx),
+            // legacy driver不会创建multishot操作
+            Inner::Legacy(_) => Poll::Ready(None),
+            Inner::Mock(this) => MockInner::poll_multishot(this, index, cx),
+        }
+    }
+
+    fn rearm_multishot<T: OpAble>(&self, index: usize, data: &mut T) -> io::Result<()> {
+        match self {
+            Inner::Uring(this) => UringInner::rearm_multishot(this, index, data),
+            Inner::Legacy(_) => Err(unsupported()),
+            Inner::Mock(this) => MockInner::rearm_multishot(this, index, data),
+        }
+    }
+
+    /// 注册provided buffer ring
+    pub(crate) fn register_buf_ring(&self, ring_addr: u64, entries: u16, bgid: u16) -> io::Result<()> {
+        match self {
+            Inner::Uring(this) => UringInner::register_buf_ring(this, ring_addr, entries, bgid),
+            Inner::Legacy(_) => Err(unsupported()),
+            Inner::Mock(this) => MockInner::register_buf_ring(this, bgid),
+        }
+    }
+
+    pub(crate) fn unregister_buf_ring(&self, bgid: u16) -> io::Result<()> {
+        match self {
+            Inner::Uring(this) => UringInner::unregister_buf_ring(this, bgid),
+            Inner::Legacy(_) => Err(unsupported()),
+            Inner::Mock(this) => MockInner::unregister_buf_ring(this, bgid),
+        }
+    }
+
+    /// 注册固定缓冲区
+    pub(crate) fn register_buffers(&self, iovecs: &[libc::iovec]) -> io::Result<()> {
+        match self {
+            Inner::Uring(this) => UringInner::register_buffers(this, iovecs),
+            Inner::Legacy(_) => Err(unsupported()),
+            Inner::Mock(this) => MockInner::register_buffers(this),
+        }
+    }
+
+    pub(crate) fn unregister_buffers(&self) -> io::Result<()> {
+        match self {
+            Inner::Uring(this) => UringInner::unregister_buffers(this),
+            Inner::Legacy(_) => Err(unsupported()),
+            Inner::Mock(this) => MockInner::unregister_buffers(this),
+        }
+    }
+
+    /// 从固定文件表中移除一个槽位
+    pub(crate) fn unregister_file(&self, slot: u32) -> io::Result<()> {
+        match self {
+            Inner::Uring(this) => UringInner::unregister_file(this, slot),
+            Inner::Legacy(_) => Err(unsupported()),
+            Inner::Mock(_) => Ok(()),
+        }
+    }
+
+    /// 普通fd不再使用，legacy driver需要把它从epoll中移除
+    pub(crate) fn deregister_fd(&self, fd: RawFd) {
+        if let Inner::Legacy(this) = self {
+            LegacyInner::deregister_fd(this, fd);
+        }
+    }
+
+    /// 等待可以提交新的op，只有io_uring driver会限制op的数量
+    fn poll_capacity(&self, cx: &mut Context<'_>) -> Poll<()> {
+        match self {
+            Inner::Uring(this) => UringInner::poll_capacity(this, cx),
+            Inner::Legacy(_) => Poll::Ready(()),
+            Inner::Mock(this) => MockInner::poll_capacity(this, cx),
+        }
     }
 
     fn poll_op<T: OpAble>(&self, data: &mut T, index: usize, cx: &mut Context<'_>) -> Poll<CompletionMeta> {
-        UringInner::poll_op(&self.0, index, cx)
+        match self {
+            Inner::Uring(this) => UringInner::poll_op(this, index, cx),
+            Inner::Legacy(this) => LegacyInner::poll_op(this, data, index, cx),
+            Inner::Mock(this) => MockInner::poll_op(this, index, cx),
+        }
     }
 
     fn drop_op<T:'static>(&self, index: usize, data: &mut Option<T>) {
-        UringInner::drop_op(&self.0, index, data);
+        match self {
+            Inner::Uring(this) => UringInner::drop_op(this, index, data),
+            Inner::Legacy(this) => LegacyInner::drop_op(this, index),
+            Inner::Mock(this) => MockInner::drop_op(this, index, data),
+        }
     }
 
-    unsafe fn cancel_op(&self, op_canceller: OpCanceller) {
-        UringInner::cancel_op(&self.0, op_canceller.index);
+    pub(crate) unsafe fn cancel_op(&self, op_canceller: OpCanceller) {
+        match self {
+            Inner::Uring(this) => UringInner::cancel_op(this, op_canceller.index),
+            Inner::Legacy(this) => LegacyInner::cancel_op(this, op_canceller.index),
+            Inner::Mock(this) => MockInner::cancel_op(this, op_canceller.index),
+        }
     }
-}
\ No newline at end of file
+}
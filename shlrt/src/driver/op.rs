@@ -1,15 +1,56 @@
-use std::future::Future;
+use std::future::{poll_fn, Future};
 use std::io;
+use std::os::fd::RawFd;
 use std::pin::Pin;
 use std::task::{Context, Poll, ready};
+use std::time::Duration;
+use io_uring::types::Timespec;
 use crate::driver;
 use crate::driver::Inner;
+use crate::driver::legacy::{unsupported, Direction};
+use crate::driver::shared_fd::FdKind;
+use crate::io::{CancelHandle, CancelRegistration};
+
+/// 按照SharedFd是普通fd还是固定槽位，分别用types::Fd或types::Fixed构造sqe
+macro_rules! with_fd {
+    ($shared:expr, $fd:ident => $build:expr) => {
+        match $shared.kind() {
+            $crate::driver::shared_fd::FdKind::Raw(raw) => {
+                let $fd = io_uring::types::Fd(raw);
+                $build
+            }
+            $crate::driver::shared_fd::FdKind::Fixed(slot) => {
+                let $fd = io_uring::types::Fixed(slot);
+                $build
+            }
+        }
+    };
+}
 
 mod accept;
+mod chain;
 mod close;
 mod connect;
 mod fsync;
+mod multishot;
 mod open;
+mod read;
+mod recv;
+mod trace;
+mod write;
+
+pub(crate) use accept::{to_socket_addr, AcceptMulti};
+pub use chain::{Chain, Link};
+pub(crate) use close::Close;
+pub(crate) use multishot::MultishotOp;
+pub(crate) use fsync::Fsync;
+pub(crate) use read::Read;
+pub(crate) use recv::RecvMultiBufRing;
+pub(crate) use trace::OpTrace;
+pub(crate) use write::Write;
+
+/// 在阻塞线程池中执行的系统调用，不能借用op中的数据
+pub(crate) type BlockingCall = Box<dyn FnOnce() -> io::Result<u32> + Send>;
 
 /// 封装io_uring的operation
 pub(crate) struct Op<T: 'static> {
@@ -19,6 +60,12 @@ pub(crate) struct Op<T: 'static> {
     pub(super) index: usize,
     // op操作包含的data信息
     pub(super) data: Option<T>,
+    // 链接超时的时间，内核在提交时读取，需要和op活得一样久
+    pub(super) timeout: Option<Box<Timespec>>,
+    // 开启tracing feature时记录op的span
+    pub(super) trace: OpTrace,
+    // 通过CancelHandle注册的取消
+    pub(super) cancel: Option<CancelRegistration>,
 }
 
 /// 操作完成时的元信息
@@ -39,6 +86,36 @@ pub(crate) struct Completion<T> {
 pub(crate) trait OpAble {
     /// 创建io_uring操作的SQE
     fn uring_op(&mut self) -> io_uring::squeue::Entry;
+
+    /// [OpAble::uring_op] 生成的sqe的opcode，检查内核支持和统计提交数量时使用
+    fn opcode(&mut self) -> u8;
+
+    /// op使用的fd，记录在tracing span中
+    fn fd(&self) -> Option<FdKind> {
+        None
+    }
+
+    /// multishot操作的结果代表需要释放的资源（例如accept得到的fd）时，
+    /// 返回释放它的函数，op被drop时还没有被取走的结果由它释放
+    fn release(&self) -> Option<fn(u32)> {
+        None
+    }
+
+    /// 内核不支持这个opcode时，在阻塞线程池中执行的系统调用。
+    /// 返回None时op以ErrorKind::Unsupported失败
+    fn blocking_call(&self) -> Option<BlockingCall> {
+        None
+    }
+
+    /// legacy driver中op等待就绪的fd和方向，返回None时系统调用总是直接执行
+    fn legacy_interest(&self) -> Option<(Direction, RawFd)> {
+        None
+    }
+
+    /// legacy driver中执行的非阻塞系统调用，fd没有就绪时返回WouldBlock
+    fn legacy_call(&mut self) -> io::Result<u32> {
+        Err(unsupported())
+    }
 }
 
 impl<T> Op<T> {
@@ -46,15 +123,31 @@ impl<T> Op<T> {
     pub(super) fn submit_with(data: T) -> io::Result<Op<T>>
         where
             T: OpAble,
+    {
+        Self::submit_or_return(data).map_err(|(e, _)| e)
+    }
+
+    /// 提交OP操作，失败时把data还给调用者，用于需要归还缓冲区的操作
+    pub(super) fn submit_or_return(data: T) -> Result<Op<T>, (io::Error, T)>
+        where
+            T: OpAble,
     {
         driver::CURRENT.with(|this| this.submit_with(data))
     }
 
+    /// 提交OP操作，并链接一个超时操作，超时后op以ErrorKind::TimedOut完成。失败时把data还给调用者
+    pub(super) fn submit_with_timeout(data: T, timeout: Duration) -> Result<Op<T>, (io::Error, T)>
+        where
+            T: OpAble,
+    {
+        driver::CURRENT.with(|this| this.submit_with_timeout(data, timeout))
+    }
+
     pub(super) fn try_submit_with(data: T) -> io::Result<Op<T>>
         where
             T: OpAble,
     {
-        /// 检查当前的TLS是否被设置
+        // 检查当前的TLS是否被设置
         if driver::CURRENT.is_set() {
             Self::submit_with(data)
         } else {
@@ -66,10 +159,22 @@ impl<T> Op<T> {
         where
             T: OpAble
     {
-        return OpCanceller{
+        OpCanceller{
             index: self.index,
         }
     }
+
+    /// 把op注册到取消句柄上，取消后op以ErrorKind::Interrupted完成
+    pub(crate) fn cancelable(mut self, handle: &CancelHandle) -> Op<T>
+        where
+            T: OpAble
+    {
+        // 同步完成的op不需要取消
+        if self.index != usize::MAX {
+            self.cancel = Some(handle.register(self.op_canceller()));
+        }
+        self
+    }
 }
 
 impl<T> Future for Op<T>
@@ -77,17 +182,47 @@ where T: Unpin + OpAble + 'static
 {
     type Output = Completion<T>;
 
-    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let this = &mut *self;
+        let _entered = this.trace.enter();
         let data_mut = this.data.as_mut().expect("unexpected operation state");
-        let meta = ready!(this.driver.0.poll_op::<T>(data_mut, this.index, cx));
+        let mut meta = ready!(this.driver.poll_op(data_mut, this.index, cx));
+        if let Err(e) = &meta.result {
+            // 用户主动取消，和超时区分开。超时在driver中已经转换为ETIMEDOUT
+            if e.raw_os_error() == Some(libc::ECANCELED)
+                && this.cancel.as_ref().is_some_and(|cancel| cancel.is_canceled())
+            {
+                meta.result = Err(io::Error::new(io::ErrorKind::Interrupted, "operation was canceled"));
+            }
+        }
 
+        this.trace.completed(&meta.result);
+        this.cancel = None;
         this.index = usize::MAX;
         let data = this.data.take().expect("unexpected operation state");
         Poll::Ready(Completion{data, meta})
     }
 }
 
+impl<T: 'static> Drop for Op<T> {
+    fn drop(&mut self) {
+        // 还在运行的op会被取消，内核可能还在访问data中的缓冲区、地址和链接超时的timespec，
+        // 它们一起交给driver保管，cqe到达后才释放
+        let mut retained = Some((self.data.take(), self.timeout.take()));
+        self.driver.drop_op(self.index, &mut retained);
+    }
+}
+
+/// 等待可以提交新的op。op数量达到 [max_in_flight](crate::RuntimeBuilder::max_in_flight) 时挂起，
+/// 直到有op结束释放位置
+///
+/// # Panics
+///
+/// 不在运行时中调用时会panic
+pub(crate) async fn wait_for_capacity() {
+    poll_fn(|cx| driver::CURRENT.with(|inner| inner.poll_capacity(cx))).await
+}
+
 #[derive(Debug, Eq, PartialEq, Clone, Hash)]
 pub(crate) struct OpCanceller {
     pub(super) index: usize,
@@ -1,38 +1,158 @@
-use std::intrinsics::size_of;
+use std::mem::size_of;
 use std::io;
-use crate::driver::shared_fd::SharedFd;
+use std::os::fd::RawFd;
+use std::time::Duration;
+use crate::driver::shared_fd::{FdKind, SharedFd};
 use std::mem::MaybeUninit;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
 use io_uring::{opcode, types};
 use io_uring::squeue::Entry;
-use crate::driver::op::{Op, OpAble};
+use crate::driver::legacy::{cvt, unsupported, Direction};
+use crate::driver::op::{MultishotOp, Op, OpAble};
 
 /// accept操作封装
 pub(crate) struct Accept {
     pub(crate) fd: SharedFd,
     pub(crate) addr: Box<(MaybeUninit<libc::sockaddr_storage>, libc::socklen_t)>,
+    /// 返回直接描述符，结果是固定文件表中的槽位
+    direct: bool,
 }
 
 impl Op<Accept> {
     /// 封装accept操作
-    fn accept(fd:&SharedFd) -> io::Result<Self> {
+    pub(crate) fn accept(fd: &SharedFd) -> io::Result<Self> {
+        Op::submit_with(Accept::new(fd))
+    }
+
+    /// 封装accept操作，新连接放入固定文件表，需要先注册固定文件表
+    pub(crate) fn accept_direct(fd: &SharedFd) -> io::Result<Self> {
+        let mut accept = Accept::new(fd);
+        accept.direct = true;
+        Op::submit_with(accept)
+    }
+
+    /// 封装accept操作，timeout内没有连接时以ErrorKind::TimedOut完成
+    pub(crate) fn accept_timeout(fd: &SharedFd, timeout: Duration) -> io::Result<Self> {
+        Op::submit_with_timeout(Accept::new(fd), timeout).map_err(|(e, _)| e)
+    }
+
+    /// 等待新连接，返回新连接的fd和对端地址，直接描述符的结果是固定文件表中的槽位
+    pub(crate) async fn accepted(self) -> io::Result<(SharedFd, SocketAddr)> {
+        let complete = self.await;
+        let result = complete.meta.result?;
+        let fd = if complete.data.direct {
+            SharedFd::new_fixed(result)
+        } else {
+            SharedFd::new(result as RawFd)?
+        };
+        // 只有accept成功后才会读取，此时内核已经写入了地址
+        let addr = to_socket_addr(unsafe { complete.data.addr.0.assume_init_ref() })?;
+        Ok((fd, addr))
+    }
+}
+
+impl Accept {
+    fn new(fd: &SharedFd) -> Accept {
         let addr = Box::new((
             MaybeUninit::uninit(),
             size_of::<libc::sockaddr_storage>() as libc::socklen_t),
         );
 
-        Op::submit_with(Accept{
+        Accept{
             fd: fd.clone(),
             addr,
-        })
+            direct: false,
+        }
     }
 }
 
 impl OpAble for Accept {
     fn uring_op(&mut self) -> Entry {
-        opcode::Accept::new(
-            types::Fd(self.fd.raw_fd()),
+        let file_index = self.direct.then(types::DestinationSlot::auto_target);
+        with_fd!(self.fd, fd => opcode::Accept::new(
+            fd,
             self.addr.0.as_mut_ptr() as *mut libc::sockaddr,
             &mut self.addr.1,
-        ).build()
+        ).file_index(file_index).build())
     }
-}
\ No newline at end of file
+
+    fn opcode(&mut self) -> u8 {
+        opcode::Accept::CODE
+    }
+
+    fn fd(&self) -> Option<FdKind> {
+        Some(self.fd.kind())
+    }
+
+    fn legacy_interest(&self) -> Option<(Direction, RawFd)> {
+        Some((Direction::Read, self.fd.raw_fd()))
+    }
+
+    fn legacy_call(&mut self) -> io::Result<u32> {
+        if self.direct {
+            return Err(unsupported());
+        }
+        // legacy driver只能等待非阻塞fd的就绪事件
+        cvt(unsafe {
+            libc::accept4(
+                self.fd.raw_fd(),
+                self.addr.0.as_mut_ptr() as *mut libc::sockaddr,
+                &mut self.addr.1,
+                libc::SOCK_NONBLOCK,
+            )
+        } as i64)
+    }
+}
+/// 把内核写入的地址转换为SocketAddr
+pub(crate) fn to_socket_addr(storage: &libc::sockaddr_storage) -> io::Result<SocketAddr> {
+    match storage.ss_family as libc::c_int {
+        libc::AF_INET => {
+            let addr = unsafe { &*(storage as *const _ as *const libc::sockaddr_in) };
+            let ip = Ipv4Addr::from(addr.sin_addr.s_addr.to_ne_bytes());
+            Ok(SocketAddr::V4(SocketAddrV4::new(ip, u16::from_be(addr.sin_port))))
+        }
+        libc::AF_INET6 => {
+            let addr = unsafe { &*(storage as *const _ as *const libc::sockaddr_in6) };
+            let ip = Ipv6Addr::from(addr.sin6_addr.s6_addr);
+            Ok(SocketAddr::V6(SocketAddrV6::new(
+                ip,
+                u16::from_be(addr.sin6_port),
+                addr.sin6_flowinfo,
+                addr.sin6_scope_id,
+            )))
+        }
+        _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "unsupported address family")),
+    }
+}
+
+/// multishot accept，一个sqe接收多个连接，每个结果都是新连接的fd
+pub(crate) struct AcceptMulti {
+    fd: SharedFd,
+}
+
+impl MultishotOp<AcceptMulti> {
+    pub(crate) fn accept_multi(fd: &SharedFd) -> io::Result<Self> {
+        MultishotOp::submit_with(AcceptMulti { fd: fd.clone() })
+    }
+}
+
+impl OpAble for AcceptMulti {
+    fn uring_op(&mut self) -> Entry {
+        with_fd!(self.fd, fd => opcode::AcceptMulti::new(fd).build())
+    }
+
+    fn opcode(&mut self) -> u8 {
+        opcode::AcceptMulti::CODE
+    }
+
+    fn fd(&self) -> Option<FdKind> {
+        Some(self.fd.kind())
+    }
+
+    /// 没有被取走的连接直接关闭
+    fn release(&self) -> Option<fn(u32)> {
+        Some(|fd| unsafe {
+            libc::close(fd as RawFd);
+        })
+    }
+}
@@ -0,0 +1,278 @@
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use io_uring::squeue::{Entry, Flags};
+use crate::buf::{IoBuf, IoBufMut};
+use crate::driver;
+use crate::driver::Inner;
+use crate::driver::op::{Close, Fsync, Op, OpAble, Read, Write};
+use crate::fs::File;
+use crate::BufResult;
+
+/// 通过IOSQE_IO_LINK串起来的一组op，内核按顺序执行，只需要一次提交。
+/// 每个op都有自己的Lifecycle，分别await返回的 [Link] 得到各自的结果。
+/// 前一个op失败时后面的op以ECANCELED完成，使用 [Chain::hard_link] 时不受影响。
+///
+/// ```ignore
+/// let mut chain = Chain::new();
+/// let write = chain.write_at(&file, buf, 0);
+/// let sync = chain.sync_data(&file);
+/// let close = chain.close(file);
+/// chain.submit()?;
+/// let (written, buf) = write.await;
+/// sync.await?;
+/// close.await?;
+/// ```
+pub struct Chain {
+    driver: Inner,
+    /// 还没有放入sq的sqe
+    entries: Vec<Entry>,
+    /// sqe的opcode
+    opcodes: Vec<u8>,
+    /// sqe对应的op索引
+    indexes: Vec<usize>,
+    hard_link: bool,
+}
+
+/// 链中的一个op，[Chain::submit] 之后await得到这个op的结果。
+/// 没有提交的链被drop时以ECANCELED完成，提交后drop会取消这个op
+pub struct Link<T> {
+    future: Pin<Box<dyn Future<Output = T>>>,
+}
+
+impl<T> Link<T> {
+    fn new(future: impl Future<Output = T> + 'static) -> Link<T> {
+        Link {
+            future: Box::pin(future),
+        }
+    }
+}
+
+impl<T> Future for Link<T> {
+    type Output = T;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        self.future.as_mut().poll(cx)
+    }
+}
+
+impl Chain {
+    /// # Panics
+    ///
+    /// 不在运行时中调用时会panic
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Chain {
+        Chain {
+            driver: driver::CURRENT.with(|inner| inner.clone()),
+            entries: Vec::new(),
+            opcodes: Vec::new(),
+            indexes: Vec::new(),
+            hard_link: false,
+        }
+    }
+
+    /// 使用IOSQE_IO_HARDLINK，前一个op失败时也继续执行后面的op
+    #[must_use]
+    pub fn hard_link(mut self) -> Self {
+        self.hard_link = true;
+        self
+    }
+
+    /// 在链的末尾加入一个op，返回的op在 [Chain::submit] 之后才会被执行
+    pub(crate) fn push<T: OpAble>(&mut self, mut data: T) -> Op<T> {
+        self.opcodes.push(data.opcode());
+        let (op, entry) = self.driver.prepare_op(data);
+        self.entries.push(entry);
+        self.indexes.push(op.index);
+        op
+    }
+
+    /// 加入 [File::read_at]
+    pub fn read_at<T: IoBufMut>(&mut self, file: &File, buf: T, pos: u64) -> Link<BufResult<usize, T>> {
+        Link::new(self.push(Read::new(file.shared_fd(), buf, pos)).read())
+    }
+
+    /// 加入 [File::write_at]
+    pub fn write_at<T: IoBuf>(&mut self, file: &File, buf: T, pos: u64) -> Link<BufResult<usize, T>> {
+        Link::new(self.push(Write::new(file.shared_fd(), buf, pos)).write())
+    }
+
+    /// 加入 [File::sync_all]
+    pub fn sync_all(&mut self, file: &File) -> Link<io::Result<()>> {
+        let op = self.push(Fsync::new(file.shared_fd(), false));
+        Link::new(async move { op.await.meta.result.map(|_| ()) })
+    }
+
+    /// 加入 [File::sync_data]
+    pub fn sync_data(&mut self, file: &File) -> Link<io::Result<()>> {
+        let op = self.push(Fsync::new(file.shared_fd(), true));
+        Link::new(async move { op.await.meta.result.map(|_| ()) })
+    }
+
+    /// 加入关闭文件的op，一般放在链的末尾
+    pub fn close(&mut self, file: File) -> Link<io::Result<()>> {
+        Link::new(self.push(Close::new(file.shared_fd())).closed())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// 把整条链一次性放入sq，sq中没有足够的空间时会先提交已有的sqe。
+    /// 失败时（例如legacy driver不支持链接）所有op以ECANCELED完成
+    pub fn submit(mut self) -> io::Result<()> {
+        let flag = if self.hard_link {
+            Flags::IO_HARDLINK
+        } else {
+            Flags::IO_LINK
+        };
+        let entries = std::mem::take(&mut self.entries);
+        self.driver.submit_chain(&self.opcodes, entries, flag)?;
+        self.indexes.clear();
+        Ok(())
+    }
+}
+
+impl Drop for Chain {
+    fn drop(&mut self) {
+        // 没有提交的op不会再有cqe，以ECANCELED结束
+        for index in self.indexes.drain(..) {
+            self.driver.complete_op(index, Err(io::Error::from_raw_os_error(libc::ECANCELED)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use io_uring::opcode;
+    use io_uring::squeue::Flags;
+
+    use super::Chain;
+    use crate::driver::mock::runtime;
+    use crate::driver::shared_fd::SharedFd;
+    use crate::fs::File;
+
+    fn file() -> File {
+        File::from_shared_fd(SharedFd::new_without_register(-1))
+    }
+
+    #[test]
+    fn link_flags() {
+        let (rt, mock) = runtime();
+        let links = rt.enter(|| {
+            let mut chain = Chain::new();
+            let write = chain.write_at(&file(), vec![1u8; 4], 0);
+            let sync = chain.sync_data(&file());
+            let close = chain.close(file());
+            chain.submit().unwrap();
+            (write, sync, close)
+        });
+        let sqes = mock.take_submitted();
+        let opcodes = sqes.iter().map(|sqe| sqe.opcode).collect::<Vec<_>>();
+        assert_eq!(opcodes, [opcode::Write::CODE, opcode::Fsync::CODE, opcode::Close::CODE]);
+        let link = Flags::IO_LINK.bits();
+        assert_eq!(sqes.iter().map(|sqe| sqe.flags).collect::<Vec<_>>(), [link, link, 0]);
+
+        let hard_links = rt.enter(|| {
+            let mut chain = Chain::new().hard_link();
+            let write = chain.write_at(&file(), vec![1u8; 4], 0);
+            let sync = chain.sync_all(&file());
+            chain.submit().unwrap();
+            (write, sync)
+        });
+        let sqes = mock.take_submitted();
+        assert_eq!(sqes[0].flags, Flags::IO_HARDLINK.bits());
+        assert_eq!(sqes[1].flags, 0);
+        drop((links, hard_links));
+    }
+
+    #[test]
+    fn failed_link_cancels_the_rest() {
+        let (rt, mock) = runtime();
+        let results = Rc::new(RefCell::new(None));
+        let output = results.clone();
+        rt.enter(|| {
+            crate::spawn(async move {
+                let mut chain = Chain::new();
+                let write = chain.write_at(&file(), vec![1u8; 4], 0);
+                let sync = chain.sync_data(&file());
+                let close = chain.close(file());
+                chain.submit().unwrap();
+                let (written, buf) = write.await;
+                *output.borrow_mut() = Some((written, buf, sync.await, close.await));
+            })
+        });
+        rt.step();
+        let sqes = mock.take_submitted();
+        // 第一个op失败，内核以ECANCELED结束链上后面的op
+        mock.complete(sqes[0].user_data, -libc::EIO, 0);
+        mock.complete(sqes[1].user_data, -libc::ECANCELED, 0);
+        mock.complete(sqes[2].user_data, -libc::ECANCELED, 0);
+        rt.step();
+
+        let (written, buf, sync, close) = results.borrow_mut().take().unwrap();
+        assert_eq!(written.unwrap_err().raw_os_error(), Some(libc::EIO));
+        assert_eq!(buf, [1u8; 4]);
+        assert_eq!(sync.unwrap_err().raw_os_error(), Some(libc::ECANCELED));
+        assert_eq!(close.unwrap_err().raw_os_error(), Some(libc::ECANCELED));
+        assert_eq!(mock.live_ops(), 0);
+    }
+
+    #[test]
+    fn dropped_link_cancels_its_op() {
+        let (rt, mock) = runtime();
+        let sync = rt.enter(|| {
+            let mut chain = Chain::new();
+            let write = chain.write_at(&file(), vec![1u8; 4], 0);
+            let sync = chain.sync_data(&file());
+            chain.submit().unwrap();
+            drop(write);
+            sync
+        });
+        let sqes = mock.take_submitted();
+        assert_eq!(sqes.len(), 3);
+        assert_eq!(sqes[2].opcode, opcode::AsyncCancel::CODE);
+
+        mock.complete(sqes[0].user_data, -libc::ECANCELED, 0);
+        mock.complete(sqes[1].user_data, -libc::ECANCELED, 0);
+        rt.step();
+        rt.enter(|| {
+            crate::spawn(async move {
+                assert_eq!(sync.await.unwrap_err().raw_os_error(), Some(libc::ECANCELED));
+            })
+        });
+        rt.step();
+        assert_eq!(mock.live_ops(), 0);
+    }
+
+    #[test]
+    fn drop_before_submit() {
+        let (rt, mock) = runtime();
+        let (write, sync) = rt.enter(|| {
+            let mut chain = Chain::new();
+            let write = chain.write_at(&file(), vec![1u8; 4], 0);
+            let sync = chain.sync_data(&file());
+            (write, sync)
+        });
+        // 链没有提交，op直接以ECANCELED结束，缓冲区照常返回
+        assert!(mock.take_submitted().is_empty());
+        rt.enter(|| {
+            crate::spawn(async move {
+                let (res, buf) = write.await;
+                assert_eq!(res.unwrap_err().raw_os_error(), Some(libc::ECANCELED));
+                assert_eq!(buf.len(), 4);
+                assert_eq!(sync.await.unwrap_err().raw_os_error(), Some(libc::ECANCELED));
+            })
+        });
+        rt.step();
+        assert_eq!(mock.live_ops(), 0);
+    }
+}
@@ -1,21 +1,61 @@
 use std::io;
-use std::os::unix::io::RawFd;
-use io_uring::{opcode, types};
+use io_uring::opcode;
 use io_uring::squeue::Entry;
-use crate::driver::op::{Op, OpAble};
+use crate::driver::legacy::cvt;
+use crate::driver::op::{BlockingCall, Op, OpAble};
+use crate::driver::shared_fd::{FdKind, SharedFd};
 
-struct Close {
-    fd: RawFd
+pub(crate) struct Close {
+    fd: SharedFd,
+}
+
+impl Close {
+    /// fd交给这个op关闭，SharedFd被drop时不再关闭它
+    pub(crate) fn new(fd: &SharedFd) -> Close {
+        fd.set_closed(true);
+        Close { fd: fd.clone() }
+    }
 }
 
 impl Op<Close> {
-    fn close(fd: RawFd) -> io::Result<Op<Close>> {
-        Self::try_submit_with(Close{fd})
+    pub(crate) fn close(fd: &SharedFd) -> io::Result<Op<Close>> {
+        Self::try_submit_with(Close::new(fd)).inspect_err(|_| fd.set_closed(false))
+    }
+
+    /// 等待关闭完成。op没有被执行（例如链中前面的op失败）时fd仍然由SharedFd负责关闭
+    pub(crate) async fn closed(self) -> io::Result<()> {
+        let complete = self.await;
+        if let Err(e) = &complete.meta.result {
+            if e.raw_os_error() == Some(libc::ECANCELED) {
+                complete.data.fd.set_closed(false);
+            }
+        }
+        complete.meta.result.map(|_| ())
     }
 }
 
 impl OpAble for Close {
     fn uring_op(&mut self) -> Entry {
-        opcode::Close::new(types::Fd(self.fd)).build()
+        // 直接描述符关闭时从固定文件表中移除
+        with_fd!(self.fd, fd => opcode::Close::new(fd).build())
+    }
+
+    fn opcode(&mut self) -> u8 {
+        opcode::Close::CODE
     }
-}
\ No newline at end of file
+
+    fn fd(&self) -> Option<FdKind> {
+        Some(self.fd.kind())
+    }
+
+    fn legacy_call(&mut self) -> io::Result<u32> {
+        cvt(unsafe { libc::close(self.fd.raw_fd()) } as i64)
+    }
+
+    fn blocking_call(&self) -> Option<BlockingCall> {
+        match self.fd.kind() {
+            FdKind::Raw(fd) => Some(Box::new(move || cvt(unsafe { libc::close(fd) } as i64))),
+            FdKind::Fixed(_) => None,
+        }
+    }
+}
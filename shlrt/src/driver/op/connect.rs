@@ -1,10 +1,13 @@
 use std::io;
+use std::os::fd::RawFd;
+use std::time::Duration;
 use core::net::SocketAddr;
-use io_uring::{opcode, types};
+use io_uring::opcode;
+use crate::driver::legacy::{cvt, Direction};
 use crate::driver::op::{Op, OpAble};
-use crate::driver::shared_fd::SharedFd;
+use crate::driver::shared_fd::{FdKind, SharedFd};
 
-struct Connect {
+pub(crate) struct Connect {
     fd: SharedFd,
     socket_addr: Box<libc::sockaddr_in>,
     socket_addr_len: libc::socklen_t,
@@ -23,15 +26,59 @@ impl Op<Connect> {
             socket_addr_len: raw_addr_length,
         })
     }
+
+    /// 封装connect操作，timeout内没有连上时以ErrorKind::TimedOut完成
+    pub(crate) fn connect_timeout(
+        socket: SharedFd,
+        addr: SocketAddr,
+        timeout: Duration,
+    ) -> io::Result<Op<Connect>> {
+        let (raw_addr, raw_addr_length) = socket_addr(&addr);
+        Op::submit_with_timeout(Connect {
+            fd: socket,
+            socket_addr: Box::new(raw_addr),
+            socket_addr_len: raw_addr_length,
+        }, timeout).map_err(|(e, _)| e)
+    }
 }
 
 impl OpAble for Connect {
     fn uring_op(&mut self) -> io_uring::squeue::Entry {
-        opcode::Connect::new(
-            types::Fd(self.fd.raw_fd()),
-            self.socket_addr.as_ptr(),
+        with_fd!(self.fd, fd => opcode::Connect::new(
+            fd,
+            &*self.socket_addr as *const libc::sockaddr_in as *const libc::sockaddr,
             self.socket_addr_len,
-        ).build()
+        ).build())
+    }
+
+    fn opcode(&mut self) -> u8 {
+        opcode::Connect::CODE
+    }
+
+    fn fd(&self) -> Option<FdKind> {
+        Some(self.fd.kind())
+    }
+
+    fn legacy_interest(&self) -> Option<(Direction, RawFd)> {
+        Some((Direction::Write, self.fd.raw_fd()))
+    }
+
+    fn legacy_call(&mut self) -> io::Result<u32> {
+        let ret = unsafe {
+            libc::connect(
+                self.fd.raw_fd(),
+                &*self.socket_addr as *const libc::sockaddr_in as *const libc::sockaddr,
+                self.socket_addr_len,
+            )
+        };
+        match cvt(ret as i64) {
+            // 非阻塞connect在后台进行，可写后再调用一次得到结果
+            Err(e) if matches!(e.raw_os_error(), Some(libc::EINPROGRESS) | Some(libc::EALREADY)) => {
+                Err(io::ErrorKind::WouldBlock.into())
+            }
+            Err(e) if e.raw_os_error() == Some(libc::EISCONN) => Ok(0),
+            res => res,
+        }
     }
 }
 
@@ -49,7 +96,7 @@ pub(crate) fn socket_addr(addr: &SocketAddr) -> (libc::sockaddr_in, libc::sockle
                 sin_zero: [0; 8],
             };
 
-            return (sockaddr_in, std::mem::size_of::<libc::sockaddr_in>() as libc::socklen_t)
+            (sockaddr_in, std::mem::size_of::<libc::sockaddr_in>() as libc::socklen_t)
         }
         _ => {
             panic!("Only support IPv4");
@@ -1,36 +1,74 @@
 use std::io;
+use std::os::fd::RawFd;
 use io_uring::{opcode, types};
 use io_uring::squeue::Entry;
-use crate::driver::op::{Op, OpAble};
-use crate::driver::shared_fd::SharedFd;
+use crate::driver::legacy::cvt;
+use crate::driver::op::{BlockingCall, Op, OpAble};
+use crate::driver::shared_fd::{FdKind, SharedFd};
 
 pub(crate) struct Fsync {
     fd: SharedFd,
     data_sync: bool,
 }
 
+impl Fsync {
+    /// data_sync为true时只同步数据，对应fdatasync
+    pub(crate) fn new(fd: &SharedFd, data_sync: bool) -> Fsync {
+        Fsync {
+            fd: fd.clone(),
+            data_sync,
+        }
+    }
+}
+
 impl Op<Fsync> {
     pub(crate) fn fsync(fd: &SharedFd) -> io::Result<Op<Fsync>> {
-        Op::submit_with(Fsync{
-            fd: fd.clone(),
-            data_sync: false,
-        })
+        Op::submit_with(Fsync::new(fd, false))
     }
 
-    fn data_sync(fd: &SharedFd) -> io::Result<Op<Fsync>> {
-        Op::submit_with(Fsync{
-            fd: fd.clone(),
-            data_sync: true,
-        })
+    pub(crate) fn datasync(fd: &SharedFd) -> io::Result<Op<Fsync>> {
+        Op::submit_with(Fsync::new(fd, true))
     }
 }
 
 impl OpAble for Fsync {
     fn uring_op(&mut self) -> Entry {
-        let mut opcode = opcode::Fsync::new(types::Fd(self.fd.raw_fd()));
-        if self.data_sync {
-            opcode = opcode.flags(types::FsyncFlags::DATASYNC);
+        let flags = if self.data_sync {
+            types::FsyncFlags::DATASYNC
+        } else {
+            types::FsyncFlags::empty()
+        };
+        with_fd!(self.fd, fd => opcode::Fsync::new(fd).flags(flags).build())
+    }
+
+    fn opcode(&mut self) -> u8 {
+        opcode::Fsync::CODE
+    }
+
+    fn fd(&self) -> Option<FdKind> {
+        Some(self.fd.kind())
+    }
+
+    fn legacy_call(&mut self) -> io::Result<u32> {
+        fsync(self.fd.raw_fd(), self.data_sync)
+    }
+
+    fn blocking_call(&self) -> Option<BlockingCall> {
+        match self.fd.kind() {
+            FdKind::Raw(fd) => {
+                let data_sync = self.data_sync;
+                Some(Box::new(move || fsync(fd, data_sync)))
+            }
+            FdKind::Fixed(_) => None,
         }
-        opcode.build()
     }
+}
+
+fn fsync(fd: RawFd, data_sync: bool) -> io::Result<u32> {
+    let ret = if data_sync {
+        unsafe { libc::fdatasync(fd) }
+    } else {
+        unsafe { libc::fsync(fd) }
+    };
+    cvt(ret as i64)
 }
\ No newline at end of file
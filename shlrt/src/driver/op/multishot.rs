@@ -0,0 +1,71 @@
+use std::io;
+use std::task::{ready, Context, Poll};
+use io_uring::cqueue;
+use crate::driver;
+use crate::driver::Inner;
+use crate::driver::op::{CompletionMeta, OpAble};
+
+/// 一个sqe返回多个cqe的op，例如multishot accept、recv、poll。
+/// 内核在最后一个cqe上清除IORING_CQE_F_MORE结束操作，如果结果成功会自动重新提交。
+pub(crate) struct MultishotOp<T: 'static> {
+    // 所属的io_uring
+    pub(crate) driver: Inner,
+    // slab的index，操作彻底结束后为usize::MAX
+    pub(crate) index: usize,
+    // op操作包含的data信息，重新提交时需要
+    pub(crate) data: Option<T>,
+}
+
+impl<T: OpAble> MultishotOp<T> {
+    /// 提交multishot操作
+    pub(crate) fn submit_with(data: T) -> io::Result<MultishotOp<T>> {
+        driver::CURRENT.with(|this| this.submit_multishot(data))
+    }
+
+    /// 取出下一个结果，操作被取消或者出错结束后返回None
+    pub(crate) fn poll_next(&mut self, cx: &mut Context<'_>) -> Poll<Option<CompletionMeta>> {
+        if self.index == usize::MAX {
+            return Poll::Ready(None);
+        }
+
+        let meta = match ready!(self.driver.poll_multishot(self.index, cx)) {
+            Some(meta) => meta,
+            None => {
+                self.finish();
+                return Poll::Ready(None);
+            }
+        };
+        if cqueue::more(meta.flags) {
+            return Poll::Ready(Some(meta));
+        }
+
+        // 内核结束了操作，这是最后一个结果
+        match &meta.result {
+            Ok(_) => {
+                let data = self.data.as_mut().expect("unexpected operation state");
+                if self.driver.rearm_multishot(self.index, data).is_err() {
+                    self.finish();
+                }
+            }
+            Err(e) if e.raw_os_error() == Some(libc::ECANCELED) => {
+                self.finish();
+                return Poll::Ready(None);
+            }
+            Err(_) => self.finish(),
+        }
+        Poll::Ready(Some(meta))
+    }
+
+    /// 释放slab中的位置
+    fn finish(&mut self) {
+        self.driver.drop_op(self.index, &mut self.data);
+        self.index = usize::MAX;
+    }
+}
+
+impl<T: 'static> Drop for MultishotOp<T> {
+    fn drop(&mut self) {
+        // 还在运行的操作会被取消，data在最后一个cqe到达后释放
+        self.driver.drop_op(self.index, &mut self.data);
+    }
+}
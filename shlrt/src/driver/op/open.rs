@@ -2,23 +2,63 @@ use std::ffi::CString;
 use std::io;
 use std::os::unix::ffi::OsStrExt;
 use std::path::Path;
-use crate::driver::op::Op;
+use io_uring::{opcode, types};
+use io_uring::squeue::Entry;
+use crate::driver::legacy::{cvt, unsupported};
+use crate::driver::op::{BlockingCall, Op, OpAble};
+use crate::fs::OpenOptions;
 
-struct Open {
+pub(crate) struct Open {
     pub(crate) path: CString,
     flags: i32,
     mode: libc::mode_t,
+    /// 返回直接描述符，结果是固定文件表中的槽位
+    pub(crate) direct: bool,
 }
 
 impl Op<Open> {
     pub(crate) fn open<P: AsRef<Path>>(path: P, options: &OpenOptions) -> io::Result<Self> {
         let path = CString::new(path.as_ref().as_os_str().as_bytes())?;
-        let flags = libc::O_CLOEXEC
+        // 直接描述符没有普通的fd，内核拒绝带O_CLOEXEC的直接打开
+        let cloexec = if options.direct { 0 } else { libc::O_CLOEXEC };
+        let flags = cloexec
             | options.access_mode()?
             | options.creation_mode()?
             | (options.custom_flags & !libc::O_ACCMODE);
         let mode = options.mode;
 
-        Self::submit_with(Open{path, flags, mode})
+        Self::submit_with(Open{path, flags, mode, direct: options.direct})
     }
-}
\ No newline at end of file
+}
+
+impl OpAble for Open {
+    fn uring_op(&mut self) -> Entry {
+        let file_index = self.direct.then(types::DestinationSlot::auto_target);
+        opcode::OpenAt::new(types::Fd(libc::AT_FDCWD), self.path.as_ptr())
+            .flags(self.flags)
+            .mode(self.mode)
+            .file_index(file_index)
+            .build()
+    }
+
+    fn opcode(&mut self) -> u8 {
+        opcode::OpenAt::CODE
+    }
+
+    fn legacy_call(&mut self) -> io::Result<u32> {
+        if self.direct {
+            return Err(unsupported());
+        }
+        cvt(unsafe { libc::open(self.path.as_ptr(), self.flags, self.mode as libc::c_uint) } as i64)
+    }
+
+    fn blocking_call(&self) -> Option<BlockingCall> {
+        if self.direct {
+            return None;
+        }
+        let (path, flags, mode) = (self.path.clone(), self.flags, self.mode);
+        Some(Box::new(move || {
+            cvt(unsafe { libc::open(path.as_ptr(), flags, mode as libc::c_uint) } as i64)
+        }))
+    }
+}
@@ -0,0 +1,98 @@
+use std::io;
+use std::os::fd::RawFd;
+use std::time::Duration;
+use io_uring::opcode;
+use io_uring::squeue::Entry;
+use crate::buf::IoBufMut;
+use crate::BufResult;
+use crate::driver::legacy::{cvt, Direction};
+use crate::driver::op::{Op, OpAble};
+use crate::driver::shared_fd::{FdKind, SharedFd};
+
+/// 读操作封装
+pub(crate) struct Read<T> {
+    fd: SharedFd,
+    pub(crate) buf: T,
+    offset: u64,
+}
+
+impl<T: IoBufMut> Read<T> {
+    pub(crate) fn new(fd: &SharedFd, buf: T, offset: u64) -> Read<T> {
+        Read {
+            fd: fd.clone(),
+            buf,
+            offset,
+        }
+    }
+}
+
+impl<T: IoBufMut> Op<Read<T>> {
+    /// 提交失败时返回缓冲区
+    pub(crate) fn read_at(fd: &SharedFd, buf: T, offset: u64) -> Result<Op<Read<T>>, (io::Error, T)> {
+        Op::submit_or_return(Read::new(fd, buf, offset)).map_err(|(e, read)| (e, read.buf))
+    }
+
+    /// 读取，timeout内没有完成时以ErrorKind::TimedOut结束
+    pub(crate) fn read_at_timeout(
+        fd: &SharedFd,
+        buf: T,
+        offset: u64,
+        timeout: Duration,
+    ) -> Result<Op<Read<T>>, (io::Error, T)> {
+        Op::submit_with_timeout(Read::new(fd, buf, offset), timeout).map_err(|(e, read)| (e, read.buf))
+    }
+
+    /// 等待读操作完成，返回读到的字节数和缓冲区
+    pub(crate) async fn read(self) -> BufResult<usize, T> {
+        let complete = self.await;
+        let res = complete.meta.result.map(|n| n as usize);
+        let mut buf = complete.data.buf;
+        if let Ok(n) = res {
+            unsafe { buf.set_init(n) };
+        }
+        (res, buf)
+    }
+}
+
+impl<T: IoBufMut> OpAble for Read<T> {
+    fn uring_op(&mut self) -> Entry {
+        let ptr = self.buf.write_ptr();
+        let len = self.buf.bytes_total() as u32;
+        // 固定缓冲区不需要每次io都锁定内存页
+        match self.buf.fixed_index_mut() {
+            Some(index) => with_fd!(self.fd, fd => opcode::ReadFixed::new(fd, ptr, len, index)
+                .offset(self.offset)
+                .build()),
+            None => with_fd!(self.fd, fd => opcode::Read::new(fd, ptr, len).offset(self.offset).build()),
+        }
+    }
+
+    fn opcode(&mut self) -> u8 {
+        match self.buf.fixed_index_mut() {
+            Some(_) => opcode::ReadFixed::CODE,
+            None => opcode::Read::CODE,
+        }
+    }
+
+    fn fd(&self) -> Option<FdKind> {
+        Some(self.fd.kind())
+    }
+
+    fn legacy_interest(&self) -> Option<(Direction, RawFd)> {
+        Some((Direction::Read, self.fd.raw_fd()))
+    }
+
+    fn legacy_call(&mut self) -> io::Result<u32> {
+        let fd = self.fd.raw_fd();
+        let ptr = self.buf.write_ptr() as *mut libc::c_void;
+        let len = self.buf.bytes_total();
+        let res = cvt(unsafe { libc::pread(fd, ptr, len, self.offset as libc::off_t) } as i64);
+        match res {
+            // socket和pipe不能指定偏移，和io_uring一样忽略offset
+            Err(e) if e.raw_os_error() == Some(libc::ESPIPE) => {
+                cvt(unsafe { libc::read(fd, ptr, len) } as i64)
+            }
+            res => res,
+        }
+    }
+}
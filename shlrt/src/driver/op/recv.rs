@@ -0,0 +1,122 @@
+use std::io;
+use std::task::{ready, Context, Poll};
+use io_uring::{opcode, squeue};
+use io_uring::squeue::Entry;
+use crate::buf::{BufRing, BufRingEntry};
+use crate::driver::op::{MultishotOp, Op, OpAble};
+use crate::driver::shared_fd::{FdKind, SharedFd};
+
+/// 从buffer ring中选择缓冲区的recv操作
+pub(crate) struct RecvBufRing {
+    fd: SharedFd,
+    ring: BufRing,
+}
+
+impl Op<RecvBufRing> {
+    pub(crate) fn recv_buf_ring(fd: &SharedFd, ring: &BufRing) -> io::Result<Self> {
+        Op::submit_with(RecvBufRing {
+            fd: fd.clone(),
+            ring: ring.clone(),
+        })
+    }
+
+    /// 等待数据，返回None表示对端已经关闭
+    pub(crate) async fn recv(self) -> io::Result<Option<BufRingEntry>> {
+        let complete = self.await;
+        complete.data.ring.take(complete.meta)
+    }
+}
+
+impl OpAble for RecvBufRing {
+    fn uring_op(&mut self) -> Entry {
+        with_fd!(self.fd, fd => opcode::Recv::new(fd, std::ptr::null_mut(), self.ring.buf_size() as u32)
+            .buf_group(self.ring.bgid())
+            .build()
+            .flags(squeue::Flags::BUFFER_SELECT))
+    }
+
+    fn opcode(&mut self) -> u8 {
+        opcode::Recv::CODE
+    }
+
+    fn fd(&self) -> Option<FdKind> {
+        Some(self.fd.kind())
+    }
+}
+
+/// 从buffer ring中选择缓冲区的read操作
+pub(crate) struct ReadBufRing {
+    fd: SharedFd,
+    ring: BufRing,
+    offset: u64,
+}
+
+impl Op<ReadBufRing> {
+    pub(crate) fn read_buf_ring(fd: &SharedFd, ring: &BufRing, offset: u64) -> io::Result<Self> {
+        Op::submit_with(ReadBufRing {
+            fd: fd.clone(),
+            ring: ring.clone(),
+            offset,
+        })
+    }
+
+    /// 等待数据，返回None表示已经读到文件末尾
+    pub(crate) async fn read(self) -> io::Result<Option<BufRingEntry>> {
+        let complete = self.await;
+        complete.data.ring.take(complete.meta)
+    }
+}
+
+impl OpAble for ReadBufRing {
+    fn uring_op(&mut self) -> Entry {
+        with_fd!(self.fd, fd => opcode::Read::new(fd, std::ptr::null_mut(), self.ring.buf_size() as u32)
+            .offset(self.offset)
+            .buf_group(self.ring.bgid())
+            .build()
+            .flags(squeue::Flags::BUFFER_SELECT))
+    }
+
+    fn opcode(&mut self) -> u8 {
+        opcode::Read::CODE
+    }
+
+    fn fd(&self) -> Option<FdKind> {
+        Some(self.fd.kind())
+    }
+}
+
+/// multishot recv，每次有数据到达都从buffer ring中取一个缓冲区
+pub(crate) struct RecvMultiBufRing {
+    fd: SharedFd,
+    ring: BufRing,
+}
+
+impl MultishotOp<RecvMultiBufRing> {
+    pub(crate) fn recv_multi(fd: &SharedFd, ring: &BufRing) -> io::Result<Self> {
+        MultishotOp::submit_with(RecvMultiBufRing {
+            fd: fd.clone(),
+            ring: ring.clone(),
+        })
+    }
+
+    /// 取出下一段数据，返回None表示操作已经结束，Some(Ok(None))表示对端已经关闭
+    pub(crate) fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Option<io::Result<Option<BufRingEntry>>>> {
+        let meta = ready!(self.poll_next(cx));
+        let ring = &self.data.as_ref().expect("unexpected operation state").ring;
+        Poll::Ready(meta.map(|meta| ring.take(meta)))
+    }
+}
+
+impl OpAble for RecvMultiBufRing {
+    fn uring_op(&mut self) -> Entry {
+        with_fd!(self.fd, fd => opcode::RecvMulti::new(fd, self.ring.bgid()).build())
+    }
+
+    fn opcode(&mut self) -> u8 {
+        opcode::RecvMulti::CODE
+    }
+
+    fn fd(&self) -> Option<FdKind> {
+        Some(self.fd.kind())
+    }
+}
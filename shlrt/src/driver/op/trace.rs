@@ -0,0 +1,176 @@
+//! op的tracing span，没有开启tracing feature时是空操作
+
+#[cfg(feature = "tracing")]
+mod imp {
+    use std::io;
+    use std::time::Instant;
+
+    use tracing::field::Empty;
+    use tracing::Span;
+
+    use crate::driver::shared_fd::FdKind;
+
+    /// 从sqe放入sq到future拿到结果的span
+    pub(crate) struct OpTrace {
+        span: Span,
+        submitted: Instant,
+    }
+
+    impl OpTrace {
+        pub(crate) fn new() -> OpTrace {
+            OpTrace {
+                span: Span::none(),
+                submitted: Instant::now(),
+            }
+        }
+
+        /// 记录op的opcode、fd和user_data，开始计时
+        pub(crate) fn submitted(&mut self, opcode: u8, fd: Option<FdKind>, user_data: u64) {
+            self.span = tracing::trace_span!(
+                "op",
+                opcode,
+                fd = ?fd,
+                user_data,
+                result = Empty,
+                errno = Empty,
+                latency_us = Empty,
+            );
+            self.submitted = Instant::now();
+        }
+
+        /// 轮询期间进入span，driver中的事件会关联到这个op
+        pub(crate) fn enter(&self) -> tracing::span::Entered<'_> {
+            self.span.enter()
+        }
+
+        /// 记录结果和耗时
+        pub(crate) fn completed(&self, result: &io::Result<u32>) {
+            match result {
+                Ok(n) => self.span.record("result", n),
+                Err(e) => self.span.record("errno", e.raw_os_error().unwrap_or(0)),
+            };
+            let latency = self.submitted.elapsed();
+            self.span.record("latency_us", latency.as_micros() as u64);
+            tracing::trace!(parent: &self.span, ?latency, "op completed");
+        }
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+mod imp {
+    use std::io;
+
+    use crate::driver::shared_fd::FdKind;
+
+    pub(crate) struct OpTrace;
+
+    /// 对应tracing::span::Entered
+    pub(crate) struct Entered;
+
+    impl OpTrace {
+        #[inline]
+        pub(crate) fn new() -> OpTrace {
+            OpTrace
+        }
+
+        #[inline]
+        pub(crate) fn submitted(&mut self, _opcode: u8, _fd: Option<FdKind>, _user_data: u64) {}
+
+        #[inline]
+        pub(crate) fn enter(&self) -> Entered {
+            Entered
+        }
+
+        #[inline]
+        pub(crate) fn completed(&self, _result: &io::Result<u32>) {}
+    }
+}
+
+pub(crate) use imp::OpTrace;
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+    use std::fmt;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use io_uring::opcode;
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+
+    use crate::driver::mock::runtime;
+    use crate::driver::op::Op;
+    use crate::driver::shared_fd::SharedFd;
+
+    /// 按顺序记录span和事件的字段，span的名字和事件都记录为message字段
+    #[derive(Default)]
+    struct Recorder {
+        next_id: AtomicU64,
+        fields: Mutex<Vec<(String, String)>>,
+    }
+
+    impl Visit for &Recorder {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            self.fields.lock().unwrap().push((field.name().to_string(), format!("{:?}", value)));
+        }
+    }
+
+    impl Recorder {
+        fn field(&self, name: &str) -> Option<String> {
+            let fields = self.fields.lock().unwrap();
+            fields.iter().find(|(field, _)| field == name).map(|(_, value)| value.clone())
+        }
+
+        fn has_message(&self, message: &str) -> bool {
+            let fields = self.fields.lock().unwrap();
+            fields.iter().any(|(field, value)| field == "message" && value == message)
+        }
+    }
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            self.fields.lock().unwrap().push(("message".to_string(), span.metadata().name().to_string()));
+            span.record(&mut &*self);
+            Id::from_u64(self.next_id.fetch_add(1, Ordering::Relaxed) + 1)
+        }
+
+        fn record(&self, _span: &Id, values: &Record<'_>) {
+            values.record(&mut &*self);
+        }
+
+        fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            event.record(&mut &*self);
+        }
+
+        fn enter(&self, _span: &Id) {}
+
+        fn exit(&self, _span: &Id) {}
+    }
+
+    #[test]
+    fn op_span_records_completion() {
+        let recorder = Arc::new(Recorder::default());
+        tracing::subscriber::with_default(recorder.clone(), || {
+            let (rt, mock) = runtime();
+            let op = rt.enter(|| Op::read_at(&SharedFd::new_without_register(-1), Vec::new(), 0).unwrap());
+            let user_data = mock.take_submitted()[0].user_data;
+            let join = rt.enter(|| crate::spawn(async move { op.await.meta.result.is_err() }));
+            mock.complete(user_data, -libc::EIO, 0);
+            rt.step();
+            assert!(join.is_finished());
+        });
+
+        assert!(recorder.has_message("op"));
+        assert_eq!(recorder.field("opcode"), Some(opcode::Read::CODE.to_string()));
+        assert_eq!(recorder.field("errno"), Some(libc::EIO.to_string()));
+        assert!(recorder.field("latency_us").is_some());
+        assert!(recorder.has_message("op completed"));
+    }
+}
@@ -0,0 +1,82 @@
+use std::io;
+use std::os::fd::RawFd;
+use io_uring::opcode;
+use io_uring::squeue::Entry;
+use crate::buf::IoBuf;
+use crate::BufResult;
+use crate::driver::legacy::{cvt, Direction};
+use crate::driver::op::{Op, OpAble};
+use crate::driver::shared_fd::{FdKind, SharedFd};
+
+/// 写操作封装
+pub(crate) struct Write<T> {
+    fd: SharedFd,
+    pub(crate) buf: T,
+    offset: u64,
+}
+
+impl<T: IoBuf> Write<T> {
+    pub(crate) fn new(fd: &SharedFd, buf: T, offset: u64) -> Write<T> {
+        Write {
+            fd: fd.clone(),
+            buf,
+            offset,
+        }
+    }
+}
+
+impl<T: IoBuf> Op<Write<T>> {
+    /// 提交失败时返回缓冲区
+    pub(crate) fn write_at(fd: &SharedFd, buf: T, offset: u64) -> Result<Op<Write<T>>, (io::Error, T)> {
+        Op::submit_or_return(Write::new(fd, buf, offset)).map_err(|(e, write)| (e, write.buf))
+    }
+
+    /// 等待写操作完成，返回写入的字节数和缓冲区
+    pub(crate) async fn write(self) -> BufResult<usize, T> {
+        let complete = self.await;
+        (complete.meta.result.map(|n| n as usize), complete.data.buf)
+    }
+}
+
+impl<T: IoBuf> OpAble for Write<T> {
+    fn uring_op(&mut self) -> Entry {
+        let ptr = self.buf.read_ptr();
+        let len = self.buf.bytes_init() as u32;
+        // 固定缓冲区不需要每次io都锁定内存页
+        match self.buf.fixed_index() {
+            Some(index) => with_fd!(self.fd, fd => opcode::WriteFixed::new(fd, ptr, len, index)
+                .offset(self.offset)
+                .build()),
+            None => with_fd!(self.fd, fd => opcode::Write::new(fd, ptr, len).offset(self.offset).build()),
+        }
+    }
+
+    fn opcode(&mut self) -> u8 {
+        match self.buf.fixed_index() {
+            Some(_) => opcode::WriteFixed::CODE,
+            None => opcode::Write::CODE,
+        }
+    }
+
+    fn fd(&self) -> Option<FdKind> {
+        Some(self.fd.kind())
+    }
+
+    fn legacy_interest(&self) -> Option<(Direction, RawFd)> {
+        Some((Direction::Write, self.fd.raw_fd()))
+    }
+
+    fn legacy_call(&mut self) -> io::Result<u32> {
+        let fd = self.fd.raw_fd();
+        let ptr = self.buf.read_ptr() as *const libc::c_void;
+        let len = self.buf.bytes_init();
+        let res = cvt(unsafe { libc::pwrite(fd, ptr, len, self.offset as libc::off_t) } as i64);
+        match res {
+            // socket和pipe不能指定偏移，和io_uring一样忽略offset
+            Err(e) if e.raw_os_error() == Some(libc::ESPIPE) => {
+                cvt(unsafe { libc::write(fd, ptr, len) } as i64)
+            }
+            res => res,
+        }
+    }
+}
@@ -1,39 +1,106 @@
 use std::fmt::Formatter;
-use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
-use std::{cell::UnsafeCell, io, rc::Rc};
+use std::os::unix::io::RawFd;
+use std::{cell::Cell, io, rc::Rc};
+use crate::driver::{self, Inner};
 
-/// 封装fd
+/// 封装fd，可以是普通的fd，也可以是io_uring固定文件表中的槽位
 #[derive(Clone, Debug)]
 pub(crate) struct SharedFd {
     inner: Rc<InnerFd>,
 }
 
+/// fd的类型，构造sqe时分别对应types::Fd和types::Fixed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum FdKind {
+    Raw(RawFd),
+    Fixed(u32),
+}
+
 impl SharedFd {
     /// 新建初始化共享文件描述符结构
     pub(crate) fn new(fd: RawFd) -> io::Result<SharedFd> {
-        Ok(SharedFd {
+        Ok(Self::new_without_register(fd))
+    }
+
+    /// 普通fd，不注册到固定文件表
+    pub(crate) fn new_without_register(fd: RawFd) -> SharedFd {
+        SharedFd {
             inner: Rc::new(InnerFd {
-                fd,
-                state: UnsafeCell::new(State::Init),
+                fd: FdKind::Raw(fd),
+                driver: None,
+                closed: Cell::new(false),
             }),
-        })
+        }
     }
 
-    pub(crate) fn raw_fd(&self) -> RawFd {
-        self.inner.fd
+    /// 固定文件表中的槽位（直接描述符），drop时从表中移除
+    ///
+    /// # Panics
+    ///
+    /// 不在运行时中调用时会panic
+    pub(crate) fn new_fixed(slot: u32) -> SharedFd {
+        SharedFd {
+            inner: Rc::new(InnerFd {
+                fd: FdKind::Fixed(slot),
+                driver: Some(driver::CURRENT.with(|inner| inner.clone())),
+                closed: Cell::new(false),
+            }),
+        }
     }
 
-    //
-    // pub(crate) fn new_without_register(fd: RawFd) -> SharedFd {
-    //
-    // }
-}
+    pub(crate) fn kind(&self) -> FdKind {
+        self.inner.fd
+    }
 
+    /// 关闭fd的op交给内核之前调用，之后drop时不再关闭fd。op没有被执行时传入false还原。
+    /// legacy driver需要在fd关闭之前把它从epoll中移除
+    pub(crate) fn set_closed(&self, closed: bool) {
+        if let (true, FdKind::Raw(fd)) = (closed, self.inner.fd) {
+            if driver::CURRENT.is_set() {
+                driver::CURRENT.with(|inner| inner.deregister_fd(fd));
+            }
+        }
+        self.inner.closed.set(closed);
+    }
 
+    /// # Panics
+    ///
+    /// 直接描述符没有对应的普通fd，调用时会panic
+    pub(crate) fn raw_fd(&self) -> RawFd {
+        match self.inner.fd {
+            FdKind::Raw(fd) => fd,
+            FdKind::Fixed(_) => panic!("a direct descriptor has no raw fd"),
+        }
+    }
+}
 
 struct InnerFd {
-    fd: RawFd,
-    state: UnsafeCell<State>,
+    fd: FdKind,
+    /// 固定槽位所属的io_uring
+    driver: Option<Inner>,
+    /// fd已经通过Close op关闭
+    closed: Cell<bool>,
+}
+
+impl Drop for InnerFd {
+    fn drop(&mut self) {
+        if self.closed.get() {
+            return;
+        }
+        match (self.fd, &self.driver) {
+            (FdKind::Fixed(slot), Some(driver)) => {
+                let _ = driver.unregister_file(slot);
+            }
+            // legacy driver需要在fd被复用之前把它从epoll中移除
+            (FdKind::Raw(fd), _) => {
+                if driver::CURRENT.is_set() {
+                    driver::CURRENT.with(|inner| inner.deregister_fd(fd));
+                }
+                unsafe { libc::close(fd) };
+            }
+            (FdKind::Fixed(_), None) => {}
+        }
+    }
 }
 
 impl std::fmt::Debug for InnerFd {
@@ -42,13 +109,52 @@ impl std::fmt::Debug for InnerFd {
     }
 }
 
-enum State {
-    /// 初始化
-    Init,
-    /// 等待被唤醒
-    Waiting(Option<std::task::Waker>),
-    /// 正在关闭
-    Closing(),
-    /// 已经完全关闭
-    Closed,
+#[cfg(test)]
+mod tests {
+    use std::os::unix::io::RawFd;
+
+    use super::SharedFd;
+    use crate::fs::File;
+    use crate::RuntimeBuilder;
+
+    fn pipe() -> (RawFd, RawFd) {
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) }, 0);
+        (fds[0], fds[1])
+    }
+
+    /// 写端全部关闭后读端读到EOF，不依赖fd号是否被复用
+    fn assert_eof(rx: RawFd) {
+        let mut buf = [0u8; 1];
+        let n = unsafe { libc::read(rx, buf.as_mut_ptr() as *mut libc::c_void, 1) };
+        assert_eq!(n, 0);
+        unsafe { libc::close(rx) };
+    }
+
+    #[test]
+    fn raw_fd_is_closed_on_drop() {
+        let (rx, tx) = pipe();
+        let fd = SharedFd::new(tx).unwrap();
+        let clone = fd.clone();
+        drop(fd);
+        drop(clone);
+        assert_eof(rx);
+    }
+
+    #[test]
+    fn close_op_takes_over_the_fd() {
+        for legacy in [false, true] {
+            let mut builder = RuntimeBuilder::new();
+            if legacy {
+                builder = builder.force_legacy();
+            }
+            let mut rt = builder.build().unwrap();
+            let (rx, tx) = pipe();
+            rt.block_on(async {
+                let file = File::from_shared_fd(SharedFd::new(tx).unwrap());
+                file.close().await.unwrap();
+            });
+            assert_eof(rx);
+        }
+    }
 }
@@ -0,0 +1,34 @@
+use std::collections::BTreeMap;
+use std::sync::{Mutex, Weak};
+use std::task::Waker;
+
+use crate::driver::EventWaker;
+
+/// 线程id到该线程driver唤醒器的映射，用于跨线程唤醒任务
+static UNPARK: Mutex<BTreeMap<usize, Weak<EventWaker>>> = Mutex::new(BTreeMap::new());
+
+/// 注册当前线程的唤醒器
+pub(crate) fn register_unpark(thread_id: usize, waker: Weak<EventWaker>) {
+    UNPARK.lock().unwrap().insert(thread_id, waker);
+}
+
+/// 注销唤醒器，只有注册的还是同一个唤醒器时才会删除
+pub(crate) fn unregister_unpark(thread_id: usize, waker: &Weak<EventWaker>) {
+    let mut unpark = UNPARK.lock().unwrap();
+    if matches!(unpark.get(&thread_id), Some(registered) if registered.ptr_eq(waker)) {
+        unpark.remove(&thread_id);
+    }
+}
+
+/// 把waker投递到所属线程，由所属线程的driver在park时调用。
+/// 所属线程的driver已经释放时返回false，waker会在当前线程被drop。
+pub(crate) fn send_waker(thread_id: usize, waker: Waker) -> bool {
+    let event_waker = UNPARK.lock().unwrap().get(&thread_id).and_then(Weak::upgrade);
+    match event_waker {
+        Some(event_waker) => {
+            let _ = event_waker.send_waker(waker);
+            true
+        }
+        None => false,
+    }
+}
@@ -1,6 +1,7 @@
+use std::collections::VecDeque;
 use std::task::Waker;
 
-/// Uring的生命周期
+use crate::driver::op::CompletionMeta;
 
 /// iouring操作的生命周期
 pub(crate) enum Lifecycle {
@@ -8,8 +9,50 @@ pub(crate) enum Lifecycle {
     Submitted,
     /// 提交者正在等待op的完成
     Waiting(Waker),
-    /// 提交者对结果已经不感兴趣。
-    Ignored(Box<dyn std::any::Any>),
+    /// 提交者对结果已经不感兴趣，保留op的数据直到内核不再使用它。
+    /// multishot操作保留释放结果的函数，之后到达的cqe的结果由它释放
+    Ignored(#[allow(dead_code)] Box<dyn std::any::Any>, Option<fn(u32)>),
     /// op已经完成
     Completed(std::io::Result<u32>, u32),
+    /// 会返回多个cqe的op
+    Multishot {
+        /// 已经收到但还没有被取走的结果
+        completions: VecDeque<CompletionMeta>,
+        waker: Option<Waker>,
+        /// 最后一个cqe没有IORING_CQE_F_MORE，内核不会再返回结果
+        terminated: bool,
+        /// 释放没有被取走的结果，见 [OpAble::release](crate::driver::op::OpAble::release)
+        release: Option<fn(u32)>,
+    },
+}
+
+impl Lifecycle {
+    pub(crate) fn multishot(release: Option<fn(u32)>) -> Lifecycle {
+        Lifecycle::Multishot {
+            completions: VecDeque::new(),
+            waker: None,
+            terminated: false,
+            release,
+        }
+    }
+
+    /// 释放multishot操作中还没有被取走的结果
+    pub(crate) fn release_completions(&mut self) {
+        if let Lifecycle::Multishot { completions, release: Some(release), .. } = self {
+            for meta in completions.drain(..) {
+                if let Ok(result) = meta.result {
+                    release(result);
+                }
+            }
+        }
+    }
+
+    /// 内核是否还会返回cqe
+    pub(crate) fn is_in_flight(&self) -> bool {
+        match self {
+            Lifecycle::Completed(..) => false,
+            Lifecycle::Multishot { terminated, .. } => !terminated,
+            _ => true,
+        }
+    }
 }
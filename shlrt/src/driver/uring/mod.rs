@@ -1,21 +1,26 @@
-use crate::driver::op::{CompletionMeta, Op, OpAble};
+use crate::driver::op::{CompletionMeta, MultishotOp, Op, OpAble, OpTrace};
 use crate::driver::uring::lifecycle::Lifecycle;
-use crate::driver::{CURRENT, Driver, Inner};
-use io_uring::{cqueue, opcode};
+use crate::driver::{CURRENT, Driver, EventWaker, Features, Inner, Metrics, UnparkHandle};
+use crate::driver::thread::{register_unpark, unregister_unpark};
+use crate::utils::slab::Slab;
+use crate::utils::thread_id::current_thread_id;
+use crate::macros::trace::trace_event;
+use io_uring::{cqueue, opcode, squeue, types};
 use io_uring::types::Timespec;
 use std::cell::UnsafeCell;
+use std::collections::VecDeque;
 use std::io;
 use std::mem::ManuallyDrop;
 use std::ops::{Deref, DerefMut};
 use std::os::fd::{AsRawFd, RawFd};
 use std::rc::Rc;
-use std::task::{Context, Poll};
-use std::time::Duration;
-use slab::Slab;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
 use crate::driver::util::timespec;
 
-mod lifecycle;
-mod waker;
+pub(crate) mod lifecycle;
 
 /// 已取消操作
 pub(crate) const CANCEL_USERDATA: u64 = u64::MAX;
@@ -23,37 +28,202 @@ pub(crate) const CANCEL_USERDATA: u64 = u64::MAX;
 /// 超时操作
 pub(crate) const TIMEOUT_USERDATA: u64 = u64::MAX - 1;
 
+/// eventfd读操作
+pub(crate) const EVENTFD_USERDATA: u64 = u64::MAX - 2;
+
 pub(crate) const MIN_REVERSED_USERDATA: u64 = u64::MAX - 2;
 
+/// 链接到op上的超时操作，user_data是op的key加上这个标志位
+pub(crate) const LINK_TIMEOUT_FLAG: u64 = 1 << 63;
+
+/// io_uring_enter的flag，要求内核收割完成事件（包括溢出的cqe）
+const IORING_ENTER_GETEVENTS: u32 = 1;
+
+/// op的key同时作为user_data：低32位是slab中的位置，接下来31位是插入时的代数，最高位留给链接超时。
+/// 位置被复用后，旧op迟到的cqe或者取消因为代数不同而找不到新的op
+const KEY_SLOT_BITS: u32 = 32;
+const KEY_SLOT_MASK: usize = (1 << KEY_SLOT_BITS) - 1;
+const KEY_GENERATION_MASK: u32 = (1 << 31) - 1;
+const _: () = assert!(usize::BITS == 64, "op key needs 64-bit usize");
+
+/// slab中保存的op
+struct Slot {
+    generation: u32,
+    lifecycle: Lifecycle,
+    /// 链接的超时已经到期（LinkTimeout的cqe是-ETIME）
+    timed_out: bool,
+}
+
 /// 保存所有的io_uring操作结构，当io_uring关闭时，需要
-struct Ops {
-    slab: Slab<Lifecycle>,
+pub(crate) struct Ops {
+    slab: Slab<Slot>,
+    /// 下一个op的代数
+    generation: u32,
+    /// slab中op数量的上限
+    max: usize,
+    /// 等待slab空出位置的任务，按照等待的顺序唤醒
+    capacity_waiters: VecDeque<Waker>,
 }
 
 impl Ops {
-    const fn new() -> Self {
-        Ops { slab: Slab::new() }
+    pub(crate) const fn new() -> Self {
+        Ops {
+            slab: Slab::new(),
+            generation: 0,
+            max: usize::MAX,
+            capacity_waiters: VecDeque::new(),
+        }
+    }
+
+    pub(crate) fn set_max(&mut self, max: usize) {
+        self.max = max;
+    }
+
+    /// op数量达到上限时拒绝新的op
+    pub(crate) fn check_capacity(&self) -> io::Result<()> {
+        if self.slab.len() >= self.max {
+            return Err(io::Error::new(
+                io::ErrorKind::ResourceBusy,
+                "too many in-flight operations",
+            ));
+        }
+        Ok(())
+    }
+
+    /// 等待slab中有空闲的位置，每释放一个位置唤醒一个等待的任务
+    pub(crate) fn poll_capacity(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if self.slab.len() < self.max {
+            // 这个任务之外还有空闲的位置时，继续唤醒下一个等待的任务
+            if self.slab.len() + 1 < self.max {
+                if let Some(waker) = self.capacity_waiters.pop_front() {
+                    waker.wake();
+                }
+            }
+            return Poll::Ready(());
+        }
+        if !self.capacity_waiters.iter().any(|waker| waker.will_wake(cx.waker())) {
+            self.capacity_waiters.push_back(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+
+    /// 插入op，返回带代数的key
+    fn insert_lifecycle(&mut self, lifecycle: Lifecycle) -> usize {
+        let generation = self.generation;
+        self.generation = generation.wrapping_add(1) & KEY_GENERATION_MASK;
+        // slab最多有2^32-64个位置，不会和代数重叠，也不会和保留的user_data冲突
+        let slot = self.slab.insert(Slot { generation, lifecycle, timed_out: false });
+        (generation as usize) << KEY_SLOT_BITS | slot
     }
 
     // Insert a new operation
     pub(crate) fn insert(&mut self) -> usize {
-        self.slab.insert(Lifecycle::Submitted)
+        self.insert_lifecycle(Lifecycle::Submitted)
+    }
+
+    /// 插入一个multishot操作
+    pub(crate) fn insert_multishot(&mut self, release: Option<fn(u32)>) -> usize {
+        self.insert_lifecycle(Lifecycle::multishot(release))
+    }
+
+    /// key对应的slab位置，位置已经释放或者被新的op复用时返回None
+    fn slot(&self, key: usize) -> Option<usize> {
+        let slot = key & KEY_SLOT_MASK;
+        match self.slab.get_ref(slot) {
+            Some(entry) if entry.generation as usize == key >> KEY_SLOT_BITS => Some(slot),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn get(&mut self, key: usize) -> Option<LifecycleRef<'_>> {
+        let slot = self.slot(key)?;
+        Some(LifecycleRef { slot, ptr: self })
+    }
+
+    fn remove_slot(&mut self, slot: usize) -> Lifecycle {
+        if let Some(waker) = self.capacity_waiters.pop_front() {
+            waker.wake();
+        }
+        unsafe { self.slab.remove(slot).unwrap_unchecked() }.lifecycle
+    }
+
+    /// slab中的op数量
+    pub(crate) fn len(&self) -> usize {
+        self.slab.len()
+    }
+
+    pub(crate) fn complete(&mut self, key: usize, result: io::Result<u32>, flags: u32) {
+        // 代数不匹配的cqe属于已经释放的op，直接丢弃
+        if let Some(lifecycle) = self.get(key) {
+            lifecycle.complete(result, flags);
+        }
     }
 
-    fn complete(&mut self, index: usize, result: io::Result<u32>, flags: u32) {
-        let lifecycle = unsafe { self.slab.get(index).unwrap_unchecked() };
-        lifecycle.complete(result, flags);
+    /// 处理链接超时的cqe。超时到期时是-ETIME，op先完成时超时被取消，结果是-ECANCELED。
+    /// 内核在同一批中提交op和超时的cqe，op被轮询前两者都已经收割
+    pub(crate) fn complete_link_timeout(&mut self, user_data: u64, result: i32) {
+        let key = (user_data & !LINK_TIMEOUT_FLAG) as usize;
+        if result != -libc::ETIME {
+            return;
+        }
+        if let Some(slot) = self.slot(key) {
+            if let Some(entry) = self.slab.get_mut(slot) {
+                entry.timed_out = true;
+            }
+        }
+    }
+
+    /// slab位置上还在内核中运行的op的key
+    pub(crate) fn in_flight_key(&self, slot: usize) -> Option<usize> {
+        let entry = self.slab.get_ref(slot)?;
+        entry
+            .lifecycle
+            .is_in_flight()
+            .then_some((entry.generation as usize) << KEY_SLOT_BITS | slot)
+    }
+
+    /// slab中已经使用过的位置数量的上限
+    pub(crate) fn slots(&self) -> usize {
+        self.slab.slots()
+    }
+
+    /// 还在内核中运行、没有收到cqe的op
+    pub(crate) fn in_flight(&self) -> impl Iterator<Item = usize> + '_ {
+        self.slab
+            .iter()
+            .filter(|(_, entry)| entry.lifecycle.is_in_flight())
+            .map(|(slot, entry)| (entry.generation as usize) << KEY_SLOT_BITS | slot)
+    }
+
+    /// 内核可能还会访问op引用的内存，泄漏所有op而不是释放
+    pub(crate) fn leak(&mut self) {
+        std::mem::forget(std::mem::replace(&mut self.slab, Slab::new()));
     }
 }
 
 pub(crate) struct LifecycleRef<'a> {
-    index: usize,
+    slot: usize,
     ptr: &'a mut Ops,
 }
 
+impl Deref for LifecycleRef<'_> {
+    type Target = Lifecycle;
+
+    fn deref(&self) -> &Lifecycle {
+        // LifecycleRef只在位置有效时创建
+        &unsafe { self.ptr.slab.get_ref(self.slot).unwrap_unchecked() }.lifecycle
+    }
+}
+
+impl DerefMut for LifecycleRef<'_> {
+    fn deref_mut(&mut self) -> &mut Lifecycle {
+        &mut unsafe { self.ptr.slab.get_mut(self.slot).unwrap_unchecked() }.lifecycle
+    }
+}
+
 impl<'a> LifecycleRef<'a> {
     pub(crate) fn remove(self) -> Lifecycle {
-        self.ptr.remove(self.index)
+        self.ptr.remove_slot(self.slot)
     }
 
     /// io_uring操作完成时执行该函数，修改状态，或者唤醒协程
@@ -64,18 +234,58 @@ impl<'a> LifecycleRef<'a> {
                 *mut_ref = Lifecycle::Completed(result, flags);
             }
             Lifecycle::Waiting(_) => {
-                let old = std::mem::replace(mut_ref, Lifecycle::Completed(result, flags));
-                match old {
-                    Lifecycle::Waiting(waker) => {
-                        waker.wake();
-                    }
-                    _ => unsafe { std::hint::unreachable_unchecked() },
-                };
+                if let Lifecycle::Waiting(waker) = std::mem::replace(mut_ref, Lifecycle::Completed(result, flags)) {
+                    waker.wake();
+                }
             }
-            Lifecycle::Ignored(_) => {
-                self.remove();
+            Lifecycle::Ignored(_, release) => {
+                if let (Ok(result), Some(release)) = (&result, release) {
+                    release(*result);
+                }
+                // multishot操作在最后一个cqe到达前不能释放
+                if !cqueue::more(flags) {
+                    self.remove();
+                }
+            }
+            Lifecycle::Multishot { completions, waker, terminated, .. } => {
+                *terminated = !cqueue::more(flags);
+                completions.push_back(CompletionMeta { result, flags });
+                if let Some(waker) = waker.take() {
+                    waker.wake();
+                }
+            }
+            Lifecycle::Completed(..) => {
+                // 单次op只会有一个cqe，多出来的cqe保留第一个结果并忽略
+                trace_event!(?result, flags, "unexpected cqe for a completed op");
             }
-            Lifecycle::Completed(..) => unsafe { std::hint::unreachable_unchecked() },
+        }
+    }
+
+    /// 轮询multishot操作，取出一个结果。内核结束操作且结果都被取走后返回None
+    pub(crate) fn poll_multishot(mut self, cx: &mut Context<'_>) -> Poll<Option<CompletionMeta>> {
+        match &mut *self {
+            Lifecycle::Multishot { completions, waker, terminated, .. } => {
+                if let Some(meta) = completions.pop_front() {
+                    return Poll::Ready(Some(meta));
+                }
+                if *terminated {
+                    return Poll::Ready(None);
+                }
+                match waker {
+                    Some(waker) if waker.will_wake(cx.waker()) => {}
+                    _ => *waker = Some(cx.waker().clone()),
+                }
+                Poll::Pending
+            }
+            _ => unreachable!("poll_multishot on a single-shot op"),
+        }
+    }
+
+    /// 内核结束multishot操作后，重新提交前重置状态
+    pub(crate) fn rearm(mut self) {
+        if let Lifecycle::Multishot { waker, terminated, .. } = &mut *self {
+            *waker = None;
+            *terminated = false;
         }
     }
 
@@ -96,31 +306,40 @@ impl<'a> LifecycleRef<'a> {
             _ => {}
         }
 
+        // 链接的超时到期后内核以ECANCELED取消op，转换为超时错误
+        let timed_out = unsafe { self.ptr.slab.get_ref(self.slot).unwrap_unchecked() }.timed_out;
         match self.remove() {
+            Lifecycle::Completed(Err(e), flags) if timed_out && e.raw_os_error() == Some(libc::ECANCELED) => {
+                Poll::Ready(CompletionMeta { result: Err(io::Error::from_raw_os_error(libc::ETIMEDOUT)), flags })
+            }
             Lifecycle::Completed(result, flags) => Poll::Ready(CompletionMeta { result, flags }),
-            _ => unsafe { std::hint::unreachable_unchecked() },
+            _ => unreachable!("poll_op on a multishot or dropped op"),
         }
     }
 
-    // TODO 这个接口有什么用呢？？？？？？
+    /// op的future被drop时调用。内核还可能读写data引用的内存，所以把data移入Ignored，
+    /// 收到最后一个cqe后才释放。返回true表示op已经结束并从slab中移除，否则调用者需要提交取消
     pub(crate) fn drop_op<T: 'static>(mut self, data: &mut Option<T>) -> bool {
-        let mut_ref = &mut (*self);
-        match mut_ref {
-            Lifecycle::Submitted | Lifecycle::Waiting(_) => {
-                if let Some(data) = data.take() {
-                    *mut_ref = Lifecycle::Ignored(Box::new(data));
-                } else {
-                    // TODO 需要修改，先要搞清楚这个地方有什么作用
-                    // *mut_ref = Lifecycle::Ignored(Box::new(T));
-                }
-                return false;
-            }
-            Lifecycle::Completed(..) => {
+        self.release_completions();
+        match &*self {
+            Lifecycle::Completed(..) | Lifecycle::Multishot { terminated: true, .. } => {
                 self.remove();
+                true
+            }
+            Lifecycle::Submitted | Lifecycle::Waiting(_) | Lifecycle::Multishot { .. } => {
+                let release = match &*self {
+                    Lifecycle::Multishot { release, .. } => *release,
+                    _ => None,
+                };
+                // 没有data时也要进入Ignored，否则cqe到达后slab中的位置永远不会释放
+                *self = match data.take() {
+                    Some(data) => Lifecycle::Ignored(Box::new(data), release),
+                    None => Lifecycle::Ignored(Box::new(()), release),
+                };
+                false
             }
-            Lifecycle::Ignored(_) => unsafe { std::hint::unreachable_unchecked() },
+            Lifecycle::Ignored(..) => unreachable!("op dropped twice"),
         }
-        true
     }
 }
 
@@ -130,82 +349,350 @@ pub(crate) struct UringInner {
     ops: Ops,
     /// IoUring对象
     uring: ManuallyDrop<io_uring::IoUring>,
-    /// 是否支持ext_arg
-    ext_arg: bool
+    /// 内核支持的功能
+    features: Features,
+    /// 跨线程唤醒器
+    shared_waker: Arc<EventWaker>,
+    /// eventfd读操作是否已经提交
+    eventfd_installed: bool,
+    /// eventfd读操作的缓冲区
+    eventfd_buf: Box<[u8; 8]>,
+    /// sq满了并且提交失败时积压的sqe，每一组（例如链接的sqe）需要一起放入sq
+    backlog: VecDeque<Vec<squeue::Entry>>,
+    /// 运行统计
+    metrics: Metrics,
+    /// 已经取消过所有进行中的op
+    shut_down: bool,
 }
 
 impl UringInner {
-    /// 提取已经完成的任务
-    fn tick(&mut self) {
+    /// 提取已经完成的任务，返回收到的cqe数量
+    fn tick(&mut self) -> usize {
+        let mut reaped = self.reap();
+        // cq满了之后内核把cqe暂存在溢出链表中，只有GETEVENTS才会把它们刷回cq
+        while self.uring.submission().cq_overflow() {
+            trace_event!("cq overflow");
+            self.metrics.cq_overflows += 1;
+            let flushed = unsafe {
+                self.uring
+                    .submitter()
+                    .enter::<libc::sigset_t>(0, 0, IORING_ENTER_GETEVENTS, None)
+            };
+            let n = self.reap();
+            reaped += n;
+            if flushed.is_err() || n == 0 {
+                break;
+            }
+        }
+        // 内核没有NODROP或者分配溢出项失败时会直接丢弃cqe
+        self.metrics.cq_dropped = self.uring.completion().overflow() as u64;
+        trace_event!(reaped, "tick");
+        reaped
+    }
+
+    /// 收割cq中的cqe
+    fn reap(&mut self) -> usize {
         let mut cq = self.uring.completion();
         cq.sync();
+        let reaped = cq.len();
 
-        /// 收获内核中已经完成的请求
+        // 收获内核中已经完成的请求
         for cqe in cq {
             let index = cqe.user_data();
             match index {
+                EVENTFD_USERDATA => self.eventfd_installed = false,
+                TIMEOUT_USERDATA if cqe.result() == -libc::ETIME => self.metrics.timeout_wakeups += 1,
                 _ if index >= MIN_REVERSED_USERDATA => {},
-                _ => self.ops.complete(index as usize, get_cqe_result(&cqe), cqe.flags()),
+                _ if index & LINK_TIMEOUT_FLAG != 0 => self.ops.complete_link_timeout(index, cqe.result()),
+                _ => {
+                    if cqe.result() < 0 {
+                        *self.metrics.errors.entry(-cqe.result()).or_insert(0) += 1;
+                    }
+                    self.ops.complete(index as usize, get_cqe_result(&cqe), cqe.flags())
+                }
             }
         }
+        self.metrics.cqes += reaped as u64;
+        reaped
     }
 
-    /// 提交任务
+    /// 提交任务，积压的sqe会在sq有空间后继续提交
     fn submit(&mut self) -> io::Result<()> {
         loop {
             match self.uring.submit() {
-                Err(e) => {
-                    if e.kind() == io::ErrorKind::Other || e.kind() == io::ErrorKind::ResourceBusy {
-                        self.tick();
+                Ok(_) => {
+                    if !self.flush_backlog() {
+                        return Ok(());
                     }
                 }
-                Ok(_) => {
-                    return Ok(());
+                Err(e) if e.raw_os_error() == Some(libc::EBUSY) || e.raw_os_error() == Some(libc::EAGAIN) => {
+                    // cq满了或者内核暂时没有资源，收割完成事件后重试，没有可以收割的事件时交给调用者
+                    if self.tick() == 0 {
+                        return Err(e);
+                    }
                 }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// 把积压的sqe放入sq，返回是否放入了sqe
+    fn flush_backlog(&mut self) -> bool {
+        let mut flushed = false;
+        while let Some(entries) = self.backlog.front() {
+            if unsafe { self.uring.submission().push_multiple(entries) }.is_err() {
+                break;
+            }
+            self.backlog.pop_front();
+            flushed = true;
+        }
+        flushed
+    }
+
+    /// 把一组sqe放入sq，sq满了时先提交一次，仍然放不下时放入积压队列，
+    /// op的future照常等待cqe，sqe在下一次提交时放入sq。opcodes和entries一一对应
+    fn push(&mut self, opcodes: &[u8], entries: &[squeue::Entry]) {
+        for &opcode in opcodes {
+            self.metrics.sqes[opcode as usize] += 1;
+        }
+        if self.backlog.is_empty() {
+            if unsafe { self.uring.submission().push_multiple(entries) }.is_ok() {
+                return;
+            }
+            if self.submit().is_ok() && unsafe { self.uring.submission().push_multiple(entries) }.is_ok() {
+                return;
             }
         }
+        self.backlog.push_back(entries.to_vec());
+    }
+
+    /// 放入一个取消user_data对应操作的sqe
+    fn push_cancel(&mut self, user_data: u64) {
+        let cancel = opcode::AsyncCancel::new(user_data).build().user_data(CANCEL_USERDATA);
+        trace_event!(user_data, "cancel");
+        self.metrics.cancels += 1;
+        self.push(&[opcode::AsyncCancel::CODE], &[cancel]);
+    }
+
+    /// 还没有收到cqe的op数量，包括eventfd读操作
+    fn outstanding(&self) -> usize {
+        self.ops.in_flight().count() + self.eventfd_installed as usize
     }
 
     /// 创建新io操作op
     fn new_op<T>(data: T, inner: &mut UringInner, driver: Inner) -> Op<T> {
         Op {
             driver,
-            index: inner.ops.insert() as usize,
+            index: inner.ops.insert(),
             data: Some(data),
+            timeout: None,
+            trace: OpTrace::new(),
+            cancel: None,
         }
     }
 
-    /// 提交任务和data
-    pub(crate) fn submit_with_data<T>(this: &Rc<UnsafeCell<UringInner>>, data: T) -> io::Result<Op<T>>
+    /// 提交任务和data，失败时返回data
+    pub(crate) fn submit_with_data<T>(this: &Rc<UnsafeCell<UringInner>>, data: T) -> Result<Op<T>, (io::Error, T)>
     where
         T: OpAble,
     {
-        let mut inner = unsafe { &mut *this.get() };
-        // 如果提交队列满了，就提交所有事件给linux内核
-        if inner.uring.submission().is_full() {
-            inner.submit()?;
+        let mut data = data;
+        let inner = unsafe { &mut *this.get() };
+        if let Err(e) = inner.ops.check_capacity() {
+            return Err((e, data));
+        }
+        let opcode = data.opcode();
+        if let Err(e) = inner.features.check(opcode) {
+            return Inner::Uring(this.clone()).blocking_fallback(data, e);
         }
 
         // 创建新的OP操作
-        let mut op = Self::new_op(data, inner, Inner(this.clone()));
+        let mut op = Self::new_op(data, inner, Inner::Uring(this.clone()));
 
         // 创建SQE
         let data = unsafe { op.data.as_mut().unwrap_unchecked() };
         // 通过sqe中的 user_data 字段索引存入ops中的Operation
         let sqe = data.uring_op().user_data(op.index as _);
+        op.trace.submitted(opcode, data.fd(), op.index as _);
 
-        // 取得sq
-        let mut sq = inner.uring.submission();
-        unsafe {
-            // 讲sqe放入sq中
-            if sq.push(&sqe).is_err() {
-                panic!("push sqe error!");
+        // 讲sqe放入sq中
+        inner.push(&[opcode], &[sqe]);
+        Ok(op)
+    }
+
+    /// 提交任务和data，并在后面链接一个超时操作，两个sqe一起放入sq
+    pub(crate) fn submit_with_timeout<T>(
+        this: &Rc<UnsafeCell<UringInner>>,
+        data: T,
+        timeout: Duration,
+    ) -> Result<Op<T>, (io::Error, T)>
+    where
+        T: OpAble,
+    {
+        let mut data = data;
+        let inner = unsafe { &mut *this.get() };
+        if let Err(e) = inner.ops.check_capacity() {
+            return Err((e, data));
+        }
+        let opcode = data.opcode();
+        for code in [opcode, opcode::LinkTimeout::CODE] {
+            if let Err(e) = inner.features.check(code) {
+                return Err((e, data));
             }
         }
 
+        let mut op = Self::new_op(data, inner, Inner::Uring(this.clone()));
+        let timespec = op.timeout.insert(Box::new(timespec(timeout)));
+        let link_timeout = opcode::LinkTimeout::new(&**timespec)
+            .build()
+            .user_data(op.index as u64 | LINK_TIMEOUT_FLAG);
+
+        let data = unsafe { op.data.as_mut().unwrap_unchecked() };
+        let sqe = data
+            .uring_op()
+            .flags(squeue::Flags::IO_LINK)
+            .user_data(op.index as _);
+        op.trace.submitted(opcode, data.fd(), op.index as _);
+
+        // op和超时不能拆到两次提交中
+        inner.push(&[opcode, opcode::LinkTimeout::CODE], &[sqe, link_timeout]);
         Ok(op)
     }
 
+    /// 创建op并生成带user_data的sqe，由调用者负责放入sq
+    pub(crate) fn prepare_op<T>(this: &Rc<UnsafeCell<UringInner>>, data: T) -> (Op<T>, squeue::Entry)
+    where
+        T: OpAble,
+    {
+        let inner = unsafe { &mut *this.get() };
+        let mut op = Self::new_op(data, inner, Inner::Uring(this.clone()));
+        let data = unsafe { op.data.as_mut().unwrap_unchecked() };
+        let sqe = data.uring_op().user_data(op.index as _);
+        op.trace.submitted(data.opcode(), data.fd(), op.index as _);
+        (op, sqe)
+    }
+
+    /// 给除了最后一个以外的sqe加上链接的flag，一起放入sq。
+    /// 空间不够时先提交已有的sqe，保证链不会被拆开
+    pub(crate) fn submit_chain(
+        this: &Rc<UnsafeCell<UringInner>>,
+        opcodes: &[u8],
+        entries: Vec<squeue::Entry>,
+        flag: squeue::Flags,
+    ) -> io::Result<()> {
+        let inner = unsafe { &mut *this.get() };
+        if entries.len() > inner.uring.submission().capacity() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "chain is longer than the submission queue",
+            ));
+        }
+        for &opcode in opcodes {
+            inner.features.check(opcode)?;
+        }
+        let last = entries.len().saturating_sub(1);
+        let entries = entries
+            .into_iter()
+            .enumerate()
+            .map(|(i, entry)| if i < last { entry.flags(flag) } else { entry })
+            .collect::<Vec<_>>();
+        inner.push(opcodes, &entries);
+        Ok(())
+    }
+
+    /// 提交multishot操作，一个sqe会返回多个cqe
+    pub(crate) fn submit_multishot<T>(this: &Rc<UnsafeCell<UringInner>>, data: T) -> io::Result<MultishotOp<T>>
+    where
+        T: OpAble,
+    {
+        let mut data = data;
+        let inner = unsafe { &mut *this.get() };
+        inner.ops.check_capacity()?;
+        let opcode = data.opcode();
+        inner.features.check(opcode)?;
+
+        let mut op = MultishotOp {
+            driver: Inner::Uring(this.clone()),
+            index: inner.ops.insert_multishot(data.release()),
+            data: Some(data),
+        };
+        let data = unsafe { op.data.as_mut().unwrap_unchecked() };
+        let sqe = data.uring_op().user_data(op.index as _);
+        inner.push(&[opcode], &[sqe]);
+        Ok(op)
+    }
+
+    /// 轮询multishot操作
+    pub(crate) fn poll_multishot(
+        this: &Rc<UnsafeCell<UringInner>>,
+        index: usize,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<CompletionMeta>> {
+        let inner = unsafe { &mut *this.get() };
+        let lifecycle = unsafe { inner.ops.get(index).unwrap_unchecked() };
+        lifecycle.poll_multishot(cx)
+    }
+
+    /// 内核结束multishot操作后，用同一个索引重新提交
+    pub(crate) fn rearm_multishot<T: OpAble>(
+        this: &Rc<UnsafeCell<UringInner>>,
+        index: usize,
+        data: &mut T,
+    ) -> io::Result<()> {
+        let inner = unsafe { &mut *this.get() };
+        unsafe { inner.ops.get(index).unwrap_unchecked() }.rearm();
+        let sqe = data.uring_op().user_data(index as _);
+        inner.push(&[data.opcode()], &[sqe]);
+        Ok(())
+    }
+
+    /// 注册provided buffer ring，ring_addr指向的内存在注销前必须有效
+    pub(crate) fn register_buf_ring(
+        this: &Rc<UnsafeCell<UringInner>>,
+        ring_addr: u64,
+        entries: u16,
+        bgid: u16,
+    ) -> io::Result<()> {
+        let inner = unsafe { &*this.get() };
+        unsafe { inner.uring.submitter().register_buf_ring(ring_addr, entries, bgid) }
+    }
+
+    pub(crate) fn unregister_buf_ring(this: &Rc<UnsafeCell<UringInner>>, bgid: u16) -> io::Result<()> {
+        let inner = unsafe { &*this.get() };
+        inner.uring.submitter().unregister_buf_ring(bgid)
+    }
+
+    /// 注册固定缓冲区，iovecs指向的内存在注销前必须有效
+    pub(crate) fn register_buffers(this: &Rc<UnsafeCell<UringInner>>, iovecs: &[libc::iovec]) -> io::Result<()> {
+        let inner = unsafe { &*this.get() };
+        unsafe { inner.uring.submitter().register_buffers(iovecs) }
+    }
+
+    pub(crate) fn unregister_buffers(this: &Rc<UnsafeCell<UringInner>>) -> io::Result<()> {
+        let inner = unsafe { &*this.get() };
+        inner.uring.submitter().unregister_buffers()
+    }
+
+    /// 从固定文件表中移除一个槽位，内核在没有进行中的op后关闭文件
+    pub(crate) fn unregister_file(this: &Rc<UnsafeCell<UringInner>>, slot: u32) -> io::Result<()> {
+        let inner = unsafe { &*this.get() };
+        inner.uring.submitter().register_files_update(slot, &[-1])?;
+        Ok(())
+    }
+
+    /// 直接完成一个没有放入sq的op
+    pub(crate) fn complete_op(this: &Rc<UnsafeCell<UringInner>>, index: usize, result: io::Result<u32>) {
+        let inner = unsafe { &mut *this.get() };
+        inner.ops.complete(index, result, 0);
+    }
+
+    /// 等待slab中有空闲的位置
+    pub(crate) fn poll_capacity(this: &Rc<UnsafeCell<UringInner>>, cx: &mut Context<'_>) -> Poll<()> {
+        let inner = unsafe { &mut *this.get() };
+        inner.ops.poll_capacity(cx)
+    }
+
     /// 轮询操作
     pub(crate) fn poll_op<'a>(
         this: &Rc<UnsafeCell<UringInner>>,
@@ -232,13 +719,7 @@ impl UringInner {
         if let Some(lifecycle) = uring.ops.get(index) {
             let must_finished = lifecycle.drop_op(data);
             if !must_finished {
-                let cancel = opcode::AsyncCancel::new(index as u64).build().user_data(u64::MAX);
-                unsafe {
-                    if uring.uring.submission().push(&cancel).is_err() {
-                        uring.submit();
-                        uring.uring.submission().push(&cancel);
-                    }
-                }
+                uring.push_cancel(index as u64);
             }
         }
     }
@@ -246,18 +727,17 @@ impl UringInner {
     /// 取消操作
     pub(crate) unsafe fn cancel_op(this: &Rc<UnsafeCell<UringInner>>, index: usize) {
         let uring = unsafe { &mut (*this.get()) };
-        // 讲user_data设置为u64::MAX表示该操作已经取消
-        let cancel = opcode::AsyncCancel::new(index as u64).build().user_data(u64::MAX);
-        // 可能会因为sq满了导致放入sqe失败，提交一次在放入sqe。
-        if uring.uring.submission().push(&cancel).is_err() {
-            uring.submit();
-            uring.uring.submission().push(&cancel);
-        }
+        uring.push_cancel(index as u64);
     }
 }
 
 impl Drop for UringInner {
     fn drop(&mut self) {
+        if self.outstanding() != 0 {
+            // 内核可能还会写这些op引用的内存，只能泄漏
+            self.ops.leak();
+            std::mem::forget(std::mem::take(&mut self.eventfd_buf));
+        }
         unsafe {
             ManuallyDrop::drop(&mut self.uring);
         };
@@ -273,22 +753,34 @@ pub struct IoUringDriver {
 }
 
 impl IoUringDriver {
-    const DEFAULT_ENTRIES: u32 = 1024;
-
-    pub(crate) fn new(b: &io_uring::Builder) -> io::Result<IoUringDriver> {
-        Self::new_with_entries(b, Self::DEFAULT_ENTRIES)
-    }
+    pub(crate) const DEFAULT_ENTRIES: u32 = 1024;
+    /// 默认的cq大小是sq的倍数，高并发时大量op同时完成不容易溢出
+    pub(crate) const DEFAULT_CQ_FACTOR: u32 = 4;
 
     pub(crate) fn new_with_entries(
         uring_builder: &io_uring::Builder,
         entries_num: u32,
     ) -> io::Result<IoUringDriver> {
         let uring = ManuallyDrop::new(uring_builder.build(entries_num)?);
+        let features = Features::probe(&uring);
+
+        let eventfd = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC) };
+        if eventfd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let shared_waker = Arc::new(EventWaker::new(eventfd));
+        register_unpark(current_thread_id(), Arc::downgrade(&shared_waker));
 
         let inner = Rc::new(UnsafeCell::new(UringInner {
             ops: Ops::new(),
             uring,
-            ext_arg: uring.params().is_feature_ext_arg(),
+            features,
+            shared_waker,
+            eventfd_installed: false,
+            eventfd_buf: Box::new([0; 8]),
+            backlog: VecDeque::new(),
+            metrics: Metrics::default(),
+            shut_down: false,
         }));
         Ok(IoUringDriver{
             uring: inner,
@@ -296,6 +788,19 @@ impl IoUringDriver {
         })
     }
 
+    /// 注册有nr个空槽位的固定文件表，用于直接描述符
+    pub(crate) fn register_files_sparse(&self, nr: u32) -> io::Result<()> {
+        let inner = unsafe { &*self.uring.get() };
+        inner.uring.submitter().register_files_sparse(nr)
+    }
+
+    /// 设置slab中op数量的上限，达到上限后直接提交的op返回ErrorKind::ResourceBusy，
+    /// 先调用 [wait_for_capacity](crate::driver::op::wait_for_capacity) 的操作会等待
+    pub(crate) fn set_max_in_flight(&self, max: usize) {
+        let inner = unsafe { &mut *self.uring.get() };
+        inner.ops.set_max(max);
+    }
+
     /// 清理提交队列
     fn flush_space(inner: &mut UringInner, need: usize) -> io::Result<()> {
         let sq = inner.uring.submission();
@@ -306,61 +811,169 @@ impl IoUringDriver {
         Ok(())
     }
 
+    /// 把一个sqe直接放入sq，sq满了时先提交一次再重试
+    fn push_entry(inner: &mut UringInner, entry: &squeue::Entry) -> io::Result<()> {
+        if unsafe { inner.uring.submission().push(entry) }.is_ok() {
+            return Ok(());
+        }
+        inner.submit()?;
+        unsafe { inner.uring.submission().push(entry) }
+            .map_err(|_| io::Error::from_raw_os_error(libc::EBUSY))
+    }
+
     /// 加入一个超时op到sq，经过duration时间后该op会成功返回
-    fn install_timeout(&self, inner: &mut UringInner, duration: Duration) {
+    fn install_timeout(&self, inner: &mut UringInner, duration: Duration) -> io::Result<()> {
         let timespec = timespec(duration);
         unsafe {std::ptr::replace(self.timespec, timespec);}
         let entry = opcode::Timeout::new(self.timespec).build().user_data(TIMEOUT_USERDATA);
-        let mut sq = inner.uring.submission();
-        unsafe { sq.push(&entry); }
+        Self::push_entry(inner, &entry)
+    }
+
+    /// 加入eventfd的读操作，其他线程写eventfd时会唤醒阻塞的submit_and_wait
+    fn install_eventfd(inner: &mut UringInner) -> io::Result<()> {
+        let entry = opcode::Read::new(
+            types::Fd(inner.shared_waker.as_raw_fd()),
+            inner.eventfd_buf.as_mut_ptr(),
+            inner.eventfd_buf.len() as u32,
+        )
+        .build()
+        .user_data(EVENTFD_USERDATA);
+        // 放入sq失败时保持未安装，下一次park时重试
+        Self::push_entry(inner, &entry)?;
+        inner.eventfd_installed = true;
+        Ok(())
+    }
+
+    /// 调用其他线程投递过来的waker，返回是否有waker
+    fn wake_remote(inner: &mut UringInner) -> bool {
+        let wakers = inner.shared_waker.take_wakers();
+        let woken = !wakers.is_empty();
+        for waker in wakers {
+            waker.wake();
+        }
+        woken
     }
 
-    /// TODO
     fn inner_park(&self, timeout: Option<Duration>) -> io::Result<()> {
         let inner = unsafe {&mut *(self.uring.get())};
-        let mut need_wait = true;
+        let mut need_wait = !Self::wake_remote(inner);
         if need_wait {
+            // 先标记即将阻塞再检查一次，其他线程在此之后投递的waker一定会写eventfd
+            inner.shared_waker.awake.store(false, Ordering::SeqCst);
+            if Self::wake_remote(inner) {
+                need_wait = false;
+            }
+        }
+        let result = self.wait(inner, timeout, need_wait);
+        inner.shared_waker.awake.store(true, Ordering::SeqCst);
+        result?;
+
+        // Process CQ
+        inner.tick();
+        Ok(())
+    }
+
+    /// 提交sq，need_wait时阻塞等待至少一个cqe或者超时
+    fn wait(&self, inner: &mut UringInner, timeout: Option<Duration>, need_wait: bool) -> io::Result<()> {
+        if need_wait {
+            // 积压的sqe必须在阻塞之前提交，否则可能一直等不到cqe
+            if !inner.backlog.is_empty() {
+                inner.submit()?;
+            }
             let mut space = 0;
+            if !inner.eventfd_installed {
+                space += 1;
+            }
             if timeout.is_some() {
                 space += 1;
             }
             if space != 0 {
                 Self::flush_space(inner, space)?;
             }
-            if let Some(duration) = timeout {
-                match inner.ext_arg {
-                    false => {
-                        self.install_timeout(inner, duration);
-                        inner.uring.submit_and_wait(1)?; // 提交sq并且等待一个OP完成
-                    },
-                    true => {
-                        let timespec = timespec(duration);
-                        let args = io_uring::types::SubmitArgs::new().timespec(&timespec);
-                        if let Err(e) = inner.uring.submitter().submit_with_args(1, &args) {
-                            if e.raw_os_error() != Some(libc::ETIME) {
-                                return Err(e);
-                            }
-                        }
-                    }
-                }
-            } else {
-                // 提交并且等待一个OP完成
-                inner.uring.submit_and_wait(1)?;
+            if !inner.eventfd_installed {
+                Self::install_eventfd(inner)?;
             }
+            trace_event!(?timeout, "park");
+            inner.metrics.parks += 1;
+            let start = Instant::now();
+            let result = match timeout {
+                Some(duration) => self.submit_and_wait_timeout(inner, duration),
+                // 提交并且等待一个OP完成
+                None => inner.uring.submit_and_wait(1).map(|_| ()),
+            };
+            inner.metrics.submit_wait_time += start.elapsed();
+            result?;
         } else {
             // 直接提交
-            inner.uring.submit()?;
+            inner.submit()?;
         }
+        Ok(())
+    }
 
-        // Process CQ
-        inner.tick();
+    /// 提交sq并且等待一个OP完成或者超时，没有ext_arg时需要预留一个sqe给超时op
+    fn submit_and_wait_timeout(&self, inner: &mut UringInner, duration: Duration) -> io::Result<()> {
+        match inner.features.ext_arg() {
+            false => {
+                self.install_timeout(inner, duration)?;
+                inner.uring.submit_and_wait(1)?;
+            },
+            true => {
+                let timespec = timespec(duration);
+                let args = io_uring::types::SubmitArgs::new().timespec(&timespec);
+                match inner.uring.submitter().submit_with_args(1, &args) {
+                    Ok(_) => {
+                        // 同时提交了sqe时内核返回提交的数量而不是ETIME，只能通过cq是否为空判断
+                        let mut cq = inner.uring.completion();
+                        cq.sync();
+                        if cq.is_empty() {
+                            inner.metrics.timeout_wakeups += 1;
+                        }
+                    }
+                    Err(e) if e.raw_os_error() == Some(libc::ETIME) => inner.metrics.timeout_wakeups += 1,
+                    Err(e) => return Err(e),
+                }
+            }
+        }
         Ok(())
     }
+
+    /// 取消所有进行中的op并等待它们的cqe，最多等待timeout，返回超时后仍未完成的op数量
+    fn inner_shutdown(&self, timeout: Duration) -> io::Result<usize> {
+        let inner = unsafe { &mut *self.uring.get() };
+        let deadline = Instant::now() + timeout;
+        // 积压的sqe先提交，否则取消操作找不到它们
+        inner.submit()?;
+        // push_cancel可能提交并收割cqe，每次重新查找位置上的op而不是持有迭代器
+        for slot in 0..inner.ops.slots() {
+            if let Some(key) = inner.ops.in_flight_key(slot) {
+                inner.push_cancel(key as u64);
+            }
+        }
+        if inner.eventfd_installed {
+            inner.push_cancel(EVENTFD_USERDATA);
+        }
+        inner.shut_down = true;
+
+        loop {
+            let outstanding = inner.outstanding();
+            let now = Instant::now();
+            if outstanding == 0 || now >= deadline {
+                // 超时为0时取消操作也要交给内核
+                inner.submit()?;
+                return Ok(outstanding);
+            }
+            if !inner.features.ext_arg() {
+                Self::flush_space(inner, 1)?;
+            }
+            self.submit_and_wait_timeout(inner, deadline - now)?;
+            inner.tick();
+        }
+    }
 }
 
 impl Driver for IoUringDriver {
     fn with<R>(&self, f: impl FnOnce() -> R) -> R {
-        let inner = Inner(self.uring.clone());
+        let inner = Inner::Uring(self.uring.clone());
         CURRENT.set(&inner, f)
     }
 
@@ -378,6 +991,29 @@ impl Driver for IoUringDriver {
     fn park_timeout(&self, duration: Duration) -> io::Result<()> {
         self.inner_park(Some(duration))
     }
+
+    fn shutdown_timeout(&self, timeout: Duration) -> io::Result<usize> {
+        self.inner_shutdown(timeout)
+    }
+
+    fn features(&self) -> Features {
+        let inner = unsafe { &*self.uring.get() };
+        inner.features
+    }
+
+    fn metrics(&self) -> Metrics {
+        let inner = unsafe { &*self.uring.get() };
+        let mut metrics = inner.metrics.clone();
+        metrics.in_flight = inner.ops.len();
+        metrics
+    }
+
+    type Unpark = UnparkHandle;
+
+    fn unpark(&self) -> Self::Unpark {
+        let inner = unsafe { &*self.uring.get() };
+        UnparkHandle(Arc::downgrade(&inner.shared_waker))
+    }
 }
 
 impl AsRawFd for IoUringDriver {
@@ -388,10 +1024,17 @@ impl AsRawFd for IoUringDriver {
 
 impl Drop for IoUringDriver {
     fn drop(&mut self) {
+        // 运行时已经取消过op并按照它的超时等待过，这里不再重复取消。
+        // 没有经过运行时关闭的driver只提交取消而不阻塞，没有完成的op会被泄漏
+        if !unsafe { &*self.uring.get() }.shut_down {
+            let _ = self.inner_shutdown(Duration::ZERO);
+        }
         // 释放时间结构体内存。
         unsafe {
             std::ptr::drop_in_place(self.timespec);
         };
+        let inner = unsafe { &*self.uring.get() };
+        unregister_unpark(current_thread_id(), &Arc::downgrade(&inner.shared_waker));
     }
 }
 
@@ -404,3 +1047,289 @@ fn get_cqe_result(cqe: &cqueue::Entry) -> io::Result<u32> {
         Err(io::Error::from_raw_os_error(-res))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::os::unix::io::RawFd;
+    use std::rc::Rc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake, Waker};
+    use std::time::{Duration, Instant};
+
+    use io_uring::opcode;
+
+    use super::lifecycle::Lifecycle;
+    use super::{IoUringDriver, Ops, CANCEL_USERDATA};
+    use crate::buf::{IoBuf, IoBufMut};
+    use crate::driver::mock::runtime;
+    use crate::driver::op::{MultishotOp, Op};
+    use crate::driver::shared_fd::SharedFd;
+    use crate::driver::{Driver, Unpark};
+
+    const IORING_CQE_F_MORE: u32 = 1 << 1;
+
+    /// drop时设置标志的缓冲区，用来检查内核可能还在写的内存什么时候被释放
+    #[derive(Debug)]
+    struct TrackedBuf {
+        buf: Vec<u8>,
+        dropped: Rc<Cell<bool>>,
+    }
+
+    impl TrackedBuf {
+        fn new() -> (TrackedBuf, Rc<Cell<bool>>) {
+            let dropped = Rc::new(Cell::new(false));
+            (TrackedBuf { buf: Vec::with_capacity(8), dropped: dropped.clone() }, dropped)
+        }
+    }
+
+    impl Drop for TrackedBuf {
+        fn drop(&mut self) {
+            self.dropped.set(true);
+        }
+    }
+
+    unsafe impl IoBuf for TrackedBuf {
+        fn read_ptr(&self) -> *const u8 {
+            self.buf.as_ptr()
+        }
+
+        fn bytes_init(&self) -> usize {
+            self.buf.len()
+        }
+    }
+
+    unsafe impl IoBufMut for TrackedBuf {
+        fn write_ptr(&mut self) -> *mut u8 {
+            self.buf.as_mut_ptr()
+        }
+
+        fn bytes_total(&mut self) -> usize {
+            self.buf.capacity()
+        }
+
+        unsafe fn set_init(&mut self, pos: usize) {
+            self.buf.set_len(pos);
+        }
+    }
+
+    #[test]
+    fn completion_before_poll() {
+        let (rt, mock) = runtime();
+        let op = rt.enter(|| Op::read_at(&SharedFd::new_without_register(-1), Vec::new(), 0).unwrap());
+        let user_data = mock.take_submitted()[0].user_data;
+        // cqe在第一次poll之前到达：Submitted -> Completed
+        mock.complete(user_data, -libc::EIO, 0);
+        rt.step();
+        assert_eq!(mock.live_ops(), 1);
+
+        rt.enter(|| {
+            crate::spawn(async move {
+                let (res, _) = op.read().await;
+                assert_eq!(res.unwrap_err().raw_os_error(), Some(libc::EIO));
+            })
+        });
+        rt.step();
+        assert_eq!(mock.live_ops(), 0);
+    }
+
+    /// 返回pipe的(读端, 写端)，写端交给被测代码关闭
+    fn pipe() -> (RawFd, RawFd) {
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) }, 0);
+        (fds[0], fds[1])
+    }
+
+    /// 写端关闭后读端读到EOF
+    fn assert_closed(rx: RawFd) {
+        let mut buf = [0u8; 1];
+        assert_eq!(unsafe { libc::read(rx, buf.as_mut_ptr() as *mut libc::c_void, 1) }, 0);
+        unsafe { libc::close(rx) };
+    }
+
+    #[test]
+    fn dropped_multishot_is_cancelled() {
+        let (rt, mock) = runtime();
+        let op = rt.enter(|| MultishotOp::accept_multi(&SharedFd::new_without_register(-1)).unwrap());
+        let user_data = mock.take_submitted()[0].user_data;
+        // accept到的连接还没有被取走
+        let (queued_rx, queued_tx) = pipe();
+        mock.complete(user_data, queued_tx, IORING_CQE_F_MORE);
+        rt.step();
+
+        // 还在运行的multishot被drop后提交AsyncCancel，最后一个cqe到达前不能释放
+        rt.enter(|| drop(op));
+        let sqes = mock.take_submitted();
+        assert_eq!(sqes[0].opcode, opcode::AsyncCancel::CODE);
+        assert_eq!(sqes[0].user_data, CANCEL_USERDATA);
+        assert_eq!(mock.live_ops(), 1);
+        assert_closed(queued_rx);
+
+        // 取消生效之前又accept到的连接也要关闭
+        let (late_rx, late_tx) = pipe();
+        mock.complete(user_data, late_tx, IORING_CQE_F_MORE);
+        mock.complete(user_data, -libc::ECANCELED, 0);
+        rt.step();
+        assert_eq!(mock.live_ops(), 0);
+        assert_closed(late_rx);
+    }
+
+    #[test]
+    fn dropped_op_keeps_buffer_until_cqe() {
+        let (rt, mock) = runtime();
+        let (buf, dropped) = TrackedBuf::new();
+        let op = rt.enter(|| Op::read_at(&SharedFd::new_without_register(-1), buf, 0).unwrap());
+        let user_data = mock.take_submitted()[0].user_data;
+
+        // 内核还可能写缓冲区，drop后只提交取消，缓冲区交给driver保管
+        rt.enter(|| drop(op));
+        let sqes = mock.take_submitted();
+        assert_eq!(sqes.len(), 1);
+        assert_eq!(sqes[0].opcode, opcode::AsyncCancel::CODE);
+        assert!(!dropped.get());
+        assert_eq!(mock.live_ops(), 1);
+
+        mock.complete(user_data, -libc::ECANCELED, 0);
+        rt.step();
+        assert!(dropped.get());
+        assert_eq!(mock.live_ops(), 0);
+    }
+
+    #[test]
+    fn dropped_completed_op_is_released() {
+        let (rt, mock) = runtime();
+        let (buf, dropped) = TrackedBuf::new();
+        let op = rt.enter(|| Op::read_at(&SharedFd::new_without_register(-1), buf, 0).unwrap());
+        let user_data = mock.take_submitted()[0].user_data;
+        mock.complete(user_data, 4, 0);
+        rt.step();
+
+        // cqe已经到达，不需要取消，直接释放
+        rt.enter(|| drop(op));
+        assert!(mock.take_submitted().is_empty());
+        assert!(dropped.get());
+        assert_eq!(mock.live_ops(), 0);
+    }
+
+    #[test]
+    fn stale_key_does_not_complete_reused_slot() {
+        let mut ops = Ops::new();
+        let old = ops.insert();
+        ops.get(old).unwrap().remove();
+        let new = ops.insert();
+        // 复用了同一个位置，但是代数不同
+        assert_eq!(old as u32, new as u32);
+        assert_ne!(old, new);
+
+        ops.complete(old, Ok(1), 0);
+        assert!(matches!(*ops.get(new).unwrap(), Lifecycle::Submitted));
+        assert!(ops.get(old).is_none());
+    }
+
+    #[test]
+    fn zero_timeout_shutdown_submits_cancels() {
+        let Ok(driver) = IoUringDriver::new_with_entries(&io_uring::IoUring::builder(), 8) else {
+            // 内核不支持io_uring
+            return;
+        };
+        let (rx, tx) = pipe();
+        let op = driver.with(|| Op::read_at(&SharedFd::new_without_register(rx), Vec::with_capacity(8), 0).unwrap());
+        driver.submit().unwrap();
+        assert_eq!(driver.shutdown_timeout(Duration::ZERO).unwrap(), 1);
+
+        // 取消已经交给内核，sq中没有留下sqe
+        let inner = unsafe { &mut *driver.uring.get() };
+        assert!(inner.uring.submission().is_empty());
+        for _ in 0..10 {
+            if inner.outstanding() == 0 {
+                break;
+            }
+            inner.uring.submitter().submit_and_wait(1).unwrap();
+            inner.tick();
+        }
+        assert_eq!(inner.outstanding(), 0);
+        driver.with(|| drop(op));
+        unsafe { libc::close(tx) };
+    }
+
+    #[test]
+    fn unpark_wakes_parked_driver() {
+        let Ok(driver) = IoUringDriver::new_with_entries(&io_uring::IoUring::builder(), 8) else {
+            return;
+        };
+        let unpark = driver.unpark();
+        let start = Instant::now();
+        let thread = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            unpark.unpark().unwrap();
+        });
+        // 通过eventfd的读操作唤醒，不会等到超时
+        driver.park_timeout(Duration::from_secs(10)).unwrap();
+        assert!(start.elapsed() < Duration::from_secs(5));
+        thread.join().unwrap();
+
+        // driver释放后unpark什么也不做
+        let unpark = driver.unpark();
+        drop(driver);
+        unpark.unpark().unwrap();
+    }
+
+    #[test]
+    fn remote_wake_resumes_task() {
+        let mut rt = crate::RuntimeBuilder::new().build().unwrap();
+        let polls = rt.block_on(async {
+            crate::spawn(async {
+                let mut polls = 0;
+                std::future::poll_fn(|cx| {
+                    polls += 1;
+                    if polls > 1 {
+                        return Poll::Ready(());
+                    }
+                    // 在其他线程唤醒任务，waker投递回运行时所在的线程
+                    let waker = cx.waker().clone();
+                    std::thread::spawn(move || {
+                        std::thread::sleep(Duration::from_millis(20));
+                        waker.wake();
+                    });
+                    Poll::Pending
+                })
+                .await;
+                polls
+            })
+            .await
+        });
+        assert_eq!(polls.unwrap(), 2);
+    }
+
+    /// 记录被唤醒次数的waker
+    struct CountWaker(AtomicUsize);
+
+    impl Wake for CountWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn freed_slot_wakes_one_waiter() {
+        let mut ops = Ops::new();
+        ops.set_max(1);
+        let key = ops.insert();
+        let counters = (0..3).map(|_| Arc::new(CountWaker(AtomicUsize::new(0)))).collect::<Vec<_>>();
+        for counter in &counters {
+            let waker = Waker::from(counter.clone());
+            assert!(ops.poll_capacity(&mut Context::from_waker(&waker)).is_pending());
+        }
+        let woken = || counters.iter().map(|c| c.0.load(Ordering::SeqCst)).collect::<Vec<_>>();
+
+        // 每释放一个位置只唤醒最早等待的一个任务
+        ops.get(key).unwrap().remove();
+        assert_eq!(woken(), [1, 0, 0]);
+        let waker = Waker::from(counters[0].clone());
+        assert!(ops.poll_capacity(&mut Context::from_waker(&waker)).is_ready());
+        let key = ops.insert();
+        ops.get(key).unwrap().remove();
+        assert_eq!(woken(), [1, 1, 0]);
+    }
+}
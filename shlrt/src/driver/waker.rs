@@ -0,0 +1,86 @@
+use std::ffi::c_void;
+use std::os::fd::{AsRawFd, RawFd};
+use std::sync::atomic::Ordering;
+use std::sync::{Mutex, Weak};
+use std::task::Waker;
+
+use crate::driver::Unpark;
+
+/// 通过eventfd来唤醒线程
+pub(crate) struct EventWaker {
+    /// event fd
+    eventfd: RawFd,
+    /// 状态，为false时表示线程可能阻塞在park中
+    pub(crate) awake: std::sync::atomic::AtomicBool,
+    /// 其他线程投递过来，需要在本线程上调用的waker
+    wakers: Mutex<Vec<Waker>>,
+}
+
+impl EventWaker {
+    pub(crate) fn new(fd: RawFd) -> Self {
+        EventWaker {
+            eventfd: fd,
+            awake: std::sync::atomic::AtomicBool::new(true),
+            wakers: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// 唤醒操作
+    pub(crate) fn wake(&self) -> std::io::Result<()> {
+        // 已经被唤醒时，直接返回OK
+        if self.awake.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+
+        // 没有被唤醒时，需要向eventFD中写入数据
+        let buf = 0x1u64.to_ne_bytes();
+        let ret = unsafe { libc::write(self.eventfd, buf.as_ptr() as *const c_void, buf.len()) };
+        if ret < 0 {
+            let e = std::io::Error::last_os_error();
+            // 计数器已满时线程一定会被唤醒
+            if e.kind() != std::io::ErrorKind::WouldBlock {
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// 投递waker并唤醒线程
+    pub(crate) fn send_waker(&self, waker: Waker) -> std::io::Result<()> {
+        self.wakers.lock().unwrap().push(waker);
+        self.wake()
+    }
+
+    /// 取出其他线程投递的waker
+    pub(crate) fn take_wakers(&self) -> Vec<Waker> {
+        std::mem::take(&mut *self.wakers.lock().unwrap())
+    }
+}
+
+impl AsRawFd for EventWaker {
+    fn as_raw_fd(&self) -> RawFd {
+        self.eventfd
+    }
+}
+
+impl Drop for EventWaker {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.eventfd);
+        }
+    }
+}
+
+/// driver的跨线程唤醒句柄
+#[derive(Clone)]
+pub struct UnparkHandle(pub(crate) Weak<EventWaker>);
+
+impl Unpark for UnparkHandle {
+    fn unpark(&self) -> std::io::Result<()> {
+        match self.0.upgrade() {
+            Some(waker) => waker.wake(),
+            // driver已经释放
+            None => Ok(()),
+        }
+    }
+}
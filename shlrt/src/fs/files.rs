@@ -1,11 +1,14 @@
 use std::io;
 use std::path::Path;
+use std::time::Duration;
 use crate::driver::shared_fd::SharedFd;
 use crate::fs::open_option::OpenOptions;
 use std::fs::{File as StdFile};
 use std::os::fd::{AsRawFd, IntoRawFd, RawFd};
-use crate::buf::{IoBuf, IoBufMut};
-use crate::driver::op::Op;
+use crate::buf::{BufRing, BufRingEntry, IoBuf, IoBufMut};
+use crate::driver::op::{wait_for_capacity, Op};
+use crate::Chain;
+use crate::io::CancelHandle;
 
 #[derive(Debug)]
 pub struct File {
@@ -26,19 +29,62 @@ impl File {
             .await
     }
 
-    pub fn from_shared_fd(fd: SharedFd) -> File {
+    pub(crate) fn from_shared_fd(fd: SharedFd) -> File {
         File { fd }
     }
 
+    pub(crate) fn shared_fd(&self) -> &SharedFd {
+        &self.fd
+    }
+
     pub fn from_std(std: StdFile) -> io::Result<File> {
         Ok(File {
             fd: SharedFd::new(std.into_raw_fd())?,
         })
     }
 
+    /// 从pos处读取数据，传入 [FixedBuf](crate::buf::FixedBuf) 时使用ReadFixed
     pub async fn read_at<T: IoBufMut>(&self, buf: T, pos: u64) -> crate::BufResult<usize, T> {
-        let op = Op::read_at(&self.fd, buf, pos).unwrap();
-        op.read().await
+        wait_for_capacity().await;
+        match Op::read_at(&self.fd, buf, pos) {
+            Ok(op) => op.read().await,
+            Err((e, buf)) => (Err(e), buf),
+        }
+    }
+
+    /// 可以通过 [Canceller](crate::Canceller) 取消的 [File::read_at]，
+    /// 取消后返回ErrorKind::Interrupted和缓冲区
+    pub async fn cancelable_read_at<T: IoBufMut>(
+        &self,
+        buf: T,
+        pos: u64,
+        handle: &CancelHandle,
+    ) -> crate::BufResult<usize, T> {
+        wait_for_capacity().await;
+        match Op::read_at(&self.fd, buf, pos) {
+            Ok(op) => op.cancelable(handle).read().await,
+            Err((e, buf)) => (Err(e), buf),
+        }
+    }
+
+    /// timeout内没有读完时返回ErrorKind::TimedOut和缓冲区，legacy driver不支持
+    pub async fn read_at_timeout<T: IoBufMut>(
+        &self,
+        buf: T,
+        pos: u64,
+        timeout: Duration,
+    ) -> crate::BufResult<usize, T> {
+        wait_for_capacity().await;
+        match Op::read_at_timeout(&self.fd, buf, pos, timeout) {
+            Ok(op) => op.read().await,
+            Err((e, buf)) => (Err(e), buf),
+        }
+    }
+
+    /// 由内核从 [BufRing] 中挑选缓冲区，从pos处读取数据，返回None表示已经读到文件末尾
+    pub async fn read_buf_ring(&self, ring: &BufRing, pos: u64) -> io::Result<Option<BufRingEntry>> {
+        wait_for_capacity().await;
+        Op::read_buf_ring(&self.fd, ring, pos)?.read().await
     }
 
     pub async fn read_exact_at<T: IoBufMut>(&self, mut buf: T, pos: u64, ) -> crate::BufResult<(), T> {
@@ -69,9 +115,13 @@ impl File {
         (Ok(()), buf)
     }
 
+    /// 在pos处写入数据，传入 [FixedBuf](crate::buf::FixedBuf) 时使用WriteFixed
     pub async fn write_at<T: IoBuf>(&self, buf: T, pos: u64) -> crate::BufResult<usize, T> {
-        let op = Op::write_at(&self.fd, buf, pos).unwrap();
-        op.write().await
+        wait_for_capacity().await;
+        match Op::write_at(&self.fd, buf, pos) {
+            Ok(op) => op.write().await,
+            Err((e, buf)) => (Err(e), buf),
+        }
     }
 
     pub async fn write_all_at<T: IoBuf>(&self, mut buf: T, pos: u64) -> crate::BufResult<(), T> {
@@ -100,25 +150,53 @@ impl File {
         (Ok(()), buf)
     }
 
+    /// 写入后接着fdatasync，两个操作链接在一起只需要一次提交。
+    /// 需要分别得到每个操作的结果或者链接更多操作时使用 [Chain](crate::Chain)
+    pub async fn write_at_sync<T: IoBuf>(&self, buf: T, pos: u64) -> crate::BufResult<usize, T> {
+        wait_for_capacity().await;
+        let mut chain = Chain::new();
+        let write = chain.write_at(self, buf, pos);
+        let sync = chain.sync_data(self);
+        if let Err(e) = chain.submit() {
+            let (_, buf) = write.await;
+            if e.kind() != io::ErrorKind::Unsupported {
+                return (Err(e), buf);
+            }
+            // 不支持链接操作时分两次提交
+            let (res, buf) = self.write_at(buf, pos).await;
+            return match res {
+                Ok(n) => (self.sync_data().await.map(|_| n), buf),
+                Err(e) => (Err(e), buf),
+            };
+        }
+
+        let (res, buf) = write.await;
+        match (res, sync.await) {
+            (Ok(n), Ok(())) => (Ok(n), buf),
+            (Err(e), _) | (_, Err(e)) => (Err(e), buf),
+        }
+    }
+
     pub async fn sync_all(&self) -> io::Result<()> {
-        let op = Op::fsync(&self.fd).unwrap();
-        let completion = op.await;
+        wait_for_capacity().await;
+        let completion = Op::fsync(&self.fd)?.await;
 
         completion.meta.result?;
         Ok(())
     }
 
     pub async fn sync_data(&self) -> io::Result<()> {
-        let op = Op::datasync(&self.fd).unwrap();
-        let completion = op.await;
+        wait_for_capacity().await;
+        let completion = Op::datasync(&self.fd)?.await;
 
         completion.meta.result?;
         Ok(())
     }
 
+    /// 关闭文件，返回close的结果
     pub async fn close(self) -> io::Result<()> {
-        self.fd.close().await;
-        Ok(())
+        wait_for_capacity().await;
+        Op::close(&self.fd)?.closed().await
     }
 }
 
@@ -126,4 +204,216 @@ impl AsRawFd for File {
     fn as_raw_fd(&self) -> RawFd {
         self.fd.raw_fd()
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::os::fd::IntoRawFd;
+    use std::rc::Rc;
+
+    use std::time::Duration;
+
+    use io_uring::opcode;
+    use io_uring::squeue::Flags;
+
+    use super::File;
+    use crate::driver::mock::runtime;
+    use crate::driver::shared_fd::SharedFd;
+
+    #[test]
+    fn read_at_waits_for_capacity() {
+        let (rt, mock) = runtime();
+        mock.set_max_in_flight(1);
+        let results = Rc::new(RefCell::new(Vec::new()));
+        for pos in 0..2 {
+            let output = results.clone();
+            rt.enter(|| {
+                crate::spawn(async move {
+                    let file = File::from_shared_fd(SharedFd::new_without_register(-1));
+                    let (res, _) = file.read_at(Vec::with_capacity(8), pos).await;
+                    output.borrow_mut().push((pos, res.unwrap()));
+                })
+            });
+        }
+
+        // 第二个读操作等待第一个结束，而不是返回ResourceBusy或者panic
+        rt.step();
+        let sqes = mock.take_submitted();
+        assert_eq!(sqes.len(), 1);
+        assert_eq!(sqes[0].opcode, opcode::Read::CODE);
+        mock.complete(sqes[0].user_data, 3, 0);
+
+        rt.step();
+        let sqes = mock.take_submitted();
+        assert_eq!(sqes.len(), 1);
+        mock.complete(sqes[0].user_data, 5, 0);
+
+        rt.step();
+        assert_eq!(*results.borrow(), [(0, 3), (1, 5)]);
+        assert_eq!(mock.live_ops(), 0);
+    }
+
+    /// 提交一个带超时的读操作，按给定的结果完成op和链接的超时
+    fn read_with_timeout(op_res: i32, timeout_res: i32) -> std::io::Result<usize> {
+        let (rt, mock) = runtime();
+        let result = Rc::new(RefCell::new(None));
+        let output = result.clone();
+        rt.enter(|| {
+            crate::spawn(async move {
+                let file = File::from_shared_fd(SharedFd::new_without_register(-1));
+                let (res, buf) = file.read_at_timeout(Vec::with_capacity(8), 0, Duration::from_secs(1)).await;
+                assert_eq!(buf.capacity(), 8);
+                *output.borrow_mut() = Some(res);
+            })
+        });
+
+        rt.step();
+        let sqes = mock.take_submitted();
+        assert_eq!(sqes[0].flags, Flags::IO_LINK.bits());
+        assert_eq!(sqes[1].opcode, opcode::LinkTimeout::CODE);
+        mock.complete(sqes[0].user_data, op_res, 0);
+        mock.complete(sqes[1].user_data, timeout_res, 0);
+        rt.step();
+        assert_eq!(mock.live_ops(), 0);
+        let res = result.borrow_mut().take().unwrap();
+        res
+    }
+
+    #[test]
+    fn read_at_timeout() {
+        assert_eq!(read_with_timeout(4, -libc::ECANCELED).unwrap(), 4);
+        let err = read_with_timeout(-libc::ECANCELED, -libc::ETIME).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+        // 超时没有到期时的ECANCELED（例如被其他取消）不能被当成超时
+        let err = read_with_timeout(-libc::ECANCELED, -libc::ECANCELED).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::ECANCELED));
+    }
+
+    #[test]
+    fn sync_all_falls_back_to_blocking_pool() {
+        let path = std::env::temp_dir().join(format!("shlrt-fsync-{}", std::process::id()));
+        let fd = std::fs::File::create(&path).unwrap().into_raw_fd();
+        let (rt, mock) = runtime();
+        mock.set_unsupported(opcode::Fsync::CODE);
+        let result = Rc::new(RefCell::new(None));
+        let output = result.clone();
+        rt.enter(|| {
+            crate::spawn(async move {
+                let file = File::from_shared_fd(SharedFd::new_without_register(fd));
+                *output.borrow_mut() = Some(file.sync_all().await);
+            })
+        });
+
+        // 线程池完成后通过跨线程唤醒回到运行时
+        for _ in 0..1000 {
+            rt.step();
+            if result.borrow().is_some() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert!(mock.take_submitted().is_empty());
+        result.borrow_mut().take().unwrap().unwrap();
+        assert_eq!(mock.live_ops(), 0);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn unsupported_read_is_rejected() {
+        let (rt, mock) = runtime();
+        mock.set_unsupported(opcode::Read::CODE);
+        let result = Rc::new(RefCell::new(None));
+        let output = result.clone();
+        rt.enter(|| {
+            crate::spawn(async move {
+                let file = File::from_shared_fd(SharedFd::new_without_register(-1));
+                let (res, buf) = file.read_at(Vec::with_capacity(8), 0).await;
+                assert_eq!(buf.capacity(), 8);
+                *output.borrow_mut() = Some(res);
+            })
+        });
+
+        // 读操作需要等待fd就绪，不能放到线程池中执行
+        rt.step();
+        assert!(mock.take_submitted().is_empty());
+        let err = result.borrow_mut().take().unwrap().unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::Unsupported);
+        assert_eq!(mock.live_ops(), 0);
+    }
+
+    #[test]
+    fn read_exact_at_resubmits_short_reads() {
+        let (rt, mock) = runtime();
+        let result = Rc::new(RefCell::new(None));
+        let output = result.clone();
+        rt.enter(|| {
+            crate::spawn(async move {
+                let file = File::from_shared_fd(SharedFd::new_without_register(-1));
+                let (res, _) = file.read_exact_at(Box::new([0u8; 8]), 0).await;
+                *output.borrow_mut() = Some(res);
+            })
+        });
+
+        rt.step();
+        let sqes = mock.take_submitted();
+        assert_eq!(sqes.len(), 1);
+        assert_eq!(sqes[0].opcode, opcode::Read::CODE);
+        mock.complete(sqes[0].user_data, 3, 0);
+
+        rt.step();
+        let sqes = mock.take_submitted();
+        assert_eq!(sqes.len(), 1);
+        mock.complete(sqes[0].user_data, 5, 0);
+
+        rt.step();
+        assert!(matches!(result.borrow_mut().take(), Some(Ok(()))));
+        assert_eq!(mock.live_ops(), 0);
+    }
+
+    #[test]
+    fn write_all_at_reports_write_zero() {
+        let (rt, mock) = runtime();
+        let result = Rc::new(RefCell::new(None));
+        let output = result.clone();
+        rt.enter(|| {
+            crate::spawn(async move {
+                let file = File::from_shared_fd(SharedFd::new_without_register(-1));
+                let (res, _) = file.write_all_at(vec![1u8; 4], 0).await;
+                *output.borrow_mut() = Some(res);
+            })
+        });
+
+        rt.step();
+        let sqes = mock.take_submitted();
+        assert_eq!(sqes[0].opcode, opcode::Write::CODE);
+        mock.complete(sqes[0].user_data, 0, 0);
+
+        rt.step();
+        let err = result.borrow_mut().take().unwrap().unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::WriteZero);
+    }
+
+    #[test]
+    fn direct_open() {
+        let mut rt = crate::RuntimeBuilder::new().with_fixed_files(4).build().unwrap();
+        if rt.features().is_legacy() {
+            return;
+        }
+        let path = std::env::temp_dir().join(format!("shlrt-direct-{}", std::process::id()));
+        rt.block_on(async {
+            let file = crate::fs::OpenOptions::new()
+                .write(true)
+                .create(true)
+                .direct(true)
+                .open(&path)
+                .await
+                .unwrap();
+            assert!(matches!(file.shared_fd().kind(), crate::driver::shared_fd::FdKind::Fixed(_)));
+            let (res, _) = file.write_at(b"direct".to_vec(), 0).await;
+            assert_eq!(res.unwrap(), 6);
+            file.close().await.unwrap();
+        });
+        assert_eq!(std::fs::read(&path).unwrap(), b"direct");
+        std::fs::remove_file(path).unwrap();
+    }
+}
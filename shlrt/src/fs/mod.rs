@@ -1,9 +1,10 @@
 use std::io;
 use std::path::Path;
 use crate::buf::IoBuf;
-use crate::fs::files::File;
+pub use files::File;
 
 mod open_option;
+pub use open_option::OpenOptions;
 mod files;
 
 pub async fn read<P: AsRef<Path>>(path: P) -> io::Result<Vec<u8>> {
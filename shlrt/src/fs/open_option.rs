@@ -1,9 +1,8 @@
 use crate::fs::files::File;
-use std::future::Future;
 use std::io;
 use std::os::unix::fs::OpenOptionsExt;
 use std::path::Path;
-use crate::driver::Op;
+use crate::driver::op::Op;
 use crate::driver::shared_fd::SharedFd;
 
 macro_rules! open_options_setter {
@@ -23,12 +22,15 @@ pub struct OpenOptions {
     truncate: bool,
     create: bool,
     create_new: bool,
-    mode: libc::mode_t,
-    custom_flags: libc::c_int,
+    pub(crate) mode: libc::mode_t,
+    pub(crate) custom_flags: libc::c_int,
+    /// 打开为直接描述符
+    pub(crate) direct: bool,
 }
 
 impl OpenOptions {
-    pub(crate) fn new() -> OpenOptions {
+    /// 所有选项都关闭，至少需要打开read、write或者append中的一个
+    pub fn new() -> OpenOptions {
         OpenOptions {
             read: false,
             write: false,
@@ -38,6 +40,7 @@ impl OpenOptions {
             create_new: false,
             mode: 0o666,
             custom_flags: 0,
+            direct: false,
         }
     }
 
@@ -48,7 +51,14 @@ impl OpenOptions {
     open_options_setter!(create);
     open_options_setter!(create_new);
 
-    fn access_mode(&self) -> io::Result<libc::c_int> {
+    /// 打开为io_uring的直接描述符，文件只存在于固定文件表中，没有普通的fd。
+    /// 需要先通过 [RuntimeBuilder::with_fixed_files](crate::RuntimeBuilder::with_fixed_files) 注册固定文件表
+    pub fn direct(&mut self, direct: bool) -> &mut OpenOptions {
+        self.direct = direct;
+        self
+    }
+
+    pub(crate) fn access_mode(&self) -> io::Result<libc::c_int> {
         match (self.read, self.write, self.append) {
             (true, false, false) => Ok(libc::O_RDONLY),
             (false, true, false) => Ok(libc::O_WRONLY),
@@ -59,7 +69,7 @@ impl OpenOptions {
         }
     }
 
-    fn creation_mode(&self) -> io::Result<libc::c_int> {
+    pub(crate) fn creation_mode(&self) -> io::Result<libc::c_int> {
         match (self.write, self.append) {
             (true, false) => {}
             (false, false) => {
@@ -83,16 +93,24 @@ impl OpenOptions {
         })
     }
 
-    pub fn open(&self, path: impl AsRef<Path>) -> impl Future<Output=io::Result<File>> {
-        async {
-            let op = Op::open(path.as_ref(), self)?;
+    pub async fn open(&self, path: impl AsRef<Path>) -> io::Result<File> {
+        let op = Op::open(path.as_ref(), self)?;
 
-            let completion = op.await;
+        let completion = op.await;
+        let fd = completion.meta.result?;
 
-            Ok(File::from_shared_fd(SharedFd::new_without_register(
-                completion.meta.result? as _,
-            )))
-        }
+        let fd = if completion.data.direct {
+            SharedFd::new_fixed(fd)
+        } else {
+            SharedFd::new_without_register(fd as _)
+        };
+        Ok(File::from_shared_fd(fd))
+    }
+}
+
+impl Default for OpenOptions {
+    fn default() -> OpenOptions {
+        OpenOptions::new()
     }
 }
 
@@ -0,0 +1,178 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::rc::Rc;
+
+use crate::driver::op::OpCanceller;
+use crate::driver;
+
+/// 主动取消一组io操作。把 [Canceller::handle] 传给可取消的读写操作，
+/// 调用 [Canceller::cancel] 后这些操作以`ErrorKind::Interrupted`完成，缓冲区照常返回。
+///
+/// 取消之后再传入handle的操作会在提交后立即被取消
+#[derive(Default)]
+pub struct Canceller {
+    shared: Rc<RefCell<Shared>>,
+}
+
+/// [Canceller] 的句柄，可以clone后传给多个操作
+#[derive(Clone)]
+pub struct CancelHandle {
+    shared: Rc<RefCell<Shared>>,
+}
+
+#[derive(Default)]
+struct Shared {
+    canceled: bool,
+    /// 还没有完成的op
+    ops: HashSet<OpCanceller>,
+}
+
+impl Canceller {
+    pub fn new() -> Canceller {
+        Canceller::default()
+    }
+
+    /// 取消句柄
+    pub fn handle(&self) -> CancelHandle {
+        CancelHandle {
+            shared: self.shared.clone(),
+        }
+    }
+
+    /// 为所有注册了句柄并且还没有完成的op提交AsyncCancel。
+    /// 内核不保证取消一定成功，已经在执行的操作仍可能正常完成
+    pub fn cancel(&self) {
+        let ops = {
+            let mut shared = self.shared.borrow_mut();
+            shared.canceled = true;
+            std::mem::take(&mut shared.ops)
+        };
+        for op in ops {
+            cancel_op(op);
+        }
+    }
+
+    /// 是否已经调用过 [Canceller::cancel]
+    pub fn is_canceled(&self) -> bool {
+        self.shared.borrow().canceled
+    }
+}
+
+impl CancelHandle {
+    /// 对应的 [Canceller] 是否已经取消
+    pub fn is_canceled(&self) -> bool {
+        self.shared.borrow().canceled
+    }
+
+    /// 把op注册到句柄上，已经取消时直接取消这个op
+    pub(crate) fn register(&self, op: OpCanceller) -> CancelRegistration {
+        let mut shared = self.shared.borrow_mut();
+        if shared.canceled {
+            cancel_op(op.clone());
+        } else {
+            shared.ops.insert(op.clone());
+        }
+        CancelRegistration {
+            handle: self.clone(),
+            op,
+        }
+    }
+}
+
+/// op持有的注册信息，op完成或者drop时从句柄上移除，避免取消复用了同一个index的新op
+pub(crate) struct CancelRegistration {
+    handle: CancelHandle,
+    op: OpCanceller,
+}
+
+impl CancelRegistration {
+    pub(crate) fn is_canceled(&self) -> bool {
+        self.handle.is_canceled()
+    }
+}
+
+impl Drop for CancelRegistration {
+    fn drop(&mut self) {
+        self.handle.shared.borrow_mut().ops.remove(&self.op);
+    }
+}
+
+fn cancel_op(op: OpCanceller) {
+    // 不在运行时中时op已经无法推进，不需要取消
+    if driver::CURRENT.is_set() {
+        driver::CURRENT.with(|inner| unsafe { inner.cancel_op(op) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use io_uring::opcode;
+
+    use super::Canceller;
+    use crate::driver::mock::runtime;
+    use crate::driver::op::Op;
+    use crate::driver::shared_fd::SharedFd;
+    use crate::fs::File;
+
+    #[test]
+    fn canceller_interrupts_read() {
+        let (rt, mock) = runtime();
+        let canceller = Canceller::new();
+        let handle = canceller.handle();
+        let result = Rc::new(RefCell::new(None));
+        let output = result.clone();
+        rt.enter(|| {
+            crate::spawn(async move {
+                let file = File::from_shared_fd(SharedFd::new_without_register(-1));
+                let out = file.cancelable_read_at(Vec::with_capacity(16), 0, &handle).await;
+                *output.borrow_mut() = Some(out);
+            })
+        });
+
+        rt.step();
+        let read = mock.take_submitted()[0].user_data;
+        rt.enter(|| canceller.cancel());
+        let sqes = mock.take_submitted();
+        assert_eq!(sqes.len(), 1);
+        assert_eq!(sqes[0].opcode, opcode::AsyncCancel::CODE);
+
+        // 取消得到的ECANCELED不能被当成超时
+        mock.complete(read, -libc::ECANCELED, 0);
+        rt.step();
+        let (res, buf) = result.borrow_mut().take().unwrap();
+        assert_eq!(res.unwrap_err().kind(), std::io::ErrorKind::Interrupted);
+        assert_eq!(buf.capacity(), 16);
+        assert_eq!(mock.live_ops(), 0);
+    }
+
+    #[test]
+    fn canceled_handle_cancels_new_ops() {
+        let (rt, mock) = runtime();
+        let canceller = Canceller::new();
+        canceller.cancel();
+        let op = rt.enter(|| {
+            Op::read_at(&SharedFd::new_without_register(-1), Vec::with_capacity(8), 0)
+                .unwrap()
+                .cancelable(&canceller.handle())
+        });
+        let sqes = mock.take_submitted();
+        assert_eq!(sqes.len(), 2);
+        assert_eq!(sqes[0].opcode, opcode::Read::CODE);
+        assert_eq!(sqes[1].opcode, opcode::AsyncCancel::CODE);
+
+        // 内核来不及取消时op照常完成
+        mock.complete(sqes[0].user_data, 4, 0);
+        rt.step();
+        rt.enter(|| {
+            crate::spawn(async move {
+                let (res, _) = op.read().await;
+                assert_eq!(res.unwrap(), 4);
+            })
+        });
+        rt.step();
+        assert_eq!(mock.live_ops(), 0);
+    }
+}
@@ -1,4 +1,13 @@
+// 还没有类型实现这些trait
+#[allow(dead_code)]
 mod as_fd;
+#[allow(dead_code)]
 mod async_buf_read;
+#[allow(dead_code)]
 mod async_read_rent;
+#[allow(dead_code)]
 mod async_read_rent_ext;
+mod canceller;
+
+pub use canceller::{CancelHandle, Canceller};
+pub(crate) use canceller::CancelRegistration;
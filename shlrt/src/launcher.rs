@@ -0,0 +1,189 @@
+use std::future::Future;
+use std::io;
+use std::sync::Arc;
+
+use crate::builder::RuntimeBuilder;
+use crate::utils::affinity;
+
+/// thread-per-core启动器
+///
+/// 每个线程绑定到一个cpu上，并通过 [RuntimeBuilder] 创建自己的运行时，
+/// 线程之间不共享io_uring。
+pub struct Launcher {
+    builder: RuntimeBuilder,
+    /// 线程数量，不设置时每个可用cpu一个线程
+    threads: Option<usize>,
+    /// 可以绑定的cpu，不设置时使用当前进程允许的所有cpu
+    cpus: Option<Vec<usize>>,
+}
+
+impl Launcher {
+    pub fn new(builder: RuntimeBuilder) -> Self {
+        Self {
+            builder,
+            threads: None,
+            cpus: None,
+        }
+    }
+
+    /// 设置线程数量，线程比cpu多时按顺序循环绑定
+    #[must_use]
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = Some(threads);
+        self
+    }
+
+    /// 设置可以绑定的cpu
+    #[must_use]
+    pub fn cpus(mut self, cpus: impl IntoIterator<Item = usize>) -> Self {
+        self.cpus = Some(cpus.into_iter().collect());
+        self
+    }
+
+    /// 启动所有线程，每个线程上运行 `f(线程序号)` 返回的future，
+    /// 等待所有线程结束后按线程序号返回结果。
+    ///
+    /// 任意线程panic时，会在所有线程结束后把第一个panic传播到调用者。
+    pub fn run<F, Fut>(self, f: F) -> io::Result<Vec<Fut::Output>>
+    where
+        F: Fn(usize) -> Fut + Send + Sync + 'static,
+        Fut: Future,
+        Fut::Output: Send + 'static,
+    {
+        let cpus = match self.cpus {
+            Some(cpus) => cpus,
+            None => affinity::allowed_cpus()?,
+        };
+        if cpus.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "no cpu available to bind",
+            ));
+        }
+        let threads = self.threads.unwrap_or(cpus.len());
+
+        let f = Arc::new(f);
+        let mut handles = Vec::with_capacity(threads);
+        let mut error = None;
+        for index in 0..threads {
+            let cpu = cpus[index % cpus.len()];
+            let builder = self.builder.clone();
+            let f = f.clone();
+            let spawned = std::thread::Builder::new()
+                .name(format!("shlrt-worker-{}", index))
+                .spawn(move || -> io::Result<Fut::Output> {
+                    affinity::bind_to_cpu(cpu)?;
+                    let mut runtime = builder.build()?;
+                    Ok(runtime.block_on(f(index)))
+                });
+            match spawned {
+                Ok(handle) => handles.push(handle),
+                Err(e) => {
+                    error = Some(e);
+                    break;
+                }
+            }
+        }
+
+        // 先等待所有线程结束，再传播panic或错误
+        let mut outputs = Vec::with_capacity(handles.len());
+        let mut panic = None;
+        for handle in handles {
+            match handle.join() {
+                Ok(Ok(output)) => outputs.push(output),
+                Ok(Err(e)) => {
+                    error.get_or_insert(e);
+                }
+                Err(payload) => {
+                    panic.get_or_insert(payload);
+                }
+            }
+        }
+        if let Some(payload) = panic {
+            std::panic::resume_unwind(payload);
+        }
+        match error {
+            Some(e) => Err(e),
+            None => Ok(outputs),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::sync::{Arc, Barrier};
+    use std::time::Duration;
+
+    use super::Launcher;
+    use crate::builder::RuntimeBuilder;
+    use crate::runtime::CURRENT;
+
+    #[test]
+    fn one_runtime_per_thread() {
+        let barrier = Arc::new(Barrier::new(3));
+        let outputs = Launcher::new(RuntimeBuilder::new())
+            .threads(3)
+            .run(move |index| {
+                let barrier = barrier.clone();
+                async move {
+                    // 所有运行时同时存在，上下文的地址不会被复用
+                    barrier.wait();
+                    let context = CURRENT.with(|cx| cx as *const _ as usize);
+                    let thread = std::thread::current();
+                    (index, thread.id(), thread.name().map(str::to_owned), context)
+                }
+            })
+            .unwrap();
+
+        assert_eq!(outputs.len(), 3);
+        for (i, (index, _, name, _)) in outputs.iter().enumerate() {
+            assert_eq!(*index, i);
+            assert_eq!(name.as_deref(), Some(format!("shlrt-worker-{}", i).as_str()));
+        }
+        for (i, a) in outputs.iter().enumerate() {
+            for b in &outputs[i + 1..] {
+                assert_ne!(a.1, b.1);
+                assert_ne!(a.3, b.3);
+            }
+        }
+    }
+
+    #[test]
+    fn results_in_index_order() {
+        let outputs = Launcher::new(RuntimeBuilder::new())
+            .threads(4)
+            .run(|index| async move {
+                // 序号大的线程先结束
+                std::thread::sleep(Duration::from_millis(10 * (4 - index) as u64));
+                index * 10
+            })
+            .unwrap();
+        assert_eq!(outputs, [0, 10, 20, 30]);
+    }
+
+    #[test]
+    fn worker_panic_is_propagated() {
+        let payload = std::panic::catch_unwind(|| {
+            Launcher::new(RuntimeBuilder::new())
+                .threads(2)
+                .run(|index| async move {
+                    if index == 1 {
+                        panic!("worker 1 failed");
+                    }
+                    index
+                })
+        })
+        .unwrap_err();
+        assert_eq!(payload.downcast_ref::<&str>(), Some(&"worker 1 failed"));
+    }
+
+    #[test]
+    fn no_cpu() {
+        let err = Launcher::new(RuntimeBuilder::new())
+            .cpus([])
+            .run(|index| async move { index })
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
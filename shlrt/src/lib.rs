@@ -1,15 +1,27 @@
-#![allow(stable_features)]
-#![feature(io_error_more)]
-#![feature(return_position_impl_trait_in_trait)]
 
 pub mod buf;
+mod blocking;
 mod driver;
 mod io;
+mod launcher;
+mod runtime;
 mod scheduler;
 mod task;
 mod utils;
 mod builder;
 mod macros;
-mod fs;
+pub mod fs;
+pub mod net;
+pub mod time;
+
+pub use blocking::{spawn_blocking, BlockingHandle};
+pub use builder::RuntimeBuilder;
+pub use driver::op::{Chain, Link};
+pub use driver::{Driver, Features, FusionDriver, IoUringDriver, LegacyDriver, Metrics, Unpark, UnparkHandle};
+pub use io::{CancelHandle, Canceller};
+pub use launcher::Launcher;
+pub use runtime::{spawn, Runtime};
+pub use shlrt_macros::{main, test};
+pub use task::{JoinError, JoinHandle};
 
 pub type BufResult<T, B> = (std::io::Result<T>, B);
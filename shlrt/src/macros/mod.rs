@@ -1,2 +1,2 @@
-mod scoped_tls;
-mod ready;
\ No newline at end of file
+pub(crate) mod scoped_tls;
+pub(crate) mod trace;
@@ -1,4 +1,4 @@
-///! COPY FROM monoio
+//! COPY FROM monoio
 
 use std::cell::Cell;
 use std::marker;
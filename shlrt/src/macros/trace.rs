@@ -0,0 +1,9 @@
+/// 开启tracing feature时输出trace级别的事件，否则什么都不做
+macro_rules! trace_event {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::trace!($($arg)*);
+    };
+}
+
+pub(crate) use trace_event;
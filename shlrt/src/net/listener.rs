@@ -0,0 +1,233 @@
+use std::future::poll_fn;
+use std::io;
+use std::mem::MaybeUninit;
+use std::net::{SocketAddr, TcpListener as StdTcpListener, ToSocketAddrs};
+use std::os::fd::{AsRawFd, IntoRawFd, RawFd};
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+
+use crate::driver::legacy::cvt;
+use crate::driver::op::{to_socket_addr, wait_for_capacity, AcceptMulti, MultishotOp, Op};
+use crate::driver::shared_fd::SharedFd;
+use crate::io::CancelHandle;
+use crate::net::TcpStream;
+
+/// 监听中的tcp socket
+#[derive(Debug)]
+pub struct TcpListener {
+    fd: SharedFd,
+}
+
+impl TcpListener {
+    /// 绑定地址并开始监听
+    pub fn bind(addr: impl ToSocketAddrs) -> io::Result<TcpListener> {
+        TcpListener::from_std(StdTcpListener::bind(addr)?)
+    }
+
+    /// 接管std的listener，socket会被设置为非阻塞，legacy driver依赖这一点
+    pub fn from_std(listener: StdTcpListener) -> io::Result<TcpListener> {
+        listener.set_nonblocking(true)?;
+        Ok(TcpListener {
+            fd: SharedFd::new(listener.into_raw_fd())?,
+        })
+    }
+
+    /// 监听的本地地址
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        let mut storage = MaybeUninit::<libc::sockaddr_storage>::zeroed();
+        let mut len = std::mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+        cvt(unsafe {
+            libc::getsockname(self.fd.raw_fd(), storage.as_mut_ptr() as *mut libc::sockaddr, &mut len)
+        } as i64)?;
+        to_socket_addr(unsafe { storage.assume_init_ref() })
+    }
+
+    /// 等待新连接
+    pub async fn accept(&self) -> io::Result<(TcpStream, SocketAddr)> {
+        wait_for_capacity().await;
+        let (fd, addr) = Op::accept(&self.fd)?.accepted().await?;
+        Ok((TcpStream::from_shared_fd(fd), addr))
+    }
+
+    /// 等待新连接，新连接作为直接描述符放入固定文件表，没有普通的fd。
+    /// 需要先通过 [RuntimeBuilder::with_fixed_files](crate::RuntimeBuilder::with_fixed_files) 注册固定文件表
+    pub async fn accept_direct(&self) -> io::Result<(TcpStream, SocketAddr)> {
+        wait_for_capacity().await;
+        let (fd, addr) = Op::accept_direct(&self.fd)?.accepted().await?;
+        Ok((TcpStream::from_shared_fd(fd), addr))
+    }
+
+    /// timeout内没有新连接时返回ErrorKind::TimedOut，legacy driver不支持
+    pub async fn accept_timeout(&self, timeout: Duration) -> io::Result<(TcpStream, SocketAddr)> {
+        wait_for_capacity().await;
+        let (fd, addr) = Op::accept_timeout(&self.fd, timeout)?.accepted().await?;
+        Ok((TcpStream::from_shared_fd(fd), addr))
+    }
+
+    /// 使用multishot accept持续接收新连接，一次提交得到多个连接，不返回对端地址。
+    /// 内核在出错后结束accept，需要5.19以上的内核
+    pub async fn incoming(&self) -> io::Result<Incoming> {
+        wait_for_capacity().await;
+        Ok(Incoming {
+            op: MultishotOp::accept_multi(&self.fd)?,
+        })
+    }
+
+    /// 可以通过 [CancelHandle] 取消的accept，取消后以ErrorKind::Interrupted完成
+    pub async fn cancelable_accept(&self, handle: &CancelHandle) -> io::Result<(TcpStream, SocketAddr)> {
+        wait_for_capacity().await;
+        let (fd, addr) = Op::accept(&self.fd)?.cancelable(handle).accepted().await?;
+        Ok((TcpStream::from_shared_fd(fd), addr))
+    }
+}
+
+/// [TcpListener::incoming] 返回的连接流，drop时取消accept
+pub struct Incoming {
+    op: MultishotOp<AcceptMulti>,
+}
+
+impl Incoming {
+    /// 取出下一个连接，accept被取消或者出错结束后返回None
+    pub fn poll_next(&mut self, cx: &mut Context<'_>) -> Poll<Option<io::Result<TcpStream>>> {
+        let meta = ready!(self.op.poll_next(cx));
+        Poll::Ready(meta.map(|meta| {
+            let fd = SharedFd::new(meta.result? as RawFd)?;
+            Ok(TcpStream::from_shared_fd(fd))
+        }))
+    }
+
+    /// 等待下一个连接
+    pub async fn next(&mut self) -> Option<io::Result<TcpStream>> {
+        poll_fn(|cx| self.poll_next(cx)).await
+    }
+}
+
+impl AsRawFd for TcpListener {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.raw_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use std::os::fd::{AsRawFd, RawFd};
+
+    use io_uring::opcode;
+
+    use super::TcpListener;
+    use crate::driver::mock::runtime;
+    use crate::driver::shared_fd::SharedFd;
+    use crate::io::Canceller;
+
+    #[test]
+    fn local_addr() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        assert!(addr.ip().is_loopback());
+        assert_ne!(addr.port(), 0);
+    }
+
+    #[test]
+    fn cancelable_accept() {
+        let (rt, mock) = runtime();
+        let canceller = Canceller::new();
+        let handle = canceller.handle();
+        let result = Rc::new(RefCell::new(None));
+        let output = result.clone();
+        rt.enter(|| {
+            crate::spawn(async move {
+                let listener = TcpListener {
+                    fd: SharedFd::new_without_register(-1),
+                };
+                *output.borrow_mut() = Some(listener.cancelable_accept(&handle).await.map(drop));
+            })
+        });
+
+        rt.step();
+        let accept = mock.take_submitted()[0];
+        assert_eq!(accept.opcode, opcode::Accept::CODE);
+        rt.enter(|| canceller.cancel());
+        assert_eq!(mock.take_submitted()[0].opcode, opcode::AsyncCancel::CODE);
+
+        mock.complete(accept.user_data, -libc::ECANCELED, 0);
+        rt.step();
+        let res = result.borrow_mut().take().unwrap();
+        assert_eq!(res.unwrap_err().kind(), std::io::ErrorKind::Interrupted);
+        assert_eq!(mock.live_ops(), 0);
+    }
+
+    #[test]
+    fn incoming() {
+        const IORING_CQE_F_MORE: u32 = 1 << 1;
+        let (rt, mock) = runtime();
+        let result = Rc::new(RefCell::new(Vec::new()));
+        let output = result.clone();
+        rt.enter(|| {
+            crate::spawn(async move {
+                let listener = TcpListener {
+                    fd: SharedFd::new_without_register(-1),
+                };
+                let mut incoming = listener.incoming().await.unwrap();
+                while let Some(stream) = incoming.next().await {
+                    output.borrow_mut().push(stream.map(|stream| stream.as_raw_fd()));
+                }
+            })
+        });
+
+        rt.step();
+        let accept = mock.take_submitted()[0];
+        assert_eq!(accept.opcode, opcode::AcceptMulti::CODE);
+        // 用pipe的写端代替连接，stream被drop后读端读到EOF
+        let pipes = [pipe(), pipe()];
+        for (_, tx) in pipes {
+            mock.complete(accept.user_data, tx, IORING_CQE_F_MORE);
+        }
+        // 出错时内核结束accept，不会重新提交
+        mock.complete(accept.user_data, -libc::EMFILE, 0);
+        rt.step();
+
+        let result = result.borrow();
+        let accepted = result[..2].iter().map(|res| *res.as_ref().unwrap()).collect::<Vec<_>>();
+        assert_eq!(accepted, pipes.map(|(_, tx)| tx));
+        assert_eq!(result[2].as_ref().unwrap_err().raw_os_error(), Some(libc::EMFILE));
+        assert!(mock.take_submitted().is_empty());
+        assert_eq!(mock.live_ops(), 0);
+        for (rx, _) in pipes {
+            let mut buf = [0u8; 1];
+            assert_eq!(unsafe { libc::read(rx, buf.as_mut_ptr() as *mut libc::c_void, 1) }, 0);
+            unsafe { libc::close(rx) };
+        }
+    }
+
+    fn pipe() -> (RawFd, RawFd) {
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) }, 0);
+        (fds[0], fds[1])
+    }
+
+    #[test]
+    fn accept_direct() {
+        use std::io::Read;
+
+        let mut rt = crate::RuntimeBuilder::new().with_fixed_files(4).build().unwrap();
+        if rt.features().is_legacy() {
+            return;
+        }
+        let listener = rt.block_on(async { TcpListener::bind("127.0.0.1:0") }).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = std::thread::spawn(move || {
+            let mut stream = std::net::TcpStream::connect(addr).unwrap();
+            let mut buf = [0u8; 1];
+            // 直接描述符被drop后从固定文件表中移除，连接随之关闭
+            assert_eq!(stream.read(&mut buf).unwrap(), 0);
+            stream.local_addr().unwrap()
+        });
+        let (stream, peer) = rt.block_on(listener.accept_direct()).unwrap();
+        assert!(matches!(stream.fd.kind(), crate::driver::shared_fd::FdKind::Fixed(_)));
+        rt.block_on(async move { drop(stream) });
+        assert_eq!(client.join().unwrap(), peer);
+    }
+}
@@ -0,0 +1,126 @@
+use std::future::poll_fn;
+use std::io;
+use std::net::{SocketAddr, TcpStream as StdTcpStream};
+use std::os::fd::{AsRawFd, IntoRawFd, RawFd};
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+
+use crate::buf::{BufRing, BufRingEntry};
+use crate::driver::op::{wait_for_capacity, MultishotOp, Op, RecvMultiBufRing};
+use crate::driver::legacy::cvt;
+use crate::driver::shared_fd::SharedFd;
+use crate::io::CancelHandle;
+
+/// 已经连接的tcp socket
+#[derive(Debug)]
+pub struct TcpStream {
+    pub(crate) fd: SharedFd,
+}
+
+impl TcpStream {
+    pub(crate) fn from_shared_fd(fd: SharedFd) -> TcpStream {
+        TcpStream { fd }
+    }
+
+    /// 接管std的socket，socket会被设置为非阻塞，legacy driver依赖这一点
+    pub fn from_std(stream: StdTcpStream) -> io::Result<TcpStream> {
+        stream.set_nonblocking(true)?;
+        Ok(TcpStream::from_shared_fd(SharedFd::new(stream.into_raw_fd())?))
+    }
+
+    /// 连接到addr，目前只支持ipv4
+    pub async fn connect(addr: SocketAddr) -> io::Result<TcpStream> {
+        let fd = TcpStream::socket(&addr)?;
+        wait_for_capacity().await;
+        Op::connect(fd.clone(), addr, false)?.await.meta.result?;
+        Ok(TcpStream::from_shared_fd(fd))
+    }
+
+    /// timeout内没有连上时返回ErrorKind::TimedOut，legacy driver不支持
+    pub async fn connect_timeout(addr: SocketAddr, timeout: Duration) -> io::Result<TcpStream> {
+        let fd = TcpStream::socket(&addr)?;
+        wait_for_capacity().await;
+        Op::connect_timeout(fd.clone(), addr, timeout)?.await.meta.result?;
+        Ok(TcpStream::from_shared_fd(fd))
+    }
+
+    /// 可以通过 [CancelHandle] 取消的connect，取消后以ErrorKind::Interrupted完成
+    pub async fn cancelable_connect(addr: SocketAddr, handle: &CancelHandle) -> io::Result<TcpStream> {
+        let fd = TcpStream::socket(&addr)?;
+        wait_for_capacity().await;
+        Op::connect(fd.clone(), addr, false)?.cancelable(handle).await.meta.result?;
+        Ok(TcpStream::from_shared_fd(fd))
+    }
+
+    /// 创建非阻塞的socket，legacy driver依赖非阻塞
+    fn socket(addr: &SocketAddr) -> io::Result<SharedFd> {
+        if !addr.is_ipv4() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "only ipv4 is supported"));
+        }
+        let fd = cvt(unsafe {
+            libc::socket(libc::AF_INET, libc::SOCK_STREAM | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC, 0)
+        } as i64)?;
+        SharedFd::new(fd as RawFd)
+    }
+
+    /// 由内核从 [BufRing] 中挑选缓冲区接收数据，返回None表示对端已经关闭。
+    /// 缓冲区在返回的 [BufRingEntry] drop后放回ring
+    pub async fn recv_buf_ring(&self, ring: &BufRing) -> io::Result<Option<BufRingEntry>> {
+        wait_for_capacity().await;
+        Op::recv_buf_ring(&self.fd, ring)?.recv().await
+    }
+
+    /// 使用multishot recv持续接收数据，每段数据都放在内核从 [BufRing] 中挑选的缓冲区里。
+    /// ring中没有空闲缓冲区时以ENOBUFS结束，需要6.0以上的内核
+    pub async fn recv_multi(&self, ring: &BufRing) -> io::Result<RecvMulti> {
+        wait_for_capacity().await;
+        Ok(RecvMulti {
+            op: Some(MultishotOp::recv_multi(&self.fd, ring)?),
+        })
+    }
+
+    /// 可以通过 [CancelHandle] 取消的 [TcpStream::recv_buf_ring]，取消后以ErrorKind::Interrupted完成
+    pub async fn cancelable_recv_buf_ring(
+        &self,
+        ring: &BufRing,
+        handle: &CancelHandle,
+    ) -> io::Result<Option<BufRingEntry>> {
+        wait_for_capacity().await;
+        Op::recv_buf_ring(&self.fd, ring)?.cancelable(handle).recv().await
+    }
+}
+
+/// [TcpStream::recv_multi] 返回的数据流，drop时取消recv
+pub struct RecvMulti {
+    /// 对端关闭后为None
+    op: Option<MultishotOp<RecvMultiBufRing>>,
+}
+
+impl RecvMulti {
+    /// 取出下一段数据，对端关闭、recv被取消或者出错结束后返回None
+    pub fn poll_next(&mut self, cx: &mut Context<'_>) -> Poll<Option<io::Result<BufRingEntry>>> {
+        let Some(op) = self.op.as_mut() else {
+            return Poll::Ready(None);
+        };
+        match ready!(op.poll_recv(cx)) {
+            Some(Ok(Some(entry))) => Poll::Ready(Some(Ok(entry))),
+            Some(Err(e)) => Poll::Ready(Some(Err(e))),
+            // 对端已经关闭，drop会取消内核重新提交的recv
+            Some(Ok(None)) | None => {
+                self.op = None;
+                Poll::Ready(None)
+            }
+        }
+    }
+
+    /// 等待下一段数据
+    pub async fn next(&mut self) -> Option<io::Result<BufRingEntry>> {
+        poll_fn(|cx| self.poll_next(cx)).await
+    }
+}
+
+impl AsRawFd for TcpStream {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.raw_fd()
+    }
+}
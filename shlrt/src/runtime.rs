@@ -0,0 +1,458 @@
+use std::future::Future;
+use std::io;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Poll, Wake, Waker};
+use std::time::Duration;
+
+use crate::blocking::BlockingPool;
+use crate::driver::{Driver, Features, Metrics, Unpark};
+use crate::scheduler::{OwnedTasks, Scheduler, TaskQueue};
+use crate::scoped_thread_local;
+use crate::task::{new_task, JoinHandle};
+use crate::time::wheel::TimeDriver;
+
+scoped_thread_local!(pub(crate) static CURRENT: Context);
+
+/// 运行时上下文，block_on期间通过CURRENT访问
+pub(crate) struct Context {
+    /// 本地任务队列
+    pub(crate) tasks: TaskQueue,
+    /// 所有未完成的任务
+    pub(crate) owned: OwnedTasks,
+    /// 阻塞任务线程池
+    pub(crate) blocking: BlockingPool,
+    /// 定时器，没有开启时为None
+    pub(crate) timer: Option<Rc<TimeDriver>>,
+}
+
+impl Context {
+    pub(crate) fn new(blocking: BlockingPool, timer: Option<Rc<TimeDriver>>) -> Context {
+        Context {
+            tasks: TaskQueue::new(),
+            owned: OwnedTasks::new(),
+            blocking,
+            timer,
+        }
+    }
+
+    /// 取消所有任务并在本线程丢弃它们的future，丢弃future时新spawn的任务也会被取消
+    fn shutdown_tasks(&self) {
+        loop {
+            self.owned.shutdown();
+            while let Some(task) = self.tasks.pop() {
+                drop(task);
+            }
+            if self.owned.is_empty() {
+                break;
+            }
+        }
+    }
+
+    /// 唤醒到期的定时器
+    fn process_timers(&self) {
+        if let Some(timer) = &self.timer {
+            timer.process();
+        }
+    }
+}
+
+/// 单线程运行时。drop时取消所有还没有完成的任务，
+/// 它们的future在运行时所在的线程上被丢弃
+pub struct Runtime<D: Driver> {
+    context: Context,
+    driver: D,
+    /// drop时等待进行中的op被取消的最长时间，调用过 [Runtime::shutdown_timeout] 后为None
+    shutdown_timeout: Option<Duration>,
+}
+
+impl<D: Driver> Runtime<D> {
+    pub(crate) fn new(context: Context, driver: D, shutdown_timeout: Duration) -> Runtime<D> {
+        Runtime {
+            context,
+            driver,
+            shutdown_timeout: Some(shutdown_timeout),
+        }
+    }
+
+    /// 取消所有任务，在运行时的上下文中丢弃它们的future，再取消进行中的op并等待内核返回
+    fn shutdown(&self, timeout: Duration) -> io::Result<usize> {
+        // future的drop可能会取消op或者访问运行时，需要在运行时的上下文中进行
+        self.driver.with(|| {
+            CURRENT.set(&self.context, || {
+                self.context.shutdown_tasks();
+                self.driver.shutdown_timeout(timeout)
+            })
+        })
+    }
+}
+
+impl<D: Driver> Runtime<D> {
+    /// 获取跨线程唤醒driver的句柄
+    pub fn unpark(&self) -> D::Unpark {
+        self.driver.unpark()
+    }
+
+    /// driver支持的功能，可以在使用可选的io_uring操作前检查
+    pub fn features(&self) -> Features {
+        self.driver.features()
+    }
+
+    /// driver运行统计的快照
+    pub fn metrics(&self) -> Metrics {
+        self.driver.metrics()
+    }
+
+    /// 关闭运行时：取消所有任务，取消所有进行中的io操作并等待内核返回，最多等待timeout。
+    ///
+    /// 返回超时后仍未完成的op数量，这些op引用的内存会被泄漏而不会被释放。
+    /// 直接drop运行时也会做同样的事情，等待的时间由
+    /// [RuntimeBuilder::shutdown_timeout](crate::RuntimeBuilder::shutdown_timeout) 设置，并且忽略结果。
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> io::Result<usize> {
+        self.shutdown_timeout = None;
+        self.shutdown(timeout)
+    }
+}
+
+impl<D: Driver> Runtime<D> {
+    /// 在当前线程上运行future直到完成，期间会运行所有spawn出来的任务
+    pub fn block_on<F: Future>(&mut self, future: F) -> F::Output {
+        assert!(
+            !CURRENT.is_set(),
+            "Can not start a runtime inside a runtime"
+        );
+
+        let main_waker = Arc::new(MainWaker::new(Box::new(self.driver.unpark())));
+        let waker = Waker::from(main_waker.clone());
+        let mut cx = std::task::Context::from_waker(&waker);
+        let mut future = std::pin::pin!(future);
+
+        self.driver.with(|| {
+            CURRENT.set(&self.context, || loop {
+                loop {
+                    // 运行本地任务，限制轮数防止io饿死
+                    let mut max_round = self.context.tasks.len() * 2;
+                    while let Some(task) = self.context.tasks.pop() {
+                        task.run();
+                        if max_round == 0 {
+                            break;
+                        }
+                        max_round -= 1;
+                    }
+
+                    // 主future被唤醒时poll它，每轮只poll一次，让出执行权的主future不会饿死其他任务
+                    if main_waker.take() {
+                        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                            return output;
+                        }
+                    }
+
+                    if self.context.tasks.is_empty() && !main_waker.is_woken() {
+                        break;
+                    }
+
+                    // 还有任务没跑完，不阻塞地提交sq并收割cq
+                    let _ = self.driver.submit();
+                    self.context.process_timers();
+                }
+
+                // 没有可运行的任务，阻塞等待io完成或者下一个定时器到期
+                match self.context.timer.as_ref().and_then(|timer| timer.next_timeout()) {
+                    Some(timeout) => {
+                        let _ = self.driver.park_timeout(timeout);
+                    }
+                    None => {
+                        let _ = self.driver.park();
+                    }
+                }
+                self.context.process_timers();
+            })
+        })
+    }
+}
+
+impl<D: Driver> Drop for Runtime<D> {
+    fn drop(&mut self) {
+        if let Some(timeout) = self.shutdown_timeout {
+            let _ = self.shutdown(timeout);
+        }
+    }
+}
+
+#[cfg(test)]
+impl<D: Driver> Runtime<D> {
+    /// 在运行时的上下文中执行f，可以用来spawn任务或者创建op
+    pub(crate) fn enter<R>(&self, f: impl FnOnce() -> R) -> R {
+        self.driver.with(|| CURRENT.set(&self.context, f))
+    }
+
+    /// 不阻塞地推进一步：处理driver中已经完成的事件，然后运行所有可以运行的任务
+    pub(crate) fn step(&self) {
+        self.enter(|| {
+            let _ = self.driver.submit();
+            self.context.process_timers();
+            while let Some(task) = self.context.tasks.pop() {
+                task.run();
+            }
+        })
+    }
+}
+
+/// block_on中主future的唤醒标记
+struct MainWaker {
+    woken: AtomicBool,
+    /// 在其他线程被唤醒时需要唤醒driver
+    unpark: Box<dyn Unpark>,
+}
+
+impl MainWaker {
+    fn new(unpark: Box<dyn Unpark>) -> MainWaker {
+        // 第一次进入循环时需要poll一次
+        MainWaker {
+            woken: AtomicBool::new(true),
+            unpark,
+        }
+    }
+
+    fn take(&self) -> bool {
+        self.woken.swap(false, Ordering::AcqRel)
+    }
+
+    fn is_woken(&self) -> bool {
+        self.woken.load(Ordering::Acquire)
+    }
+}
+
+impl Wake for MainWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.woken.store(true, Ordering::Release);
+        // driver没有park时不会产生系统调用
+        let _ = self.unpark.unpark();
+    }
+}
+
+/// 在当前线程的运行时上创建任务，future不需要是Send的
+///
+/// # Panics
+///
+/// 不在运行时中调用时会panic
+pub fn spawn<T>(future: T) -> JoinHandle<T::Output>
+where
+    T: Future + 'static,
+    T::Output: 'static,
+{
+    let (task, join) = new_task(future, Scheduler);
+    CURRENT.with(|cx| {
+        cx.owned.insert(task.owned_ref());
+        cx.tasks.push(task);
+    });
+    join
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+    use std::future::poll_fn;
+    use std::rc::Rc;
+    use std::sync::{Arc, Mutex};
+    use std::task::{Poll, Waker};
+    use std::time::Duration;
+
+    use io_uring::opcode;
+
+    use crate::driver::mock::runtime;
+    use crate::driver::shared_fd::SharedFd;
+    use crate::fs::File;
+
+    /// 让出一次执行权：唤醒自己后返回Pending，任务被放回运行队列末尾
+    async fn yield_once() {
+        let mut yielded = false;
+        poll_fn(|cx| {
+            if std::mem::replace(&mut yielded, true) {
+                return Poll::Ready(());
+            }
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        })
+        .await
+    }
+
+    /// drop时记录所在的线程
+    struct DropGuard(Rc<Cell<Option<std::thread::ThreadId>>>);
+
+    impl Drop for DropGuard {
+        fn drop(&mut self) {
+            self.0.set(Some(std::thread::current().id()));
+        }
+    }
+
+    #[test]
+    fn drop_cancels_pending_tasks() {
+        let (rt, _mock) = runtime();
+        let dropped = Rc::new(Cell::new(None));
+        let waker = Arc::new(Mutex::new(None::<Waker>));
+        let guard = DropGuard(dropped.clone());
+        let remote = waker.clone();
+        let join = rt.enter(|| {
+            crate::spawn(async move {
+                let _guard = guard;
+                poll_fn(|cx| {
+                    *remote.lock().unwrap() = Some(cx.waker().clone());
+                    Poll::<()>::Pending
+                })
+                .await
+            })
+        });
+        rt.step();
+        assert!(!join.is_finished());
+
+        drop(rt);
+        // future在运行时所在的线程上被丢弃
+        assert_eq!(dropped.get(), Some(std::thread::current().id()));
+        assert!(join.is_finished());
+
+        // 运行时退出后从其他线程唤醒，只会释放引用
+        let waker = waker.lock().unwrap().take().unwrap();
+        std::thread::spawn(move || waker.wake()).join().unwrap();
+    }
+
+    #[test]
+    fn completed_tasks_leave_the_owned_list() {
+        let (rt, _mock) = runtime();
+        let join = rt.enter(|| crate::spawn(async { 1 }));
+        rt.enter(|| assert!(!super::CURRENT.with(|cx| cx.owned.is_empty())));
+        rt.step();
+        assert!(join.is_finished());
+        rt.enter(|| assert!(super::CURRENT.with(|cx| cx.owned.is_empty())));
+    }
+
+    #[test]
+    fn tasks_spawned_while_dropping_are_cancelled() {
+        let (rt, _mock) = runtime();
+        let dropped = Rc::new(Cell::new(None));
+
+        struct SpawnOnDrop(Option<DropGuard>);
+
+        impl Drop for SpawnOnDrop {
+            fn drop(&mut self) {
+                let guard = self.0.take();
+                crate::spawn(async move {
+                    let _guard = guard;
+                    std::future::pending::<()>().await
+                });
+            }
+        }
+
+        let spawner = SpawnOnDrop(Some(DropGuard(dropped.clone())));
+        rt.enter(|| {
+            crate::spawn(async move {
+                let _spawner = spawner;
+                std::future::pending::<()>().await
+            })
+        });
+        rt.step();
+        drop(rt);
+        assert!(dropped.get().is_some());
+    }
+
+    #[test]
+    fn shutdown_timeout_cancels_in_flight_ops() {
+        let (rt, mock) = runtime();
+        rt.enter(|| {
+            crate::spawn(async {
+                let file = File::from_shared_fd(SharedFd::new_without_register(-1));
+                let _ = file.read_at(Vec::with_capacity(8), 0).await;
+            })
+        });
+        rt.step();
+        assert_eq!(mock.take_submitted()[0].opcode, opcode::Read::CODE);
+
+        // 内核还没有返回cqe，op没有完成
+        assert_eq!(rt.shutdown_timeout(Duration::from_secs(1)).unwrap(), 1);
+        // 丢弃任务时和关闭driver时各取消一次，drop运行时不会再取消和等待
+        let sqes = mock.take_submitted();
+        assert_eq!(sqes.len(), 2);
+        assert!(sqes.iter().all(|sqe| sqe.opcode == opcode::AsyncCancel::CODE));
+        assert_eq!(mock.live_ops(), 1);
+    }
+
+    #[test]
+    fn spawned_tasks_run_in_order() {
+        let (rt, _mock) = runtime();
+        let order = Rc::new(RefCell::new(Vec::new()));
+        for id in 0..3 {
+            let order = order.clone();
+            rt.enter(|| crate::spawn(async move { order.borrow_mut().push(id) }));
+        }
+        assert!(order.borrow().is_empty());
+        rt.step();
+        assert_eq!(*order.borrow(), [0, 1, 2]);
+    }
+
+    #[test]
+    fn yielded_tasks_go_to_the_back_of_the_queue() {
+        let (rt, _mock) = runtime();
+        let order = Rc::new(RefCell::new(Vec::new()));
+        for id in ['a', 'b'] {
+            let order = order.clone();
+            rt.enter(|| {
+                crate::spawn(async move {
+                    for round in 0..2 {
+                        order.borrow_mut().push((id, round));
+                        yield_once().await;
+                    }
+                })
+            });
+        }
+        rt.step();
+        assert_eq!(*order.borrow(), [('a', 0), ('b', 0), ('a', 1), ('b', 1)]);
+    }
+
+    #[test]
+    fn block_on_returns_join_handle_output() {
+        let (mut rt, _mock) = runtime();
+        let output = rt.block_on(async {
+            let first = crate::spawn(async { 1 });
+            let second = crate::spawn(async {
+                yield_once().await;
+                2
+            });
+            // 主future等待时运行队列中的任务继续执行
+            second.await.unwrap() * 10 + first.await.unwrap()
+        });
+        assert_eq!(output, 21);
+    }
+
+    #[test]
+    fn block_on_runs_detached_tasks() {
+        let (mut rt, _mock) = runtime();
+        let done = Rc::new(Cell::new(false));
+        let flag = done.clone();
+        rt.block_on(async move {
+            crate::spawn(async move { flag.set(true) }).detach();
+            while !done.get() {
+                yield_once().await;
+            }
+        });
+    }
+
+    #[test]
+    fn join_handle_reports_abort_and_panic() {
+        let (mut rt, _mock) = runtime();
+        rt.block_on(async {
+            let aborted = crate::spawn(std::future::pending::<()>());
+            aborted.abort();
+            assert!(aborted.await.unwrap_err().is_cancelled());
+
+            let panicked = crate::spawn(async { panic!("task failed") });
+            let err = panicked.await.unwrap_err();
+            assert!(err.is_panic());
+            assert_eq!(err.into_panic().downcast_ref::<&str>(), Some(&"task failed"));
+        });
+    }
+}
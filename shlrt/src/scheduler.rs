@@ -1,5 +1,100 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+
+use crate::runtime::CURRENT;
+use crate::task::Task;
+
+/// 本线程的调度器，任务放入当前运行时的本地队列
+#[derive(Clone, Copy)]
 pub(crate) struct Scheduler;
 
-impl Schedule for Scheduler {}
+impl Schedule for Scheduler {
+    fn schedule(&self, task: Task<Self>) {
+        // 运行时已经退出时直接丢弃任务
+        CURRENT.try_with(|cx| {
+            if let Some(cx) = cx {
+                cx.tasks.push(task);
+            }
+        });
+    }
+
+    fn yield_now(&self, task: Task<Self>) {
+        self.schedule(task);
+    }
+
+    fn release(&self, id: usize) {
+        // 在运行时中drop任务持有的引用
+        let task = CURRENT.try_with(|cx| cx.and_then(|cx| cx.owned.remove(id)));
+        drop(task);
+    }
+}
+
+pub trait Schedule: Sized + 'static {
+    /// 任务被唤醒，放入运行队列
+    fn schedule(&self, task: Task<Self>);
+
+    /// 任务在poll期间被唤醒，poll结束后重新放回运行队列
+    fn yield_now(&self, task: Task<Self>) {
+        self.schedule(task);
+    }
+
+    /// 任务完成，从运行时的任务列表中移除
+    fn release(&self, id: usize);
+}
+
+/// 运行时拥有的所有未完成的任务，各持有一份任务引用。
+/// 运行时drop时在本线程取消并丢弃它们的future，future不会在其他线程上被drop
+pub(crate) struct OwnedTasks(RefCell<HashMap<usize, Task<Scheduler>>>);
+
+impl OwnedTasks {
+    pub(crate) fn new() -> OwnedTasks {
+        OwnedTasks(RefCell::new(HashMap::new()))
+    }
+
+    pub(crate) fn insert(&self, task: Task<Scheduler>) {
+        self.0.borrow_mut().insert(task.id(), task);
+    }
+
+    fn remove(&self, id: usize) -> Option<Task<Scheduler>> {
+        self.0.borrow_mut().remove(&id)
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.borrow().is_empty()
+    }
+
+    /// 取消所有任务。future的drop可能spawn新任务或者唤醒其他任务，所以不能持有借用
+    pub(crate) fn shutdown(&self) {
+        let tasks = std::mem::take(&mut *self.0.borrow_mut());
+        for (_, task) in tasks {
+            task.shutdown();
+        }
+    }
+}
+
+/// 本地任务队列
+pub(crate) struct TaskQueue(RefCell<VecDeque<Task<Scheduler>>>);
+
+impl TaskQueue {
+    const DEFAULT_CAPACITY: usize = 4096;
+
+    pub(crate) fn new() -> TaskQueue {
+        TaskQueue(RefCell::new(VecDeque::with_capacity(Self::DEFAULT_CAPACITY)))
+    }
+
+    pub(crate) fn push(&self, task: Task<Scheduler>) {
+        self.0.borrow_mut().push_back(task);
+    }
+
+    pub(crate) fn pop(&self) -> Option<Task<Scheduler>> {
+        self.0.borrow_mut().pop_front()
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.0.borrow().len()
+    }
 
-pub trait Schedule {}
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.borrow().is_empty()
+    }
+}
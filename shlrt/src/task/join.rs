@@ -0,0 +1,69 @@
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use crate::task::error::JoinError;
+use crate::task::raw::RawTask;
+
+/// 等待任务完成并取得输出
+///
+/// drop JoinHandle不会取消任务，任务会在后台继续运行直到完成（detach）。
+pub struct JoinHandle<T> {
+    raw: RawTask,
+    _p: PhantomData<T>,
+}
+
+impl<T> JoinHandle<T> {
+    pub(super) fn new(raw: RawTask) -> JoinHandle<T> {
+        JoinHandle {
+            raw,
+            _p: PhantomData,
+        }
+    }
+
+    /// 取消任务，任务会在下一次被调度时丢弃future，
+    /// 之后await JoinHandle得到 [JoinError::is_cancelled] 的错误
+    pub fn abort(&self) {
+        self.raw.abort();
+    }
+
+    /// 任务是否已经结束（完成、panic或被取消）
+    pub fn is_finished(&self) -> bool {
+        self.raw.header().state.is_complete()
+    }
+
+    /// 放弃任务的输出，任务在后台继续运行
+    pub fn detach(self) {
+        drop(self);
+    }
+}
+
+impl<T> Unpin for JoinHandle<T> {}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = Result<T, JoinError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut ret = Poll::Pending;
+        unsafe {
+            self.raw
+                .try_read_output(&mut ret as *mut Poll<Self::Output> as *mut (), cx.waker());
+        }
+        ret
+    }
+}
+
+impl<T> Drop for JoinHandle<T> {
+    fn drop(&mut self) {
+        self.raw.drop_join_handle();
+    }
+}
+
+impl<T> std::fmt::Debug for JoinHandle<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JoinHandle")
+            .field("finished", &self.is_finished())
+            .finish()
+    }
+}
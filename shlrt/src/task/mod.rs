@@ -1 +1,70 @@
+use std::future::Future;
+use std::marker::PhantomData;
 
+use crate::scheduler::Schedule;
+
+mod error;
+mod join;
+mod raw;
+mod state;
+mod waker;
+
+pub use error::JoinError;
+pub use join::JoinHandle;
+use raw::RawTask;
+
+/// 运行队列中的任务，持有一份任务引用
+pub(crate) struct Task<S: 'static> {
+    raw: RawTask,
+    _p: PhantomData<S>,
+}
+
+impl<S: 'static> Task<S> {
+    unsafe fn from_raw(raw: RawTask) -> Task<S> {
+        Task {
+            raw,
+            _p: PhantomData,
+        }
+    }
+
+    pub(crate) fn id(&self) -> usize {
+        self.raw.id()
+    }
+
+    /// 再创建一份任务引用，交给运行时的任务列表
+    pub(crate) fn owned_ref(&self) -> Task<S> {
+        self.raw.header().ref_inc();
+        unsafe { Task::from_raw(self.raw) }
+    }
+
+    /// 运行时关闭时丢弃future，JoinHandle得到取消的错误
+    pub(crate) fn shutdown(self) {
+        self.raw.shutdown();
+    }
+
+    /// poll一次任务
+    pub(crate) fn run(self) {
+        let raw = self.raw;
+        std::mem::forget(self);
+        raw.poll();
+    }
+}
+
+impl<S: 'static> Drop for Task<S> {
+    fn drop(&mut self) {
+        self.raw.drop_reference();
+    }
+}
+
+/// 创建任务，返回的Task需要交给调度器
+pub(crate) fn new_task<T, S>(task: T, scheduler: S) -> (Task<S>, JoinHandle<T::Output>)
+where
+    S: Schedule,
+    T: Future + 'static,
+    T::Output: 'static,
+{
+    let raw = RawTask::new::<T, S>(task, scheduler);
+    let task = unsafe { Task::from_raw(raw) };
+    let join = JoinHandle::new(raw);
+    (task, join)
+}
@@ -0,0 +1,296 @@
+use std::cell::UnsafeCell;
+use std::future::Future;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::pin::Pin;
+use std::ptr::NonNull;
+use std::sync::atomic::{fence, AtomicUsize, Ordering};
+use std::task::{Context, Poll, Waker};
+
+use crate::scheduler::Schedule;
+use crate::task::error::JoinError;
+use crate::task::state::State;
+use crate::task::waker::waker_ref;
+use crate::task::Task;
+use crate::utils::thread_id::current_thread_id;
+
+/// 任务头部，所有任务共用，通过vtable分发到具体的future类型
+#[repr(C)]
+pub(crate) struct Header {
+    /// 任务状态
+    pub(crate) state: State,
+    /// 引用计数，Task、JoinHandle以及每个Waker各持有一份
+    refs: AtomicUsize,
+    vtable: &'static Vtable,
+    /// 任务所属线程，future只会在这个线程上被poll
+    owner_id: usize,
+}
+
+impl Header {
+    pub(crate) fn owner_id(&self) -> usize {
+        self.owner_id
+    }
+
+    pub(crate) fn is_current_thread(&self) -> bool {
+        self.owner_id == current_thread_id()
+    }
+
+    pub(crate) fn ref_inc(&self) {
+        self.refs.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 减少引用计数，返回是否为最后一份引用
+    fn ref_dec(&self) -> bool {
+        if self.refs.fetch_sub(1, Ordering::Release) != 1 {
+            return false;
+        }
+        fence(Ordering::Acquire);
+        true
+    }
+}
+
+/// 类型擦除后的任务操作
+struct Vtable {
+    /// poll任务，消耗一份引用
+    poll: unsafe fn(NonNull<Header>),
+    /// 任务被唤醒时调用
+    schedule: unsafe fn(NonNull<Header>),
+    /// 取消任务
+    abort: unsafe fn(NonNull<Header>),
+    /// 运行时关闭，立即丢弃future
+    shutdown: unsafe fn(NonNull<Header>),
+    /// 释放任务内存
+    dealloc: unsafe fn(NonNull<Header>),
+    /// 读取任务输出，dst的类型为 *mut Poll<Result<T::Output, JoinError>>
+    try_read_output: unsafe fn(NonNull<Header>, *mut (), &Waker),
+    /// JoinHandle被drop，消耗一份引用
+    drop_join_handle: unsafe fn(NonNull<Header>),
+}
+
+fn vtable<T: Future, S: Schedule>() -> &'static Vtable {
+    &Vtable {
+        poll: poll::<T, S>,
+        schedule: schedule::<T, S>,
+        abort: abort::<T, S>,
+        shutdown: shutdown::<T, S>,
+        dealloc: dealloc::<T, S>,
+        try_read_output: try_read_output::<T, S>,
+        drop_join_handle: drop_join_handle::<T, S>,
+    }
+}
+
+/// 任务在堆上的完整布局，header必须在第一个字段
+#[repr(C)]
+struct Cell<T: Future, S> {
+    header: Header,
+    scheduler: S,
+    stage: UnsafeCell<Stage<T>>,
+    /// 等待任务完成的JoinHandle的waker
+    join_waker: UnsafeCell<Option<Waker>>,
+}
+
+enum Stage<T: Future> {
+    Running(T),
+    Finished(Result<T::Output, JoinError>),
+    Consumed,
+}
+
+impl<T: Future, S: Schedule> Cell<T, S> {
+    unsafe fn from_header<'a>(ptr: NonNull<Header>) -> &'a Cell<T, S> {
+        ptr.cast::<Cell<T, S>>().as_ref()
+    }
+
+    fn poll_future(&self, cx: &mut Context<'_>) -> Poll<()> {
+        let stage = unsafe { &mut *self.stage.get() };
+        let future = match stage {
+            Stage::Running(future) => future,
+            _ => unreachable!("unexpected stage"),
+        };
+        let polled = catch_unwind(AssertUnwindSafe(|| unsafe { Pin::new_unchecked(future) }.poll(cx)));
+        let output = match polled {
+            Ok(Poll::Pending) => return Poll::Pending,
+            Ok(Poll::Ready(output)) => Ok(output),
+            Err(payload) => Err(JoinError::panic(payload)),
+        };
+        *stage = Stage::Finished(output);
+        Poll::Ready(())
+    }
+
+    /// 丢弃future，输出设置为取消
+    fn cancel_future(&self) {
+        let stage = unsafe { &mut *self.stage.get() };
+        // future的drop也可能panic
+        let output = match catch_unwind(AssertUnwindSafe(|| *stage = Stage::Consumed)) {
+            Ok(()) => Err(JoinError::cancelled()),
+            Err(payload) => Err(JoinError::panic(payload)),
+        };
+        *stage = Stage::Finished(output);
+    }
+
+    /// 任务完成，从运行时的任务列表中移除，唤醒JoinHandle或者直接丢弃输出
+    fn complete(&self) {
+        self.header.state.transition_to_complete();
+        self.scheduler.release(&self.header as *const Header as usize);
+        if !self.header.state.is_join_interested() {
+            unsafe { *self.stage.get() = Stage::Consumed };
+            return;
+        }
+        if let Some(waker) = unsafe { (*self.join_waker.get()).take() } {
+            waker.wake();
+        }
+    }
+}
+
+unsafe fn poll<T: Future, S: Schedule>(ptr: NonNull<Header>) {
+    let cell = Cell::<T, S>::from_header(ptr);
+    if cell.header.state.transition_to_running() {
+        if cell.header.state.is_cancelled() {
+            cell.cancel_future();
+            cell.complete();
+        } else {
+            let waker = waker_ref(ptr);
+            let mut cx = Context::from_waker(&waker);
+            match cell.poll_future(&mut cx) {
+                Poll::Ready(()) => cell.complete(),
+                Poll::Pending => {
+                    if cell.header.state.transition_to_idle() {
+                        // poll期间被唤醒或取消过，放回队列尾部
+                        cell.header.ref_inc();
+                        cell.scheduler.yield_now(Task::from_raw(RawTask { ptr }));
+                    }
+                }
+            }
+        }
+    }
+    RawTask { ptr }.drop_reference();
+}
+
+unsafe fn schedule<T: Future, S: Schedule>(ptr: NonNull<Header>) {
+    let cell = Cell::<T, S>::from_header(ptr);
+    if cell.header.state.transition_to_notified() {
+        cell.header.ref_inc();
+        cell.scheduler.schedule(Task::from_raw(RawTask { ptr }));
+    }
+}
+
+unsafe fn abort<T: Future, S: Schedule>(ptr: NonNull<Header>) {
+    let cell = Cell::<T, S>::from_header(ptr);
+    if cell.header.state.transition_to_cancelled() {
+        // 放入运行队列，在下一次poll时丢弃future
+        cell.header.ref_inc();
+        cell.scheduler.schedule(Task::from_raw(RawTask { ptr }));
+    }
+}
+
+unsafe fn shutdown<T: Future, S: Schedule>(ptr: NonNull<Header>) {
+    let cell = Cell::<T, S>::from_header(ptr);
+    // 运行时drop时任务都没有在运行
+    if cell.header.state.transition_to_shutdown() {
+        cell.cancel_future();
+        cell.complete();
+    }
+}
+
+unsafe fn dealloc<T: Future, S: Schedule>(ptr: NonNull<Header>) {
+    drop(Box::from_raw(ptr.cast::<Cell<T, S>>().as_ptr()));
+}
+
+unsafe fn try_read_output<T: Future, S: Schedule>(ptr: NonNull<Header>, dst: *mut (), waker: &Waker) {
+    let cell = Cell::<T, S>::from_header(ptr);
+    let dst = &mut *(dst as *mut Poll<Result<T::Output, JoinError>>);
+    if cell.header.state.is_complete() {
+        match std::mem::replace(&mut *cell.stage.get(), Stage::Consumed) {
+            Stage::Finished(output) => *dst = Poll::Ready(output),
+            _ => panic!("JoinHandle polled after completion"),
+        }
+        return;
+    }
+
+    let join_waker = &mut *cell.join_waker.get();
+    match join_waker {
+        Some(old) if old.will_wake(waker) => {}
+        _ => *join_waker = Some(waker.clone()),
+    }
+}
+
+unsafe fn drop_join_handle<T: Future, S: Schedule>(ptr: NonNull<Header>) {
+    let cell = Cell::<T, S>::from_header(ptr);
+    cell.header.state.unset_join_interest();
+    if cell.header.state.is_complete() {
+        // 输出没有被读取，在这里释放
+        *cell.stage.get() = Stage::Consumed;
+    }
+    *cell.join_waker.get() = None;
+    RawTask { ptr }.drop_reference();
+}
+
+/// 类型擦除的任务指针，不自动管理引用计数
+#[derive(Clone, Copy)]
+pub(crate) struct RawTask {
+    ptr: NonNull<Header>,
+}
+
+impl RawTask {
+    /// 分配任务，初始持有两份引用：Task和JoinHandle
+    pub(crate) fn new<T, S>(future: T, scheduler: S) -> RawTask
+    where
+        T: Future,
+        S: Schedule,
+    {
+        let cell = Box::new(Cell {
+            header: Header {
+                state: State::new(),
+                refs: AtomicUsize::new(2),
+                vtable: vtable::<T, S>(),
+                owner_id: current_thread_id(),
+            },
+            scheduler,
+            stage: UnsafeCell::new(Stage::Running(future)),
+            join_waker: UnsafeCell::new(None),
+        });
+        let ptr = unsafe { NonNull::new_unchecked(Box::into_raw(cell)) };
+        RawTask { ptr: ptr.cast() }
+    }
+
+    pub(crate) unsafe fn from_raw(ptr: NonNull<Header>) -> RawTask {
+        RawTask { ptr }
+    }
+
+    pub(crate) fn header(&self) -> &Header {
+        unsafe { self.ptr.as_ref() }
+    }
+
+    pub(crate) fn poll(self) {
+        unsafe { (self.header().vtable.poll)(self.ptr) }
+    }
+
+    pub(crate) fn schedule(self) {
+        unsafe { (self.header().vtable.schedule)(self.ptr) }
+    }
+
+    pub(crate) fn abort(self) {
+        unsafe { (self.header().vtable.abort)(self.ptr) }
+    }
+
+    pub(crate) fn shutdown(self) {
+        unsafe { (self.header().vtable.shutdown)(self.ptr) }
+    }
+
+    /// 任务的唯一标识，任务释放前不会重复
+    pub(crate) fn id(self) -> usize {
+        self.ptr.as_ptr() as usize
+    }
+
+    pub(crate) unsafe fn try_read_output(self, dst: *mut (), waker: &Waker) {
+        (self.header().vtable.try_read_output)(self.ptr, dst, waker)
+    }
+
+    pub(crate) fn drop_join_handle(self) {
+        unsafe { (self.header().vtable.drop_join_handle)(self.ptr) }
+    }
+
+    pub(crate) fn drop_reference(self) {
+        if self.header().ref_dec() {
+            unsafe { (self.header().vtable.dealloc)(self.ptr) }
+        }
+    }
+}
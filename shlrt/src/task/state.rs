@@ -0,0 +1,107 @@
+use std::cell::Cell;
+
+/// 任务已经在运行队列中
+const SCHEDULED: usize = 1;
+/// 任务正在被poll
+const RUNNING: usize = 1 << 1;
+/// 任务已经完成
+const COMPLETE: usize = 1 << 2;
+/// 任务在poll期间被唤醒，poll返回后需要重新入队
+const NOTIFIED: usize = 1 << 3;
+/// JoinHandle还存活，关心任务的输出
+const JOIN_INTEREST: usize = 1 << 4;
+/// 任务被取消，下次poll时直接丢弃future
+const CANCELLED: usize = 1 << 5;
+
+/// 任务状态，只在任务所属的线程上读写
+pub(crate) struct State(Cell<usize>);
+
+impl State {
+    /// 新建的任务会被直接放入运行队列
+    pub(crate) fn new() -> State {
+        State(Cell::new(SCHEDULED | JOIN_INTEREST))
+    }
+
+    /// 开始poll任务，任务已经完成时返回false
+    pub(crate) fn transition_to_running(&self) -> bool {
+        let state = self.0.get();
+        debug_assert!(state & SCHEDULED != 0);
+        if state & COMPLETE != 0 {
+            self.0.set(state & !SCHEDULED);
+            return false;
+        }
+        self.0.set((state & !SCHEDULED) | RUNNING);
+        true
+    }
+
+    /// poll返回Pending，如果期间被唤醒过则转为SCHEDULED并返回true
+    pub(crate) fn transition_to_idle(&self) -> bool {
+        let state = self.0.get();
+        debug_assert!(state & RUNNING != 0);
+        let next = state & !RUNNING;
+        if next & NOTIFIED != 0 {
+            self.0.set((next & !NOTIFIED) | SCHEDULED);
+            return true;
+        }
+        self.0.set(next);
+        false
+    }
+
+    /// 任务被唤醒，返回true时需要调用者把任务放入运行队列
+    pub(crate) fn transition_to_notified(&self) -> bool {
+        let state = self.0.get();
+        if state & (COMPLETE | SCHEDULED) != 0 {
+            return false;
+        }
+        if state & RUNNING != 0 {
+            self.0.set(state | NOTIFIED);
+            return false;
+        }
+        self.0.set(state | SCHEDULED);
+        true
+    }
+
+    /// 取消任务，返回true时需要调用者把任务放入运行队列
+    pub(crate) fn transition_to_cancelled(&self) -> bool {
+        let state = self.0.get();
+        if state & (COMPLETE | CANCELLED) != 0 {
+            return false;
+        }
+        self.0.set(state | CANCELLED);
+        self.transition_to_notified()
+    }
+
+    /// 运行时关闭，返回false表示任务已经完成
+    pub(crate) fn transition_to_shutdown(&self) -> bool {
+        let state = self.0.get();
+        debug_assert!(state & RUNNING == 0);
+        if state & COMPLETE != 0 {
+            return false;
+        }
+        self.0.set(state | CANCELLED);
+        true
+    }
+
+    /// 任务完成
+    pub(crate) fn transition_to_complete(&self) {
+        let state = self.0.get();
+        self.0.set((state & !(RUNNING | NOTIFIED)) | COMPLETE);
+    }
+
+    /// JoinHandle被drop
+    pub(crate) fn unset_join_interest(&self) {
+        self.0.set(self.0.get() & !JOIN_INTEREST);
+    }
+
+    pub(crate) fn is_complete(&self) -> bool {
+        self.0.get() & COMPLETE != 0
+    }
+
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.0.get() & CANCELLED != 0
+    }
+
+    pub(crate) fn is_join_interested(&self) -> bool {
+        self.0.get() & JOIN_INTEREST != 0
+    }
+}
@@ -0,0 +1,52 @@
+use std::mem::ManuallyDrop;
+use std::ptr::NonNull;
+use std::task::{RawWaker, RawWakerVTable, Waker};
+
+use crate::driver::thread::send_waker;
+use crate::task::raw::{Header, RawTask};
+
+static WAKER_VTABLE: RawWakerVTable =
+    RawWakerVTable::new(clone_waker, wake_by_val, wake_by_ref, drop_waker);
+
+/// 借用任务引用构造waker，不增加引用计数，所以不能被drop
+pub(crate) fn waker_ref(ptr: NonNull<Header>) -> ManuallyDrop<Waker> {
+    ManuallyDrop::new(unsafe { Waker::from_raw(raw_waker(ptr.as_ptr() as *const ())) })
+}
+
+fn raw_waker(ptr: *const ()) -> RawWaker {
+    RawWaker::new(ptr, &WAKER_VTABLE)
+}
+
+unsafe fn raw_task(ptr: *const ()) -> RawTask {
+    RawTask::from_raw(NonNull::new_unchecked(ptr as *mut Header))
+}
+
+unsafe fn clone_waker(ptr: *const ()) -> RawWaker {
+    raw_task(ptr).header().ref_inc();
+    raw_waker(ptr)
+}
+
+unsafe fn wake_by_val(ptr: *const ()) {
+    let raw = raw_task(ptr);
+    if raw.header().is_current_thread() {
+        raw.schedule();
+        raw.drop_reference();
+    } else {
+        // 引用的所有权随waker一起转交给所属线程。所属的运行时已经退出时waker在这里被drop，
+        // 运行时drop时已经丢弃了future，这里最多只会释放任务的内存
+        send_waker(raw.header().owner_id(), Waker::from_raw(raw_waker(ptr)));
+    }
+}
+
+unsafe fn wake_by_ref(ptr: *const ()) {
+    let raw = raw_task(ptr);
+    if raw.header().is_current_thread() {
+        raw.schedule();
+    } else {
+        send_waker(raw.header().owner_id(), Waker::from_raw(clone_waker(ptr)));
+    }
+}
+
+unsafe fn drop_waker(ptr: *const ()) {
+    raw_task(ptr).drop_reference();
+}
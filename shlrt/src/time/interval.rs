@@ -0,0 +1,101 @@
+use std::future::{poll_fn, Future};
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use std::time::{Duration, Instant};
+
+use crate::time::sleep::{sleep_until, Sleep};
+
+/// 创建周期为period的Interval，第一次tick立即完成
+///
+/// # Panics
+///
+/// period为0、不在运行时中或者没有开启定时器时会panic
+pub fn interval(period: Duration) -> Interval {
+    interval_at(Instant::now(), period)
+}
+
+/// 创建从start开始、周期为period的Interval
+///
+/// # Panics
+///
+/// period为0、不在运行时中或者没有开启定时器时会panic
+pub fn interval_at(start: Instant, period: Duration) -> Interval {
+    assert!(period > Duration::ZERO, "`period` must be non-zero.");
+    Interval {
+        sleep: sleep_until(start),
+        period,
+        missed_tick_behavior: MissedTickBehavior::default(),
+    }
+}
+
+/// 错过tick之后如何安排后面的tick
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MissedTickBehavior {
+    /// 立即补上所有错过的tick，之后按原来的节奏继续
+    #[default]
+    Burst,
+    /// 从当前时间开始重新计算周期
+    Delay,
+    /// 跳过错过的tick，在原来节奏上的下一个时间点继续
+    Skip,
+}
+
+impl MissedTickBehavior {
+    fn next_timeout(&self, timeout: Instant, now: Instant, period: Duration) -> Instant {
+        match self {
+            MissedTickBehavior::Burst => timeout + period,
+            MissedTickBehavior::Delay => now + period,
+            MissedTickBehavior::Skip => {
+                let behind = (now - timeout).as_nanos() % period.as_nanos();
+                now + period - Duration::from_nanos(behind as u64)
+            }
+        }
+    }
+}
+
+/// 周期性的定时器
+#[derive(Debug)]
+pub struct Interval {
+    sleep: Sleep,
+    period: Duration,
+    missed_tick_behavior: MissedTickBehavior,
+}
+
+impl Interval {
+    /// 等待下一个tick，返回这个tick原定的时间
+    pub async fn tick(&mut self) -> Instant {
+        poll_fn(|cx| self.poll_tick(cx)).await
+    }
+
+    pub fn poll_tick(&mut self, cx: &mut Context<'_>) -> Poll<Instant> {
+        ready!(Pin::new(&mut self.sleep).poll(cx));
+
+        let timeout = self.sleep.deadline();
+        let now = Instant::now();
+        // 允许5毫秒的误差，不算错过
+        let next = if now > timeout + Duration::from_millis(5) {
+            self.missed_tick_behavior.next_timeout(timeout, now, self.period)
+        } else {
+            timeout + self.period
+        };
+        self.sleep.reset(next);
+        Poll::Ready(timeout)
+    }
+
+    /// 下一个tick从现在开始一个周期之后
+    pub fn reset(&mut self) {
+        self.sleep.reset(Instant::now() + self.period);
+    }
+
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    pub fn missed_tick_behavior(&self) -> MissedTickBehavior {
+        self.missed_tick_behavior
+    }
+
+    pub fn set_missed_tick_behavior(&mut self, behavior: MissedTickBehavior) {
+        self.missed_tick_behavior = behavior;
+    }
+}
@@ -0,0 +1,102 @@
+//! 定时器，需要通过 [RuntimeBuilder::enable_timer](crate::RuntimeBuilder::enable_timer) 开启
+
+mod interval;
+mod sleep;
+mod timeout;
+pub(crate) mod wheel;
+
+pub use interval::{interval, interval_at, Interval, MissedTickBehavior};
+pub use sleep::{sleep, sleep_until, Sleep};
+pub use timeout::{timeout, timeout_at, Elapsed, Timeout};
+
+use std::rc::Rc;
+
+use crate::runtime::CURRENT;
+use wheel::TimeDriver;
+
+/// 获取当前运行时的定时器
+///
+/// # Panics
+///
+/// 不在运行时中或者没有开启定时器时会panic
+fn current() -> Rc<TimeDriver> {
+    CURRENT.with(|cx| {
+        cx.timer
+            .clone()
+            .expect("timer is not enabled, call RuntimeBuilder::enable_timer")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::pending;
+    use std::time::{Duration, Instant};
+
+    use super::{interval_at, sleep, timeout, MissedTickBehavior};
+    use crate::{FusionDriver, Runtime, RuntimeBuilder};
+
+    fn runtime() -> Runtime<FusionDriver> {
+        RuntimeBuilder::new().enable_timer().build().unwrap()
+    }
+
+    #[test]
+    fn sleep_parks_until_deadline() {
+        let mut rt = runtime();
+        let start = Instant::now();
+        rt.block_on(async { sleep(Duration::from_millis(30)).await });
+        assert!(start.elapsed() >= Duration::from_millis(30));
+        // 没有其他事件，只能由park的超时唤醒。legacy driver不统计
+        if !rt.features().is_legacy() {
+            let metrics = rt.metrics();
+            assert!(metrics.parks() >= 1);
+            assert!(metrics.timeout_wakeups() >= 1);
+        }
+    }
+
+    #[test]
+    fn timeout_elapses() {
+        let mut rt = runtime();
+        rt.block_on(async {
+            assert!(timeout(Duration::from_millis(10), pending::<()>()).await.is_err());
+            assert_eq!(timeout(Duration::from_millis(10), async { 1 }).await.unwrap(), 1);
+        });
+    }
+
+    /// 第一个tick之后阻塞线程错过两个tick，返回之后两个tick原定的时间
+    fn missed_ticks(behavior: MissedTickBehavior) -> (Instant, [Instant; 2]) {
+        let mut rt = runtime();
+        rt.block_on(async {
+            let period = Duration::from_millis(20);
+            let start = Instant::now();
+            let mut interval = interval_at(start, period);
+            interval.set_missed_tick_behavior(behavior);
+            assert_eq!(interval.tick().await, start);
+            std::thread::sleep(Duration::from_millis(50));
+            let late = interval.tick().await;
+            assert_eq!(late, start + period);
+            (start, [late, interval.tick().await])
+        })
+    }
+
+    #[test]
+    fn missed_tick_burst() {
+        let (start, ticks) = missed_ticks(MissedTickBehavior::Burst);
+        // 错过的tick立即补上
+        assert_eq!(ticks[1], start + Duration::from_millis(40));
+    }
+
+    #[test]
+    fn missed_tick_delay() {
+        let (start, ticks) = missed_ticks(MissedTickBehavior::Delay);
+        // 从补上错过的tick时开始重新计算周期
+        assert!(ticks[1] >= start + Duration::from_millis(70));
+    }
+
+    #[test]
+    fn missed_tick_skip() {
+        let (start, ticks) = missed_ticks(MissedTickBehavior::Skip);
+        // 跳过40毫秒的tick，按原来的节奏在60毫秒时继续
+        assert_eq!(ticks[1], start + Duration::from_millis(60));
+        assert!(Instant::now() >= ticks[1]);
+    }
+}
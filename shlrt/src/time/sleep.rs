@@ -0,0 +1,97 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use crate::time::wheel::{TimeDriver, TimerEntry};
+
+/// 等待duration时间
+///
+/// # Panics
+///
+/// 不在运行时中或者没有开启定时器时会panic
+pub fn sleep(duration: Duration) -> Sleep {
+    match Instant::now().checked_add(duration) {
+        Some(deadline) => sleep_until(deadline),
+        None => sleep_until(far_future()),
+    }
+}
+
+/// 等待到deadline
+///
+/// # Panics
+///
+/// 不在运行时中或者没有开启定时器时会panic
+pub fn sleep_until(deadline: Instant) -> Sleep {
+    let driver = super::current();
+    let entry = Rc::new(TimerEntry::new());
+    driver.register(&entry, deadline);
+    Sleep {
+        driver,
+        entry,
+        deadline,
+    }
+}
+
+/// 大约30年之后
+pub(crate) fn far_future() -> Instant {
+    Instant::now() + Duration::from_secs(86400 * 365 * 30)
+}
+
+/// [sleep] 和 [sleep_until] 返回的future
+pub struct Sleep {
+    driver: Rc<TimeDriver>,
+    entry: Rc<TimerEntry>,
+    deadline: Instant,
+}
+
+impl Sleep {
+    /// 到期时间
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    /// 是否已经到期
+    pub fn is_elapsed(&self) -> bool {
+        Instant::now() >= self.deadline
+    }
+
+    /// 修改到期时间，不需要重新创建Sleep
+    pub fn reset(&mut self, deadline: Instant) {
+        self.deadline = deadline;
+        self.driver.register(&self.entry, deadline);
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = self.get_mut();
+        if Instant::now() >= this.deadline {
+            this.driver.deregister(&this.entry);
+            return Poll::Ready(());
+        }
+        if this.entry.is_fired() {
+            // 超出时间轮范围的定时器会提前到期，重新注册
+            this.driver.register(&this.entry, this.deadline);
+        }
+        this.entry.set_waker(cx.waker());
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        self.driver.deregister(&self.entry);
+    }
+}
+
+impl std::fmt::Debug for Sleep {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Sleep")
+            .field("deadline", &self.deadline)
+            .finish()
+    }
+}
@@ -0,0 +1,379 @@
+//! 分层时间轮，每层64个槽位，最小精度1毫秒，共6层
+//! 参考tokio的实现，节点通过 [LinkedList] 串在槽位上
+
+use std::cell::{Cell, RefCell};
+use std::ptr::NonNull;
+use std::rc::Rc;
+use std::task::Waker;
+use std::time::{Duration, Instant};
+
+use crate::utils::linked_list::{Link, LinkedList, Pointers};
+
+/// 每层的槽位数量
+const LEVEL_MULT: usize = 64;
+/// 层数
+const NUM_LEVELS: usize = 6;
+/// 时间轮能表示的最大tick数
+pub(crate) const MAX_DURATION: u64 = (1 << (6 * NUM_LEVELS)) - 1;
+
+/// 时间轮上的一个定时器，由Sleep持有
+pub(crate) struct TimerEntry {
+    pointers: Pointers<TimerEntry>,
+    /// 到期的tick
+    when: Cell<u64>,
+    /// 所在的层和槽位，None表示不在时间轮上
+    position: Cell<Option<(usize, usize)>>,
+    /// 是否已经到期
+    fired: Cell<bool>,
+    waker: RefCell<Option<Waker>>,
+}
+
+impl TimerEntry {
+    pub(crate) fn new() -> TimerEntry {
+        TimerEntry {
+            pointers: Pointers::new(),
+            when: Cell::new(0),
+            position: Cell::new(None),
+            fired: Cell::new(false),
+            waker: RefCell::new(None),
+        }
+    }
+
+    pub(crate) fn is_fired(&self) -> bool {
+        self.fired.get()
+    }
+
+    pub(crate) fn set_waker(&self, waker: &Waker) {
+        let mut slot = self.waker.borrow_mut();
+        match &*slot {
+            Some(old) if old.will_wake(waker) => {}
+            _ => *slot = Some(waker.clone()),
+        }
+    }
+
+    /// 标记到期，返回需要唤醒的waker
+    fn fire(&self) -> Option<Waker> {
+        self.position.set(None);
+        self.fired.set(true);
+        self.waker.borrow_mut().take()
+    }
+}
+
+unsafe impl Link for TimerEntry {
+    type Handle = Rc<TimerEntry>;
+    type Target = TimerEntry;
+
+    fn as_raw(handle: &Rc<TimerEntry>) -> NonNull<TimerEntry> {
+        NonNull::from(&**handle)
+    }
+
+    unsafe fn from_raw(ptr: NonNull<TimerEntry>) -> Rc<TimerEntry> {
+        Rc::from_raw(ptr.as_ptr())
+    }
+
+    unsafe fn pointers(target: NonNull<TimerEntry>) -> NonNull<Pointers<TimerEntry>> {
+        NonNull::new_unchecked(std::ptr::addr_of_mut!((*target.as_ptr()).pointers))
+    }
+}
+
+/// 一层时间轮
+struct Level {
+    level: usize,
+    /// 每一位表示对应的槽位是否有定时器
+    occupied: u64,
+    slots: [LinkedList<TimerEntry, TimerEntry>; LEVEL_MULT],
+}
+
+/// 下一个需要处理的槽位
+struct Expiration {
+    level: usize,
+    slot: usize,
+    deadline: u64,
+}
+
+impl Level {
+    fn new(level: usize) -> Level {
+        Level {
+            level,
+            occupied: 0,
+            slots: std::array::from_fn(|_| LinkedList::new()),
+        }
+    }
+
+    /// 找到now之后第一个有定时器的槽位
+    fn next_expiration(&self, now: u64) -> Option<Expiration> {
+        if self.occupied == 0 {
+            return None;
+        }
+        let slot_range = slot_range(self.level);
+        let level_range = slot_range * LEVEL_MULT as u64;
+
+        let now_slot = (now / slot_range) as u32;
+        let zeros = self.occupied.rotate_right(now_slot).trailing_zeros() as usize;
+        let slot = (zeros + now_slot as usize) % LEVEL_MULT;
+
+        let level_start = now & !(level_range - 1);
+        let mut deadline = level_start + slot as u64 * slot_range;
+        if deadline <= now {
+            // 只有最高层会出现回绕
+            deadline += level_range;
+        }
+        Some(Expiration {
+            level: self.level,
+            slot,
+            deadline,
+        })
+    }
+
+    fn push(&mut self, slot: usize, entry: Rc<TimerEntry>) {
+        self.slots[slot].push_front(entry);
+        self.occupied |= 1 << slot;
+    }
+
+    fn remove(&mut self, slot: usize, entry: &TimerEntry) -> Option<Rc<TimerEntry>> {
+        let handle = unsafe { self.slots[slot].remove(NonNull::from(entry)) };
+        if self.slots[slot].is_empty() {
+            self.occupied &= !(1 << slot);
+        }
+        handle
+    }
+
+    fn take_slot(&mut self, slot: usize) -> LinkedList<TimerEntry, TimerEntry> {
+        self.occupied &= !(1 << slot);
+        std::mem::take(&mut self.slots[slot])
+    }
+}
+
+fn slot_range(level: usize) -> u64 {
+    (LEVEL_MULT as u64).pow(level as u32)
+}
+
+/// when应该放在哪一层
+fn level_for(elapsed: u64, when: u64) -> usize {
+    const SLOT_MASK: u64 = (1 << 6) - 1;
+    let masked = ((elapsed ^ when) | SLOT_MASK).min(MAX_DURATION - 1);
+    let significant = 63 - masked.leading_zeros() as usize;
+    significant / 6
+}
+
+fn slot_for(when: u64, level: usize) -> usize {
+    ((when >> (level * 6)) % LEVEL_MULT as u64) as usize
+}
+
+/// 分层时间轮
+pub(crate) struct Wheel {
+    /// 已经处理到的tick
+    elapsed: u64,
+    levels: Vec<Level>,
+}
+
+impl Wheel {
+    pub(crate) fn new() -> Wheel {
+        Wheel {
+            elapsed: 0,
+            levels: (0..NUM_LEVELS).map(Level::new).collect(),
+        }
+    }
+
+    pub(crate) fn elapsed(&self) -> u64 {
+        self.elapsed
+    }
+
+    /// 插入定时器，已经到期时返回Err
+    pub(crate) fn insert(&mut self, entry: Rc<TimerEntry>, when: u64) -> Result<(), Rc<TimerEntry>> {
+        entry.when.set(when);
+        entry.fired.set(false);
+        if when <= self.elapsed {
+            return Err(entry);
+        }
+        let level = level_for(self.elapsed, when);
+        let slot = slot_for(when, level);
+        entry.position.set(Some((level, slot)));
+        self.levels[level].push(slot, entry);
+        Ok(())
+    }
+
+    /// 从时间轮上移除定时器
+    pub(crate) fn remove(&mut self, entry: &TimerEntry) {
+        if let Some((level, slot)) = entry.position.take() {
+            drop(self.levels[level].remove(slot, entry));
+        }
+    }
+
+    /// 下一个需要处理的tick
+    pub(crate) fn next_expiration(&self) -> Option<u64> {
+        self.next_slot().map(|expiration| expiration.deadline)
+    }
+
+    fn next_slot(&self) -> Option<Expiration> {
+        // 低层的槽位一定比高层的先到期
+        self.levels
+            .iter()
+            .find_map(|level| level.next_expiration(self.elapsed))
+    }
+
+    /// 推进到now，把到期定时器的waker放入wakers
+    pub(crate) fn poll(&mut self, now: u64, wakers: &mut Vec<Waker>) {
+        while let Some(expiration) = self.next_slot() {
+            if expiration.deadline > now {
+                break;
+            }
+            let mut entries = self.levels[expiration.level].take_slot(expiration.slot);
+            self.elapsed = expiration.deadline;
+            while let Some(entry) = entries.pop_back() {
+                entry.position.set(None);
+                let when = entry.when.get();
+                if let Err(entry) = self.insert(entry, when) {
+                    wakers.extend(entry.fire());
+                }
+            }
+        }
+        self.elapsed = self.elapsed.max(now);
+    }
+}
+
+impl Drop for Wheel {
+    fn drop(&mut self) {
+        // 链表不会释放节点，需要手动取出来
+        for level in self.levels.iter_mut() {
+            for slot in 0..LEVEL_MULT {
+                let mut entries = level.take_slot(slot);
+                while let Some(entry) = entries.pop_back() {
+                    entry.position.set(None);
+                }
+            }
+        }
+    }
+}
+
+/// 每个运行时一个的定时器
+pub(crate) struct TimeDriver {
+    /// tick 0对应的时间
+    start: Instant,
+    wheel: RefCell<Wheel>,
+}
+
+impl TimeDriver {
+    pub(crate) fn new() -> TimeDriver {
+        TimeDriver {
+            start: Instant::now(),
+            wheel: RefCell::new(Wheel::new()),
+        }
+    }
+
+    /// 时间向上取整到tick，保证定时器不会提前到期
+    fn deadline_to_tick(&self, deadline: Instant) -> u64 {
+        let duration = deadline.saturating_duration_since(self.start) + Duration::from_nanos(999_999);
+        (duration.as_millis() as u64).min(self.wheel.borrow().elapsed() + MAX_DURATION - 1)
+    }
+
+    fn now_tick(&self) -> u64 {
+        Instant::now().saturating_duration_since(self.start).as_millis() as u64
+    }
+
+    /// 注册定时器，已经到期时直接标记为fired
+    pub(crate) fn register(&self, entry: &Rc<TimerEntry>, deadline: Instant) {
+        let when = self.deadline_to_tick(deadline);
+        let mut wheel = self.wheel.borrow_mut();
+        wheel.remove(entry);
+        if let Err(entry) = wheel.insert(entry.clone(), when) {
+            drop(entry.fire());
+        }
+    }
+
+    pub(crate) fn deregister(&self, entry: &TimerEntry) {
+        self.wheel.borrow_mut().remove(entry);
+    }
+
+    /// 距离下一个定时器到期的时间
+    pub(crate) fn next_timeout(&self) -> Option<Duration> {
+        let when = self.wheel.borrow().next_expiration()?;
+        let deadline = self.start + Duration::from_millis(when);
+        Some(deadline.saturating_duration_since(Instant::now()))
+    }
+
+    /// 唤醒所有到期的定时器
+    pub(crate) fn process(&self) {
+        let mut wakers = Vec::new();
+        self.wheel.borrow_mut().poll(self.now_tick(), &mut wakers);
+        // 唤醒时可能会drop定时器，不能持有借用
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::Wake;
+
+    struct CountWaker(AtomicUsize);
+
+    impl Wake for CountWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn entry(waker: &Waker) -> Rc<TimerEntry> {
+        let entry = Rc::new(TimerEntry::new());
+        entry.set_waker(waker);
+        entry
+    }
+
+    #[test]
+    fn fire_in_order() {
+        let count = Arc::new(CountWaker(AtomicUsize::new(0)));
+        let waker = Waker::from(count.clone());
+        let mut wheel = Wheel::new();
+        let whens = [1, 63, 64, 65, 4095, 4096, 300_000, 1 << 30];
+        let entries = whens.iter().map(|_| entry(&waker)).collect::<Vec<_>>();
+        for (entry, when) in entries.iter().zip(whens) {
+            assert!(wheel.insert(entry.clone(), when).is_ok());
+        }
+
+        for (i, when) in whens.iter().enumerate() {
+            assert_eq!(wheel.next_expiration().map(|next| next <= *when), Some(true));
+            let mut wakers = Vec::new();
+            wheel.poll(when - 1, &mut wakers);
+            assert!(!entries[i].is_fired());
+            wheel.poll(*when, &mut wakers);
+            assert!(entries[i].is_fired());
+            assert!(entries[i + 1..].iter().all(|entry| !entry.is_fired()));
+            wakers.into_iter().for_each(Waker::wake);
+        }
+        assert_eq!(count.0.load(Ordering::SeqCst), whens.len());
+        assert_eq!(wheel.next_expiration(), None);
+    }
+
+    #[test]
+    fn remove_entry() {
+        let waker = Waker::from(Arc::new(CountWaker(AtomicUsize::new(0))));
+        let mut wheel = Wheel::new();
+        let removed = entry(&waker);
+        let kept = entry(&waker);
+        assert!(wheel.insert(removed.clone(), 100).is_ok());
+        assert!(wheel.insert(kept.clone(), 100).is_ok());
+        wheel.remove(&removed);
+        assert_eq!(Rc::strong_count(&removed), 1);
+
+        let mut wakers = Vec::new();
+        wheel.poll(100, &mut wakers);
+        assert_eq!(wakers.len(), 1);
+        assert!(!removed.is_fired());
+        assert!(kept.is_fired());
+        assert!(wheel.insert(removed, 50).is_err());
+    }
+
+    #[test]
+    fn drop_releases_entries() {
+        let entry = Rc::new(TimerEntry::new());
+        let mut wheel = Wheel::new();
+        assert!(wheel.insert(entry.clone(), 1 << 20).is_ok());
+        drop(wheel);
+        assert_eq!(Rc::strong_count(&entry), 1);
+    }
+}
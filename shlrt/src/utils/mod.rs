@@ -1 +1,5 @@
+pub(crate) mod affinity;
+pub(crate) mod linked_list;
+pub(crate) mod slab;
+pub(crate) mod thread_id;
 mod uring;
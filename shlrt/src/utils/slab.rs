@@ -1,9 +1,6 @@
 //! All copy from monoio
 
-use std::{
-    mem::MaybeUninit,
-    ops::{Deref, DerefMut},
-};
+use std::mem::MaybeUninit;
 
 /// Pre-allocated storage for a uniform data type
 #[derive(Default)]
@@ -34,7 +31,6 @@ impl<T> Slab<T> {
     }
 
     /// Get slab len.
-    #[allow(unused)]
     pub(crate) fn len(&self) -> usize {
         self.pages.iter().fold(0, |acc, page| match page {
             Some(page) => acc + page.used,
@@ -42,22 +38,33 @@ impl<T> Slab<T> {
         })
     }
 
-    pub(crate) fn get(&mut self, key: usize) -> Option<Ref<'_, T>> {
-        let page_id = get_page_id(key);
-        // here we make 2 mut ref so we must make it safe.
-        let slab = unsafe { &mut *(self as *mut Slab<T>) };
-        let page = match unsafe { self.pages.get_unchecked_mut(page_id) } {
-            Some(page) => page,
-            None => return None,
-        };
-        let index = key - page.prev_len;
-        match page.get_entry_mut(index) {
-            None => None,
-            Some(entry) => match entry {
-                Entry::Vacant(_) => None,
-                Entry::Occupied(_) => Some(Ref { slab, page, index }),
-            },
-        }
+    /// Upper bound of the keys handed out so far.
+    pub(crate) fn slots(&self) -> usize {
+        self.pages
+            .iter()
+            .flatten()
+            .map(|page| page.prev_len + page.initialized)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Get a shared reference to the value of key.
+    pub(crate) fn get_ref(&self, key: usize) -> Option<&T> {
+        let page = self.pages.get(get_page_id(key))?.as_ref()?;
+        page.get(key.checked_sub(page.prev_len)?)
+    }
+
+    /// Get a mutable reference to the value of key.
+    pub(crate) fn get_mut(&mut self, key: usize) -> Option<&mut T> {
+        let page = self.pages.get_mut(get_page_id(key))?.as_mut()?;
+        page.get_mut(key.checked_sub(page.prev_len)?)
+    }
+
+    /// Iterate over all occupied keys and values.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (usize, &T)> + '_ {
+        self.pages.iter().flatten().flat_map(|page| {
+            (0..page.initialized).filter_map(move |slot| page.get(slot).map(|val| (slot + page.prev_len, val)))
+        })
     }
 
     /// Insert an element into slab. The key is returned.
@@ -89,7 +96,6 @@ impl<T> Slab<T> {
     }
 
     /// Remove an element from slab.
-    #[allow(unused)]
     pub(crate) fn remove(&mut self, key: usize) -> Option<T> {
         let page_id = get_page_id(key);
         let page = match unsafe { self.pages.get_unchecked_mut(page_id) } {
@@ -104,21 +110,17 @@ impl<T> Slab<T> {
     pub(crate) fn mark_remove(&mut self) {
         // compact
         self.generation = self.generation.wrapping_add(1);
-        if self.generation % COMPACT_INTERVAL == 0 {
+        if self.generation.is_multiple_of(COMPACT_INTERVAL) {
             // reset write page index
             self.w_page_id = 0;
-            // find the last allocated page and try to drop
-            if let Some((id, last_page)) = self
-                .pages
-                .iter_mut()
-                .enumerate()
-                .rev()
-                .find_map(|(id, p)| p.as_mut().map(|p| (id, p)))
-            {
-                if last_page.is_empty() && id > 0 {
-                    unsafe {
+            // drop all trailing empty pages, so the memory allocated by a burst is released
+            for id in (1..NUM_PAGES).rev() {
+                match unsafe { self.pages.get_unchecked_mut(id) } {
+                    Some(page) if page.is_empty() => unsafe {
                         *self.pages.get_unchecked_mut(id) = None;
-                    }
+                    },
+                    Some(_) => break,
+                    None => {}
                 }
             }
         }
@@ -134,54 +136,6 @@ fn get_page_id(key: usize) -> usize {
     ((POINTER_WIDTH - slot_shifted.leading_zeros()) as usize).min(NUM_PAGES - 1)
 }
 
-/// Ref point to a valid slot.
-pub(crate) struct Ref<'a, T> {
-    slab: &'a mut Slab<T>,
-    page: &'a mut Page<T>,
-    index: usize,
-}
-
-impl<'a, T> Ref<'a, T> {
-    #[allow(unused)]
-    pub(crate) fn remove(self) -> T {
-        // # Safety
-        // We make sure the index is valid.
-        let val = unsafe { self.page.remove(self.index).unwrap_unchecked() };
-        self.slab.mark_remove();
-        val
-    }
-}
-
-impl<'a, T> AsRef<T> for Ref<'a, T> {
-    fn as_ref(&self) -> &T {
-        // # Safety
-        // We make sure the index is valid.
-        unsafe { self.page.get(self.index).unwrap_unchecked() }
-    }
-}
-
-impl<'a, T> AsMut<T> for Ref<'a, T> {
-    fn as_mut(&mut self) -> &mut T {
-        // # Safety
-        // We make sure the index is valid.
-        unsafe { self.page.get_mut(self.index).unwrap_unchecked() }
-    }
-}
-
-impl<'a, T> Deref for Ref<'a, T> {
-    type Target = T;
-
-    fn deref(&self) -> &Self::Target {
-        self.as_ref()
-    }
-}
-
-impl<'a, T> DerefMut for Ref<'a, T> {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        self.as_mut()
-    }
-}
-
 enum Entry<T> {
     Vacant(usize),
     Occupied(T),
@@ -299,13 +253,6 @@ impl<T> Page<T> {
         unsafe { self.slots.get_unchecked_mut(slot).assume_init_mut() }.as_mut()
     }
 
-    fn get_entry_mut(&mut self, slot: usize) -> Option<&mut Entry<T>> {
-        if slot >= self.initialized {
-            return None;
-        }
-        unsafe { Some(self.slots.get_unchecked_mut(slot).assume_init_mut()) }
-    }
-
     fn remove(&mut self, slot: usize) -> Option<T> {
         if slot >= self.initialized {
             return None;
@@ -335,7 +282,7 @@ impl<T> Drop for Page<T> {
             } else {
                 // slow drop
                 to_drop.set_len(self.initialized);
-                std::mem::transmute::<_, Vec<Entry<T>>>(to_drop);
+                std::mem::transmute::<Vec<MaybeUninit<Entry<T>>>, Vec<Entry<T>>>(to_drop);
             }
         }
     }
@@ -349,9 +296,9 @@ mod tests {
     fn insert_get_remove_one() {
         let mut slab = Slab::default();
         let key = slab.insert(10);
-        assert_eq!(slab.get(key).unwrap().as_mut(), &10);
+        assert_eq!(slab.get_mut(key), Some(&mut 10));
         assert_eq!(slab.remove(key), Some(10));
-        assert!(slab.get(key).is_none());
+        assert!(slab.get_ref(key).is_none());
         assert_eq!(slab.len(), 0);
     }
 
@@ -366,7 +313,7 @@ mod tests {
 
                 let key = slab.insert(val);
                 keys.push((key, val));
-                assert_eq!(slab.get(key).unwrap().as_mut(), &val);
+                assert_eq!(slab.get_ref(key), Some(&val));
             }
 
             for (key, val) in keys.drain(..) {
@@ -378,9 +325,9 @@ mod tests {
     #[test]
     fn get_not_exist() {
         let mut slab = Slab::<i32>::new();
-        assert!(slab.get(0).is_none());
-        assert!(slab.get(1).is_none());
-        assert!(slab.get(usize::MAX).is_none());
+        assert!(slab.get_ref(0).is_none());
+        assert!(slab.get_ref(1).is_none());
+        assert!(slab.get_mut(usize::MAX).is_none());
         assert!(slab.remove(0).is_none());
         assert!(slab.remove(1).is_none());
         assert!(slab.remove(usize::MAX).is_none());
@@ -394,8 +341,40 @@ mod tests {
             assert_eq!(slab.remove(*key).unwrap(), val);
         });
         keys.iter().for_each(|key| {
-            assert!(slab.get(*key).is_none());
+            assert!(slab.get_ref(*key).is_none());
         });
         assert_eq!(slab.len(), 0);
     }
+
+    #[test]
+    fn iter_occupied() {
+        let mut slab = Slab::new();
+        let keys = (0..200).map(|i| slab.insert(i)).collect::<Vec<_>>();
+        for key in keys.iter().step_by(2) {
+            slab.remove(*key);
+        }
+        assert!(keys.iter().all(|key| *key < slab.slots()));
+        let left = slab.iter().map(|(key, val)| (key, *val)).collect::<Vec<_>>();
+        assert_eq!(left.len(), 100);
+        for (key, val) in left {
+            assert_eq!(slab.get_ref(key), Some(&val));
+            assert_eq!(val % 2, 1);
+        }
+    }
+
+    #[test]
+    fn compact_after_burst() {
+        let mut slab = Slab::new();
+        let keys = (0..10_000).map(|i| slab.insert(i)).collect::<Vec<_>>();
+        assert!(slab.pages[5].is_some());
+        for key in keys {
+            slab.remove(key);
+        }
+        for _ in 0..COMPACT_INTERVAL {
+            let key = slab.insert(0);
+            slab.remove(key);
+        }
+        assert!(slab.pages[0].is_some());
+        assert!(slab.pages[1..].iter().all(Option::is_none));
+    }
 }
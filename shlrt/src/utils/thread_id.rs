@@ -0,0 +1,12 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static ID_GEN: AtomicUsize = AtomicUsize::new(1);
+
+thread_local! {
+    static THREAD_ID: usize = ID_GEN.fetch_add(1, Ordering::Relaxed);
+}
+
+/// 当前线程的id，比std::thread::current().id()更轻量
+pub(crate) fn current_thread_id() -> usize {
+    THREAD_ID.with(|id| *id)
+}